@@ -1,17 +1,27 @@
 use vangers::{
     config,
-    render::{ScreenTargets, COLOR_FORMAT, DEPTH_FORMAT},
+    render::{request_device, OffscreenTargets, ScreenTargets, COLOR_FORMAT, DEPTH_FORMAT},
 };
 
 use env_logger;
 use futures::executor::{LocalPool, LocalSpawner};
-use log::info;
+use log::{error, info};
 use winit::{
     event,
     event_loop::{ControlFlow, EventLoop},
     window::{Window, WindowBuilder},
 };
 
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    process,
+};
+
+// Saves the frame on the disk, instead of presenting it.
+const SCREENSHOT_KEY: event::VirtualKeyCode = event::VirtualKeyCode::F12;
+
 pub trait Application {
     fn on_key(&mut self, input: event::KeyboardInput) -> bool;
     fn on_mouse_wheel(&mut self, _delta: event::MouseScrollDelta) {}
@@ -22,6 +32,7 @@ pub trait Application {
     fn update(
         &mut self,
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
         delta: f32,
         spawner: &LocalSpawner,
     ) -> Vec<wgpu::CommandBuffer>;
@@ -34,6 +45,7 @@ pub trait Application {
 }
 
 pub struct Harness {
+    title: String,
     task_pool: LocalPool,
     event_loop: EventLoop<()>,
     window: Window,
@@ -46,18 +58,46 @@ pub struct Harness {
     depth_target: wgpu::TextureView,
 }
 
+fn load_settings() -> (config::Settings, wgpu::Extent3d) {
+    env_logger::init();
+    info!("Loading the settings");
+    let settings = config::Settings::load("config/settings.ron");
+    let extent = wgpu::Extent3d {
+        width: settings.window.size[0],
+        height: settings.window.size[1],
+        depth: 1,
+    };
+    (settings, extent)
+}
+
+fn create_depth_target(device: &wgpu::Device, extent: wgpu::Extent3d) -> wgpu::TextureView {
+    device
+        .create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        })
+        .create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn save_png(path: &Path, extent: wgpu::Extent3d, pixels: &[u8]) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), extent.width, extent.height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    info!("Saved the frame to {}", path.display());
+    Ok(())
+}
+
 impl Harness {
     pub fn init(title: &str) -> (Self, config::Settings) {
-        env_logger::init();
         let mut task_pool = LocalPool::new();
-
-        info!("Loading the settings");
-        let settings = config::Settings::load("config/settings.ron");
-        let extent = wgpu::Extent3d {
-            width: settings.window.size[0],
-            height: settings.window.size[1],
-            depth: 1,
-        };
+        let (settings, extent) = load_settings();
 
         info!("Initializing the window");
         let instance = wgpu::Instance::new(settings.backend.to_wgpu());
@@ -70,23 +110,14 @@ impl Harness {
             .unwrap();
         let surface = unsafe { instance.create_surface(&window) };
 
-        info!("Initializing the device");
-        let adapter = task_pool
-            .run_until(instance.request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::Default,
-                compatible_surface: Some(&surface),
-            }))
-            .expect("Unable to initialize GPU via the selected backend.");
-        let (device, queue) = task_pool
-            .run_until(adapter.request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    shader_validation: true,
-                },
-                None,
-            ))
-            .unwrap();
+        let (device, queue) = request_device(
+            &mut task_pool,
+            &instance,
+            settings.backend.to_wgpu(),
+            Some(&surface),
+            false,
+        )
+        .expect("Unable to initialize GPU via the selected backend.");
 
         let sc_desc = wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
@@ -96,19 +127,10 @@ impl Harness {
             present_mode: wgpu::PresentMode::Mailbox,
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
-        let depth_target = device
-            .create_texture(&wgpu::TextureDescriptor {
-                label: Some("Depth"),
-                size: extent,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: DEPTH_FORMAT,
-                usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-            })
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_target = create_depth_target(&device, extent);
 
         let harness = Harness {
+            title: title.to_string(),
             task_pool,
             event_loop,
             window: window,
@@ -129,7 +151,9 @@ impl Harness {
 
         let mut last_time = time::Instant::now();
         let mut needs_reload = false;
+        let mut needs_screenshot = false;
         let Harness {
+            title,
             mut task_pool,
             event_loop,
             window,
@@ -166,17 +190,7 @@ impl Harness {
                         present_mode: wgpu::PresentMode::Mailbox,
                     };
                     swap_chain = device.create_swap_chain(&surface, &sc_desc);
-                    depth_target = device
-                        .create_texture(&wgpu::TextureDescriptor {
-                            label: Some("Depth"),
-                            size: extent,
-                            mip_level_count: 1,
-                            sample_count: 1,
-                            dimension: wgpu::TextureDimension::D2,
-                            format: DEPTH_FORMAT,
-                            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
-                        })
-                        .create_view(&wgpu::TextureViewDescriptor::default());
+                    depth_target = create_depth_target(&device, extent);
                     app.resize(&device, extent);
                 }
                 event::Event::WindowEvent { event, .. } => match event {
@@ -191,6 +205,17 @@ impl Harness {
                     event::WindowEvent::CloseRequested => {
                         *control_flow = ControlFlow::Exit;
                     }
+                    event::WindowEvent::KeyboardInput {
+                        input:
+                            event::KeyboardInput {
+                                state: event::ElementState::Pressed,
+                                virtual_keycode: Some(SCREENSHOT_KEY),
+                                ..
+                            },
+                        ..
+                    } => {
+                        needs_screenshot = true;
+                    }
                     event::WindowEvent::KeyboardInput { input, .. } => {
                         if !app.on_key(input) {
                             *control_flow = ControlFlow::Exit;
@@ -211,11 +236,29 @@ impl Harness {
                     last_time += duration;
                     let delta = duration.as_secs() as f32 + duration.subsec_nanos() as f32 * 1.0e-9;
 
-                    let update_command_buffers = app.update(&device, delta, &spawner);
+                    let update_command_buffers = app.update(&device, &queue, delta, &spawner);
                     if !update_command_buffers.is_empty() {
                         queue.submit(update_command_buffers);
                     }
 
+                    if needs_screenshot {
+                        needs_screenshot = false;
+                        let offscreen = OffscreenTargets::new(&device, extent);
+                        let render_command_buffer =
+                            app.draw(&device, offscreen.targets(), &spawner);
+                        queue.submit(Some(render_command_buffer));
+                        let since_epoch = time::SystemTime::now()
+                            .duration_since(time::UNIX_EPOCH)
+                            .unwrap();
+                        let path =
+                            PathBuf::from(format!("{}-{}.png", title, since_epoch.as_millis()));
+                        let pixels = offscreen.read_pixels(&device, &queue);
+                        if let Err(e) = save_png(&path, extent, &pixels) {
+                            error!("Unable to save {}: {}", path.display(), e);
+                        }
+                        return;
+                    }
+
                     match swap_chain.get_current_frame() {
                         Ok(frame) => {
                             let targets = ScreenTargets {
@@ -234,3 +277,187 @@ impl Harness {
         });
     }
 }
+
+// Limits of a run without a window.
+pub struct HeadlessConfig {
+    // frames per second of the simulated time, regardless of the wall clock
+    pub frame_rate: f32,
+    pub max_frames: Option<usize>,
+    // in seconds of the simulated time
+    pub max_time: Option<f32>,
+    // draw every frame into an offscreen target, or only update
+    pub render: bool,
+    // where to save the last frame
+    pub screenshot: Option<PathBuf>,
+    // use a CPU implementation of the backend, for reproducible images
+    pub software: bool,
+}
+
+// Adds the options of `HeadlessConfig::from_matches` to the command line.
+pub fn headless_options(options: &mut getopts::Options) {
+    options
+        .optflag(
+            "",
+            "headless",
+            "run without a window, until --frames or --time is reached",
+        )
+        .optopt(
+            "",
+            "frame-rate",
+            "simulated frames per second when headless (default: 60)",
+            "FPS",
+        )
+        .optopt("", "frames", "number of frames to run when headless", "N")
+        .optopt("", "time", "simulated time to run when headless", "SECONDS")
+        .optflag(
+            "",
+            "no-render",
+            "skip the offscreen drawing when headless, still requires a device",
+        )
+        .optopt(
+            "",
+            "screenshot",
+            "run headless and save the last frame as PNG (default: after 1 frame)",
+            "FILE",
+        )
+        .optflag(
+            "",
+            "software",
+            "run headless on a software adapter, e.g. lavapipe",
+        );
+}
+
+impl HeadlessConfig {
+    // Returns `None` for the windowed mode, and an error for a run without a limit.
+    // A screenshot alone is taken after a single frame.
+    pub fn from_matches(matches: &getopts::Matches) -> Result<Option<Self>, String> {
+        let screenshot = matches.opt_str("screenshot").map(PathBuf::from);
+        let software = matches.opt_present("software");
+        if !matches.opt_present("headless") && screenshot.is_none() && !software {
+            return Ok(None);
+        }
+        let mut max_frames = matches.opt_get("frames").expect("Invalid frame count");
+        let max_time = matches.opt_get("time").expect("Invalid time");
+        if max_frames.is_none() && max_time.is_none() {
+            if screenshot.is_none() {
+                return Err("Headless run needs --frames or --time".to_string());
+            }
+            max_frames = Some(1);
+        }
+        Ok(Some(HeadlessConfig {
+            frame_rate: matches
+                .opt_get_default("frame-rate", 60.0)
+                .expect("Invalid frame rate"),
+            max_frames,
+            max_time,
+            render: !matches.opt_present("no-render"),
+            screenshot,
+            software,
+        }))
+    }
+}
+
+// Runs an application without a window, surface, or swap chain.
+pub struct Headless {
+    task_pool: LocalPool,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub extent: wgpu::Extent3d,
+    targets: OffscreenTargets,
+}
+
+impl Headless {
+    pub fn init(config: &HeadlessConfig) -> (Self, config::Settings) {
+        let mut task_pool = LocalPool::new();
+        let (settings, extent) = load_settings();
+        let backends = settings.backend.to_wgpu();
+        let instance = wgpu::Instance::new(backends);
+        // the game objects live on the device even if nothing is drawn
+        let (device, queue) =
+            match request_device(&mut task_pool, &instance, backends, None, config.software) {
+                Some(pair) => pair,
+                None => {
+                    error!(
+                        "Unable to find {} adapter, which headless runs need even with --no-render",
+                        if config.software { "a software" } else { "an" }
+                    );
+                    process::exit(1)
+                }
+            };
+        let targets = OffscreenTargets::new(&device, extent);
+
+        let headless = Headless {
+            task_pool,
+            device,
+            queue,
+            extent,
+            targets,
+        };
+        (headless, settings)
+    }
+
+    // Advances the application by fixed steps until one of the limits is reached,
+    // saving the last frame if requested, and exits with the status of the run.
+    pub fn run<A: Application>(self, mut app: A, config: &HeadlessConfig) -> ! {
+        use std::time;
+
+        assert!(
+            config.max_frames.is_some() || config.max_time.is_some(),
+            "Headless run needs a frame or time limit"
+        );
+        let Headless {
+            mut task_pool,
+            device,
+            queue,
+            extent,
+            targets,
+        } = self;
+        let delta = 1.0 / config.frame_rate;
+        let start = time::Instant::now();
+        let mut frame = 0;
+
+        loop {
+            let sim_time = frame as f32 * delta;
+            if config.max_frames.map_or(false, |max| frame >= max)
+                || config.max_time.map_or(false, |max| sim_time >= max)
+            {
+                break;
+            }
+            task_pool.run_until_stalled();
+            let spawner = task_pool.spawner();
+
+            let update_command_buffers = app.update(&device, &queue, delta, &spawner);
+            if !update_command_buffers.is_empty() {
+                queue.submit(update_command_buffers);
+            }
+            if config.render {
+                let render_command_buffer = app.draw(&device, targets.targets(), &spawner);
+                queue.submit(Some(render_command_buffer));
+            }
+            // keep the GPU in lockstep, so that the read-backs are ready for the next frame
+            device.poll(wgpu::Maintain::Wait);
+            frame += 1;
+        }
+
+        info!(
+            "Simulated {} frames ({} s) in {:?}",
+            frame,
+            frame as f32 * delta,
+            start.elapsed()
+        );
+
+        if let Some(ref path) = config.screenshot {
+            if !config.render || frame == 0 {
+                let render_command_buffer =
+                    app.draw(&device, targets.targets(), &task_pool.spawner());
+                queue.submit(Some(render_command_buffer));
+            }
+            let pixels = targets.read_pixels(&device, &queue);
+            if let Err(e) = save_png(path, extent, &pixels) {
+                error!("Unable to save {}: {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+        process::exit(0)
+    }
+}
@@ -31,7 +31,7 @@ impl CarView {
 
         info!("Loading car registry");
         let game_reg = config::game::Registry::load(settings);
-        let car_reg = config::car::load_registry(settings, &game_reg, device, &object);
+        let car_reg = config::car::load_registry(settings, &game_reg);
         let cinfo = match car_reg.get(&settings.car.id) {
             Some(ci) => ci,
             None => {
@@ -39,7 +39,7 @@ impl CarView {
                 panic!("Unable to find `{}` in {:?}", settings.car.id, names);
             }
         };
-        let mut model = cinfo.model.clone();
+        let mut model = model::upload_model(&cinfo.model, device, &object);
         for (ms, sid) in model.slots.iter_mut().zip(settings.car.slots.iter()) {
             let info = &game_reg.model_infos[sid];
             let raw = Mesh::load(&mut settings.open_relative(&info.path));
@@ -136,6 +136,7 @@ impl Application for CarView {
     fn update(
         &mut self,
         _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
         delta: f32,
         _spawner: &LocalSpawner,
     ) -> Vec<wgpu::CommandBuffer> {
@@ -3,9 +3,7 @@ mod app;
 mod boilerplate;
 
 fn main() {
-    use std::env;
-
-    let (mut harness, settings) = boilerplate::Harness::init("car");
+    use std::{env, process};
 
     let args: Vec<_> = env::args().collect();
     let mut options = getopts::Options::new();
@@ -15,16 +13,31 @@ fn main() {
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
         .optflag("h", "help", "print this help menu");
+    boilerplate::headless_options(&mut options);
 
     let matches = options.parse(&args[1..]).unwrap();
+    let brief = format!("Usage: {} [options]", args[0]);
     if matches.opt_present("h") || !matches.free.is_empty() {
         println!("Vangers mechos explorer");
-        let brief = format!("Usage: {} [options]", args[0]);
         println!("{}", options.usage(&brief));
         return;
     }
 
-    let app = app::CarView::new(&settings, &harness.device, &mut harness.queue);
-
-    harness.main_loop(app);
+    let headless = boilerplate::HeadlessConfig::from_matches(&matches).unwrap_or_else(|e| {
+        println!("{}", e);
+        println!("{}", options.usage(&brief));
+        process::exit(1)
+    });
+    match headless {
+        Some(config) => {
+            let (headless, settings) = boilerplate::Headless::init(&config);
+            let app = app::CarView::new(&settings, &headless.device, &headless.queue);
+            headless.run(app, &config);
+        }
+        None => {
+            let (harness, settings) = boilerplate::Harness::init("car");
+            let app = app::CarView::new(&settings, &harness.device, &harness.queue);
+            harness.main_loop(app);
+        }
+    }
 }
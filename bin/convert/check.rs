@@ -0,0 +1,86 @@
+use m3d::validate::{self, Issue};
+
+use std::{fs, panic, path::Path};
+
+// The loaders panic on corrupt data, so the error is caught here
+// to be reported along with the other models.
+fn check_file(path: &Path) -> Option<Result<Vec<Issue>, String>> {
+    let extension = path
+        .extension()
+        .and_then(|ostr| ostr.to_str())
+        .unwrap_or("");
+    let check: fn(&Path) -> Vec<Issue> = match extension.to_lowercase().as_str() {
+        "m3d" => |path| {
+            let raw = m3d::FullModel::load(fs::File::open(path).unwrap());
+            validate::full_model(&raw)
+        },
+        "a3d" => |path| {
+            let raw = m3d::AnimatedMesh::load(fs::File::open(path).unwrap());
+            validate::animated_mesh(&raw)
+        },
+        _ => return None,
+    };
+    Some(panic::catch_unwind(|| check(path)).map_err(|payload| {
+        if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else {
+            "unknown error".to_string()
+        }
+    }))
+}
+
+fn collect(path: &Path, files: &mut Vec<std::path::PathBuf>) {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        entries.sort();
+        for entry in entries {
+            collect(&entry, files);
+        }
+    } else {
+        files.push(path.to_path_buf());
+    }
+}
+
+// Returns true if no issues were found.
+pub fn run(path: &Path) -> bool {
+    let mut files = Vec::new();
+    collect(path, &mut files);
+
+    let mut num_models = 0;
+    let mut num_bad_models = 0;
+    let mut num_issues = 0;
+    for file in files {
+        let issues = match check_file(&file) {
+            Some(Ok(issues)) => issues,
+            Some(Err(message)) => {
+                println!("{}: unable to load: {}", file.display(), message);
+                num_models += 1;
+                num_bad_models += 1;
+                num_issues += 1;
+                continue;
+            }
+            None => continue,
+        };
+        num_models += 1;
+        if issues.is_empty() {
+            continue;
+        }
+        println!("{}: {} issues", file.display(), issues.len());
+        for issue in &issues {
+            println!("\t{}", issue);
+        }
+        num_bad_models += 1;
+        num_issues += issues.len();
+    }
+
+    println!(
+        "Checked {} models: {} with issues, {} issues total",
+        num_models, num_bad_models, num_issues
+    );
+    num_issues == 0
+}
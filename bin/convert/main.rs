@@ -1,5 +1,7 @@
+mod check;
 mod layers;
 mod level_png;
+mod model_gltf;
 mod model_obj;
 
 use std::{
@@ -59,18 +61,43 @@ fn main() {
     let mut options = getopts::Options::new();
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
-        .optflag("h", "help", "print this help menu");
+        .optflag("h", "help", "print this help menu")
+        .optflag(
+            "c",
+            "check",
+            "validate the given model or a directory of models",
+        )
+        .optopt(
+            "p",
+            "palette",
+            "object palette for the exported materials",
+            "objects.pal",
+        );
 
     let matches = options.parse(&args[1..]).unwrap();
+    if matches.opt_present("c") && matches.free.len() == 1 {
+        let path = PathBuf::from(matches.free[0].as_str());
+        if !check::run(&path) {
+            std::process::exit(1);
+        }
+        return;
+    }
     if matches.opt_present("h") || matches.free.len() != 2 {
         println!("Vangers resource converter");
-        let brief = format!("Usage: {} [options] <input> <output>", args[0]);
+        let brief = format!(
+            "Usage: {0} [options] <input> <output>\n       {0} --check <model_or_dir>",
+            args[0]
+        );
         println!("{}", options.usage(&brief));
         return;
     }
 
     let src_path = PathBuf::from(matches.free[0].as_str());
     let dst_path = PathBuf::from(matches.free[1].as_str());
+    let palette = matches.opt_str("p").map(|path| {
+        let file = File::open(&path).expect("Unable to open the palette");
+        vangers::level::read_palette(file, None)
+    });
 
     match (
         src_path
@@ -87,7 +114,7 @@ fn main() {
             println!("\tLoading M3D...");
             let raw = m3d::FullModel::load(file);
             println!("\tExporting OBJ data...");
-            model_obj::export_m3d(raw, &dst_path);
+            model_obj::export_m3d(raw, &dst_path, palette.as_ref());
         }
         ("ron", "md3") => {
             println!("\tImporting OBJ data...");
@@ -100,7 +127,14 @@ fn main() {
             println!("\tLoading A3D...");
             let raw = m3d::AnimatedMesh::load(file);
             println!("\tExporting OBJ data...");
-            model_obj::export_a3d(raw, &dst_path);
+            model_obj::export_a3d(raw, &dst_path, palette.as_ref());
+        }
+        ("a3d", "gltf") => {
+            let file = File::open(&src_path).unwrap();
+            println!("\tLoading A3D...");
+            let raw = m3d::AnimatedMesh::load(file);
+            println!("\tExporting glTF morph targets...");
+            model_gltf::export_a3d(raw, &dst_path, palette.as_ref());
         }
         ("ron", "a3d") => {
             println!("\tImporting OBJ data...");
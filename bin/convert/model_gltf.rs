@@ -0,0 +1,281 @@
+use crate::model_obj::{palette_range, DrawAnimatedMesh, Palette};
+use m3d::NORMALIZER;
+use vangers::config::common::MAIN_LOOP_TIME;
+
+use byteorder::{LittleEndian as E, WriteBytesExt};
+
+use std::{
+    fs,
+    io::{BufWriter, Result as IoResult, Write},
+    path::PathBuf,
+};
+
+// Rotates the game's Z-up space into the Y-up space of glTF.
+const ROOT_ROTATION: [f32; 4] = [-0.70710677, 0.0, 0.0, 0.70710677];
+
+const FLOAT: u32 = 5126;
+const ARRAY_BUFFER: u32 = 34962;
+
+struct Accessor {
+    offset: usize,
+    count: usize,
+    kind: &'static str,
+    min: Vec<f32>,
+    max: Vec<f32>,
+}
+
+struct Attributes {
+    position: usize,
+    normal: usize,
+    color: Option<usize>,
+}
+
+#[derive(Default)]
+struct Builder {
+    data: Vec<u8>,
+    accessors: Vec<Accessor>,
+}
+
+impl Builder {
+    fn add(&mut self, values: &[f32], components: usize, kind: &'static str) -> usize {
+        let mut min = vec![std::f32::MAX; components];
+        let mut max = vec![std::f32::MIN; components];
+        for chunk in values.chunks(components) {
+            for (i, &v) in chunk.iter().enumerate() {
+                min[i] = min[i].min(v);
+                max[i] = max[i].max(v);
+            }
+        }
+        let offset = self.data.len();
+        for &v in values {
+            self.data.write_f32::<E>(v).unwrap();
+        }
+        self.accessors.push(Accessor {
+            offset,
+            count: values.len() / components,
+            kind,
+            min,
+            max,
+        });
+        self.accessors.len() - 1
+    }
+}
+
+fn normalize(n: [i8; 3]) -> [f32; 3] {
+    let v = [
+        n[0] as f32 / NORMALIZER,
+        n[1] as f32 / NORMALIZER,
+        n[2] as f32 / NORMALIZER,
+    ];
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if len > 0.0 {
+        [v[0] / len, v[1] / len, v[2] / len]
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+fn format_floats(values: &[f32]) -> String {
+    let strings = values
+        .iter()
+        .map(|v| format!("{:?}", v))
+        .collect::<Vec<_>>();
+    format!("[{}]", strings.join(", "))
+}
+
+// Exports the animated mesh as a glTF with one morph target per frame,
+// driven by a looping weights animation.
+pub fn export_a3d(a3d: DrawAnimatedMesh, gltf_path: &PathBuf, palette: Option<&Palette>) {
+    assert!(!a3d.meshes.is_empty(), "No frames to export");
+    if !a3d.is_morphable() {
+        panic!("A3D frames have different topology, can't export as morph targets");
+    }
+
+    // glTF doesn't share indices between attributes, so the triangles are expanded.
+    let frames = a3d
+        .meshes
+        .iter()
+        .map(|mesh| {
+            let geom = &mesh.geometry;
+            let mut positions = Vec::with_capacity(geom.polygons.len() * 9);
+            let mut normals = Vec::with_capacity(geom.polygons.len() * 9);
+            for tri in &geom.polygons {
+                for v in &tri.vertices {
+                    let p = geom.positions[v.pos as usize];
+                    positions.extend_from_slice(&[p[0] as f32, p[1] as f32, p[2] as f32]);
+                    normals.extend_from_slice(&normalize(geom.normals[v.normal as usize]));
+                }
+            }
+            (positions, normals)
+        })
+        .collect::<Vec<_>>();
+
+    let mut builder = Builder::default();
+    let (ref base_positions, ref base_normals) = frames[0];
+    let position = builder.add(base_positions, 3, "VEC3");
+    let normal = builder.add(base_normals, 3, "VEC3");
+    let color = palette.map(|pal| {
+        let mut colors = Vec::with_capacity(base_positions.len());
+        for tri in &a3d.meshes[0].geometry.polygons {
+            let (start, end) = palette_range(tri.material[0], &a3d.color);
+            let c = pal[end.max(start + 1) - 1];
+            for _ in 0..3 {
+                colors.extend_from_slice(&[
+                    c[0] as f32 / 255.0,
+                    c[1] as f32 / 255.0,
+                    c[2] as f32 / 255.0,
+                ]);
+            }
+        }
+        builder.add(&colors, 3, "VEC3")
+    });
+
+    let mut targets = Vec::new();
+    for (positions, normals) in frames.iter().skip(1) {
+        let delta = |values: &[f32], base: &[f32]| {
+            values
+                .iter()
+                .zip(base)
+                .map(|(v, b)| v - b)
+                .collect::<Vec<_>>()
+        };
+        let pos_id = builder.add(&delta(positions, base_positions), 3, "VEC3");
+        let norm_id = builder.add(&delta(normals, base_normals), 3, "VEC3");
+        targets.push((pos_id, norm_id));
+    }
+
+    // One key per frame, plus the closing key that returns to frame 0 for the loop.
+    let num_targets = targets.len();
+    let times = (0..=frames.len())
+        .map(|i| i as f32 * MAIN_LOOP_TIME)
+        .collect::<Vec<_>>();
+    let mut weights = vec![0.0; times.len() * num_targets];
+    for frame in 1..frames.len() {
+        weights[frame * num_targets + frame - 1] = 1.0;
+    }
+    let animation = if num_targets != 0 {
+        let time_id = builder.add(&times, 1, "SCALAR");
+        let weight_id = builder.add(&weights, 1, "SCALAR");
+        Some((time_id, weight_id))
+    } else {
+        None
+    };
+
+    let bin_path = gltf_path.with_extension("bin");
+    fs::write(&bin_path, &builder.data).unwrap();
+    let bin_name = bin_path.file_name().unwrap().to_str().unwrap();
+    save_json(
+        gltf_path,
+        &builder,
+        bin_name,
+        &Attributes {
+            position,
+            normal,
+            color,
+        },
+        &targets,
+        animation,
+    )
+    .unwrap();
+}
+
+fn save_json(
+    path: &PathBuf,
+    builder: &Builder,
+    bin_name: &str,
+    attributes: &Attributes,
+    targets: &[(usize, usize)],
+    animation: Option<(usize, usize)>,
+) -> IoResult<()> {
+    let mut dest = BufWriter::new(fs::File::create(path)?);
+    writeln!(dest, "{{")?;
+    writeln!(
+        dest,
+        "  \"asset\": {{ \"version\": \"2.0\", \"generator\": \"vangers convert\" }},"
+    )?;
+    writeln!(dest, "  \"scene\": 0,")?;
+    writeln!(dest, "  \"scenes\": [{{ \"nodes\": [0] }}],")?;
+    writeln!(
+        dest,
+        "  \"nodes\": [{{ \"mesh\": 0, \"rotation\": {} }}],",
+        format_floats(&ROOT_ROTATION)
+    )?;
+
+    write!(
+        dest,
+        "  \"meshes\": [{{ \"primitives\": [{{ \"attributes\": {{ \"POSITION\": {}, \"NORMAL\": {}",
+        attributes.position, attributes.normal
+    )?;
+    if let Some(id) = attributes.color {
+        write!(dest, ", \"COLOR_0\": {}", id)?;
+    }
+    write!(dest, " }}")?;
+    if !targets.is_empty() {
+        let list = targets
+            .iter()
+            .map(|&(pos, norm)| format!("{{ \"POSITION\": {}, \"NORMAL\": {} }}", pos, norm))
+            .collect::<Vec<_>>();
+        write!(dest, ", \"targets\": [{}]", list.join(", "))?;
+    }
+    write!(dest, " }}]")?;
+    if !targets.is_empty() {
+        let weights = vec![0.0; targets.len()];
+        write!(dest, ", \"weights\": {}", format_floats(&weights))?;
+    }
+    writeln!(dest, " }}],")?;
+
+    if let Some((time_id, weight_id)) = animation {
+        writeln!(
+            dest,
+            "  \"animations\": [{{ \"samplers\": [{{ \"input\": {}, \"output\": {}, \"interpolation\": \"LINEAR\" }}], \
+            \"channels\": [{{ \"sampler\": 0, \"target\": {{ \"node\": 0, \"path\": \"weights\" }} }}] }}],",
+            time_id, weight_id
+        )?;
+    }
+
+    writeln!(
+        dest,
+        "  \"buffers\": [{{ \"uri\": \"{}\", \"byteLength\": {} }}],",
+        bin_name,
+        builder.data.len()
+    )?;
+
+    // Each accessor gets its own view, so that only vertex attributes are marked as such.
+    let views = builder
+        .accessors
+        .iter()
+        .map(|acc| {
+            let size = acc.count * acc.min.len() * 4;
+            let target = if acc.kind == "VEC3" {
+                format!(", \"target\": {}", ARRAY_BUFFER)
+            } else {
+                String::new()
+            };
+            format!(
+                "    {{ \"buffer\": 0, \"byteOffset\": {}, \"byteLength\": {}{} }}",
+                acc.offset, size, target
+            )
+        })
+        .collect::<Vec<_>>();
+    writeln!(dest, "  \"bufferViews\": [\n{}\n  ],", views.join(",\n"))?;
+
+    let accessors = builder
+        .accessors
+        .iter()
+        .enumerate()
+        .map(|(i, acc)| {
+            format!(
+                "    {{ \"bufferView\": {}, \"componentType\": {}, \"count\": {}, \"type\": \"{}\", \"min\": {}, \"max\": {} }}",
+                i,
+                FLOAT,
+                acc.count,
+                acc.kind,
+                format_floats(&acc.min),
+                format_floats(&acc.max)
+            )
+        })
+        .collect::<Vec<_>>();
+    writeln!(dest, "  \"accessors\": [\n{}\n  ]", accessors.join(",\n"))?;
+    writeln!(dest, "}}")?;
+    Ok(())
+}
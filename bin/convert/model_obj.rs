@@ -1,9 +1,10 @@
 use m3d::{
-    AnimatedMesh, CollisionQuad, ColorId, Debrie, DrawTriangle, FullModel, Geometry, Mesh, Model,
-    Polygon, Slot, Vertex, NORMALIZER, NUM_COLOR_IDS,
+    AnimatedMesh, BodyColor, CollisionQuad, ColorId, Debrie, DrawTriangle, FullModel, Geometry,
+    Mesh, Model, Polygon, Slot, Vertex, NORMALIZER, NUM_COLOR_IDS,
 };
+use vangers::render::object::COLOR_TABLE;
 
-use obj::{IndexTuple, Obj};
+use obj::{IndexTuple, Obj, ObjMaterial};
 
 use std::{
     fs,
@@ -13,17 +14,21 @@ use std::{
 
 type RefModel = Model<Mesh<String>, Mesh<String>>;
 type RefAnimatedMesh = AnimatedMesh<String>;
-type DrawAnimatedMesh = AnimatedMesh<Geometry<DrawTriangle>>;
+pub type DrawAnimatedMesh = AnimatedMesh<Geometry<DrawTriangle>>;
+pub type Palette = [[u8; 4]; 0x100];
 
-pub fn export_m3d(full: FullModel, model_path: &PathBuf) {
+const MATERIAL_LIB_PATH: &str = "materials.mtl";
+
+pub fn export_m3d(full: FullModel, model_path: &PathBuf, palette: Option<&Palette>) {
     const BODY_PATH: &str = "body.obj";
     const SHAPE_PATH: &str = "body-shape.obj";
 
     let dir_path = model_path.parent().unwrap();
+    let mut color_mask = 0u32;
 
     let model = RefModel {
         body: full.body.map(|geom| {
-            save_draw_geometry(&geom, dir_path.join(BODY_PATH)).unwrap();
+            color_mask |= save_draw_geometry(&geom, dir_path.join(BODY_PATH)).unwrap();
             BODY_PATH.to_string()
         }),
         shape: full.shape.map(|geom| {
@@ -40,7 +45,7 @@ pub fn export_m3d(full: FullModel, model_path: &PathBuf) {
                 wheel.map(|mesh| {
                     mesh.map(|geom| {
                         let name = format!("wheel{}.obj", i);
-                        save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
+                        color_mask |= save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
                         name
                     })
                 })
@@ -53,7 +58,7 @@ pub fn export_m3d(full: FullModel, model_path: &PathBuf) {
             .map(|(i, debrie)| Debrie {
                 mesh: debrie.mesh.map(|geom| {
                     let name = format!("debrie{}.obj", i);
-                    save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
+                    color_mask |= save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
                     name
                 }),
                 shape: debrie.shape.map(|geom| {
@@ -66,12 +71,20 @@ pub fn export_m3d(full: FullModel, model_path: &PathBuf) {
         slots: Slot::map_all(full.slots, |mesh, i| {
             mesh.map(|geom| {
                 let name = format!("slot{}.obj", i);
-                save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
+                color_mask |= save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
                 name
             })
         }),
     };
 
+    save_material_lib(
+        dir_path.join(MATERIAL_LIB_PATH),
+        color_mask,
+        &model.color,
+        palette,
+    )
+    .unwrap();
+
     let string = ron::ser::to_string_pretty(&model, ron::ser::PrettyConfig::default()).unwrap();
     fs::write(model_path, string).unwrap();
 }
@@ -108,8 +121,9 @@ pub fn import_m3d(model_path: &PathBuf) -> FullModel {
     }
 }
 
-pub fn export_a3d(a3d: DrawAnimatedMesh, mesh_path: &PathBuf) {
+pub fn export_a3d(a3d: DrawAnimatedMesh, mesh_path: &PathBuf, palette: Option<&Palette>) {
     let dir_path = mesh_path.parent().unwrap();
+    let mut color_mask = 0u32;
 
     let amesh = RefAnimatedMesh {
         bound: a3d.bound,
@@ -121,13 +135,21 @@ pub fn export_a3d(a3d: DrawAnimatedMesh, mesh_path: &PathBuf) {
             .map(|(i, mesh)| {
                 let name = format!("body-{}.obj", i + 1);
                 mesh.map(|geom| {
-                    save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
+                    color_mask |= save_draw_geometry(&geom, dir_path.join(&name)).unwrap();
                     name
                 })
             })
             .collect(),
     };
 
+    save_material_lib(
+        dir_path.join(MATERIAL_LIB_PATH),
+        color_mask,
+        &amesh.color,
+        palette,
+    )
+    .unwrap();
+
     let string = ron::ser::to_string_pretty(&amesh, ron::ser::PrettyConfig::default()).unwrap();
     fs::write(mesh_path, string).unwrap();
 }
@@ -190,8 +212,61 @@ fn flatten_pos(poly: &[IndexTuple], positions: &[[f32; 3]]) -> [i8; 3] {
     ]
 }
 
-pub fn save_draw_geometry(geom: &Geometry<DrawTriangle>, path: PathBuf) -> IoResult<()> {
+fn color_id_from_name(name: &str) -> Option<u32> {
+    (0..NUM_COLOR_IDS).find(|&id| format!("{:?}", map_color_id(id)) == name)
+}
+
+pub fn palette_range(color_id: u32, body_color: &BodyColor) -> (usize, usize) {
+    let (offset, shift) = if color_id == ColorId::Body as u32 {
+        (body_color.offset as usize, body_color.shift as usize)
+    } else {
+        let [offset, shift] = COLOR_TABLE[color_id as usize];
+        (offset as usize, shift as usize)
+    };
+    (offset, (offset + (0x80 >> shift)).min(0x100))
+}
+
+fn write_color(dest: &mut fs::File, key: &str, color: [u8; 4]) -> IoResult<()> {
+    writeln!(
+        dest,
+        "{} {} {} {}",
+        key,
+        color[0] as f32 / 255.0,
+        color[1] as f32 / 255.0,
+        color[2] as f32 / 255.0
+    )
+}
+
+// One material per used color ID, named after `ColorId` variants.
+// Ambient is the darkest entry of the palette range, diffuse is the brightest.
+pub fn save_material_lib(
+    path: PathBuf,
+    color_mask: u32,
+    body_color: &BodyColor,
+    palette: Option<&Palette>,
+) -> IoResult<()> {
+    let mut dest = fs::File::create(&path)?;
+    for color_id in 0..NUM_COLOR_IDS {
+        if color_mask & (1 << color_id) == 0 {
+            continue;
+        }
+        writeln!(dest, "newmtl {:?}", map_color_id(color_id))?;
+        if let Some(pal) = palette {
+            let (start, end) = palette_range(color_id, body_color);
+            write_color(&mut dest, "Ka", pal[start])?;
+            write_color(&mut dest, "Kd", pal[end.max(start + 1) - 1])?;
+        }
+        writeln!(dest, "illum 1")?;
+        writeln!(dest, "")?;
+    }
+    Ok(())
+}
+
+// Returns the mask of used color IDs.
+pub fn save_draw_geometry(geom: &Geometry<DrawTriangle>, path: PathBuf) -> IoResult<u32> {
     let mut dest = fs::File::create(&path).unwrap();
+    writeln!(dest, "mtllib {}", MATERIAL_LIB_PATH)?;
+    writeln!(dest, "")?;
     for p in geom.positions.iter() {
         writeln!(dest, "v {} {} {}", p[0], p[1], p[2])?;
     }
@@ -216,6 +291,7 @@ pub fn save_draw_geometry(geom: &Geometry<DrawTriangle>, path: PathBuf) -> IoRes
             continue;
         }
         writeln!(dest, "g {:?}", map_color_id(color_id))?;
+        writeln!(dest, "usemtl {:?}", map_color_id(color_id))?;
         for p in &geom.polygons {
             if p.material[0] != color_id {
                 continue;
@@ -228,7 +304,7 @@ pub fn save_draw_geometry(geom: &Geometry<DrawTriangle>, path: PathBuf) -> IoRes
         }
     }
 
-    Ok(())
+    Ok(mask)
 }
 
 pub fn save_collision_geometry(geom: &Geometry<CollisionQuad>, path: PathBuf) -> IoResult<()> {
@@ -289,10 +365,6 @@ pub fn load_geometry<P: Polygon>(path: PathBuf) -> Geometry<P> {
         })
         .collect();
 
-    let color_names = (0..NUM_COLOR_IDS)
-        .map(|id| format!("{:?}", map_color_id(id)))
-        .collect::<Vec<_>>();
-
     let data_ref = &obj.data;
     let polygons = obj
         .data
@@ -301,9 +373,15 @@ pub fn load_geometry<P: Polygon>(path: PathBuf) -> Geometry<P> {
         .flat_map(|object| {
             object.groups.iter().flat_map(|group| {
                 let mut vertices = Vec::with_capacity(4);
-                let color_id = color_names
-                    .iter()
-                    .position(|c| c == &group.name)
+                // the material takes precedence, the group name is a fallback
+                let material_name = match group.material {
+                    Some(ObjMaterial::Ref(ref name)) => Some(name.as_str()),
+                    Some(ObjMaterial::Mtl(ref mtl)) => Some(mtl.name.as_str()),
+                    None => None,
+                };
+                let color_id = material_name
+                    .and_then(color_id_from_name)
+                    .or_else(|| color_id_from_name(&group.name))
                     .unwrap_or(0);
                 group.polys.iter().map(move |poly| {
                     vertices.clear();
@@ -0,0 +1,270 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use vangers::{
+    config,
+    environment::{Environment, Step},
+    level,
+    physics::Control,
+};
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    net::TcpListener,
+    panic,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Request {
+    // the missing fields keep the values of the previous episode
+    Reset {
+        #[serde(default)]
+        seed: Option<u64>,
+        #[serde(default)]
+        level: Option<String>,
+        #[serde(default)]
+        car: Option<String>,
+    },
+    Step {
+        #[serde(default)]
+        control: Control,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Response {
+    Step(Step),
+    Error { error: String },
+}
+
+// Game data shared by all the sessions, with the levels loaded on demand.
+struct World {
+    common: Arc<config::common::Common>,
+    cars: HashMap<String, config::car::CarInfo>,
+    // levels that can't be loaded keep the error to report
+    level_paths: HashMap<String, Result<PathBuf, String>>,
+    levels: Mutex<HashMap<String, Arc<level::Level>>>,
+    max_quant: f32,
+    episode_ticks: usize,
+    default_level: String,
+    default_car: String,
+}
+
+impl World {
+    fn load(settings: &config::Settings, episode_ticks: usize) -> Self {
+        let game = config::game::Registry::load(settings);
+        let cars = config::car::load_registry(settings, &game);
+        let common = config::common::load_with(settings);
+        let level_paths = config::worlds::load(settings.open_relative("wrlds.dat"))
+            .into_iter()
+            .map(|(name, ini_name)| {
+                let path = settings.resolve_path(&ini_name).map_err(|e| e.to_string());
+                (name, path)
+            })
+            .collect();
+        World {
+            common: Arc::new(common),
+            cars,
+            level_paths,
+            levels: Mutex::new(HashMap::new()),
+            max_quant: settings.game.physics.max_quant,
+            episode_ticks,
+            default_level: settings.game.level.clone(),
+            default_car: settings.car.id.clone(),
+        }
+    }
+
+    // The loading doesn't hold the lock, so that the other sessions can go on.
+    fn level(&self, name: &str) -> Result<Arc<level::Level>, String> {
+        if let Some(level) = self.levels.lock().unwrap().get(name) {
+            return Ok(Arc::clone(level));
+        }
+        let level = if name.is_empty() {
+            level::Level::new_test()
+        } else {
+            let path = self
+                .level_paths
+                .get(name)
+                .ok_or_else(|| format!("Unknown level '{}'", name))?
+                .as_ref()?;
+            info!("Loading level {}", name);
+            panic::catch_unwind(|| level::load(&level::LevelConfig::load(path))).map_err(
+                |payload| {
+                    let reason = if let Some(message) = payload.downcast_ref::<String>() {
+                        message.clone()
+                    } else if let Some(message) = payload.downcast_ref::<&str>() {
+                        message.to_string()
+                    } else {
+                        "unknown error".to_string()
+                    };
+                    format!("Unable to load level '{}': {}", name, reason)
+                },
+            )?
+        };
+        // another session may have loaded it in the meantime
+        let mut levels = self.levels.lock().unwrap();
+        let level = levels
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(level));
+        Ok(Arc::clone(level))
+    }
+}
+
+// One client driving one vehicle at a time.
+struct Session<'a> {
+    world: &'a World,
+    level: String,
+    car: String,
+    env: Option<Environment>,
+    // the episode is over until the next reset
+    done: bool,
+}
+
+impl Session<'_> {
+    fn handle(&mut self, request: Request) -> Result<Step, String> {
+        match request {
+            Request::Reset { seed, level, car } => {
+                // the session keeps its level and car unless the new ones work out
+                let car = car.unwrap_or_else(|| self.car.clone());
+                if !self.world.cars.contains_key(&car) {
+                    return Err(format!("Unknown car '{}'", car));
+                }
+                let level = level.unwrap_or_else(|| self.level.clone());
+                let mut env = Environment::new(
+                    self.world.level(&level)?,
+                    Arc::clone(&self.world.common),
+                    self.world.cars[&car].clone(),
+                    self.world.max_quant,
+                    self.world.episode_ticks,
+                );
+                let observation = env
+                    .reset(seed.unwrap_or_else(rand::random))
+                    .ok_or_else(|| format!("No dry place to spawn on level '{}'", level))?;
+                self.car = car;
+                self.level = level;
+                self.env = Some(env);
+                self.done = false;
+                Ok(Step {
+                    observation,
+                    reward: 0.0,
+                    done: false,
+                })
+            }
+            Request::Step { control } => match self.env {
+                Some(_) if self.done => Err("The episode is over, send a reset".to_string()),
+                Some(ref mut env) => {
+                    let step = env.step(&control);
+                    self.done = step.done;
+                    Ok(step)
+                }
+                None => Err("No episode, send a reset first".to_string()),
+            },
+        }
+    }
+}
+
+// Answers every line of JSON with a line of JSON, until the input is closed.
+fn serve<R: BufRead, W: Write>(world: &World, input: R, mut output: W) {
+    let mut session = Session {
+        world,
+        level: world.default_level.clone(),
+        car: world.default_car.clone(),
+        env: None,
+        done: false,
+    };
+    for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                info!("Closing the session: {}", e);
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = serde_json::from_str(&line)
+            .map_err(|e| e.to_string())
+            .and_then(|request| session.handle(request));
+        let response = match result {
+            Ok(step) => Response::Step(step),
+            Err(error) => Response::Error { error },
+        };
+        let sent = serde_json::to_writer(&mut output, &response)
+            .map_err(io::Error::from)
+            .and_then(|()| writeln!(output))
+            .and_then(|()| output.flush());
+        if let Err(e) = sent {
+            info!("Closing the session: {}", e);
+            return;
+        }
+    }
+}
+
+fn main() {
+    use std::env;
+
+    env_logger::init();
+    let args: Vec<_> = env::args().collect();
+    let mut options = getopts::Options::new();
+    options
+        .optflag("h", "help", "print this help menu")
+        .optopt(
+            "",
+            "port",
+            "serve the clients on a localhost TCP port instead of stdin/stdout",
+            "PORT",
+        )
+        .optopt(
+            "",
+            "episode-ticks",
+            "length of an episode in the simulation ticks (default: 1000)",
+            "TICKS",
+        );
+
+    let matches = options.parse(&args[1..]).unwrap();
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        println!("Vangers driving environment, speaking JSON lines");
+        let brief = format!("Usage: {} [options]", args[0]);
+        println!("{}", options.usage(&brief));
+        return;
+    }
+    let episode_ticks = matches
+        .opt_get_default("episode-ticks", 1000)
+        .expect("Invalid episode length");
+
+    let settings = config::Settings::load("config/settings.ron");
+    let world = World::load(&settings, episode_ticks);
+
+    match matches.opt_get::<u16>("port").expect("Invalid port") {
+        Some(port) => {
+            let listener = TcpListener::bind(("127.0.0.1", port)).expect("Unable to bind the port");
+            info!("Listening on {}", listener.local_addr().unwrap());
+            let world = Arc::new(world);
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        info!("Failed to accept a client: {}", e);
+                        continue;
+                    }
+                };
+                let world = Arc::clone(&world);
+                thread::spawn(move || {
+                    info!("Serving {:?}", stream.peer_addr());
+                    let input = BufReader::new(stream.try_clone().unwrap());
+                    serve(&world, input, stream);
+                });
+            }
+        }
+        None => {
+            let stdin = io::stdin();
+            serve(&world, stdin.lock(), io::stdout());
+        }
+    }
+}
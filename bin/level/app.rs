@@ -49,7 +49,9 @@ impl LevelView {
                 "Unable to find the world, supported: {:?}",
                 worlds.keys().collect::<Vec<_>>()
             ));
-            let ini_path = settings.data_path.join(ini_name);
+            let ini_path = settings
+                .resolve_path(ini_name)
+                .unwrap_or_else(|e| panic!("Unable to find the level: {}", e));
             info!("Using level {}", ini_name);
 
             let level_config = level::LevelConfig::load(&ini_path);
@@ -256,6 +258,7 @@ impl Application for LevelView {
     fn update(
         &mut self,
         _device: &wgpu::Device,
+        _queue: &wgpu::Queue,
         delta: f32,
         _spawner: &LocalSpawner,
     ) -> Vec<wgpu::CommandBuffer> {
@@ -2,15 +2,62 @@ mod app;
 #[path = "../boilerplate.rs"]
 mod boilerplate;
 
+use vangers::config;
+
 fn main() {
-    let (mut harness, settings) = boilerplate::Harness::init("level");
+    use std::{env, process};
+
+    let args: Vec<_> = env::args().collect();
+    let mut options = getopts::Options::new();
+    options
+        .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
+        .optflag("h", "help", "print this help menu")
+        .optopt(
+            "",
+            "terrain",
+            "override the terrain renderer, e.g. \"Sliced\"",
+            "RON",
+        );
+    boilerplate::headless_options(&mut options);
 
-    let app = app::LevelView::new(
-        &settings,
-        harness.extent,
-        &harness.device,
-        &mut harness.queue,
-    );
+    let matches = options.parse(&args[1..]).unwrap();
+    let brief = format!("Usage: {} [options]", args[0]);
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        println!("Vangers level viewer");
+        println!("{}", options.usage(&brief));
+        return;
+    }
+    let terrain = matches
+        .opt_str("terrain")
+        .map(|ron| ron::de::from_str::<config::settings::Terrain>(&ron).expect("Invalid terrain"));
 
-    harness.main_loop(app);
+    let headless = boilerplate::HeadlessConfig::from_matches(&matches).unwrap_or_else(|e| {
+        println!("{}", e);
+        println!("{}", options.usage(&brief));
+        process::exit(1)
+    });
+    match headless {
+        Some(config) => {
+            let (headless, mut settings) = boilerplate::Headless::init(&config);
+            if let Some(terrain) = terrain {
+                settings.render.terrain = terrain;
+            }
+            let app = app::LevelView::new(
+                &settings,
+                headless.extent,
+                &headless.device,
+                &headless.queue,
+            );
+            headless.run(app, &config);
+        }
+        None => {
+            let (harness, mut settings) = boilerplate::Harness::init("level");
+            if let Some(terrain) = terrain {
+                settings.render.terrain = terrain;
+            }
+            let app =
+                app::LevelView::new(&settings, harness.extent, &harness.device, &harness.queue);
+            harness.main_loop(app);
+        }
+    }
 }
@@ -5,10 +5,57 @@ use futures::executor::LocalSpawner;
 use log::info;
 use wgpu::util::DeviceExt as _;
 
-use std::mem;
+use std::{mem, path::Path, sync::Arc};
+
+struct Animation {
+    raw: model::RawAnimation,
+    frames: Vec<Arc<model::Mesh>>,
+    // rewritten with the interpolated frames, if they can be morphed
+    morph_target: Option<Arc<model::Mesh>>,
+    morphed_time: Option<f32>,
+    time: f32,
+    playing: bool,
+}
+
+impl Animation {
+    fn blend(&self) -> m3d::FrameBlend {
+        self.raw
+            .blend_at(self.time, config::common::MAIN_LOOP_TIME, true)
+    }
+
+    fn step(&mut self, offset: isize) {
+        let count = self.frames.len() as isize;
+        let current = self.blend().current as isize;
+        let frame = (current + offset).rem_euclid(count);
+        self.time = frame as f32 * config::common::MAIN_LOOP_TIME;
+        self.playing = false;
+        info!("Frame {}/{}", frame + 1, count);
+    }
+
+    // Jumps to the point of the timeline, given as a fraction of the whole.
+    fn scrub(&mut self, fraction: f32) {
+        let duration = self.raw.duration(config::common::MAIN_LOOP_TIME);
+        self.time = fraction.max(0.0).min(1.0) * duration;
+        self.playing = false;
+    }
+
+    fn current_mesh(&self) -> &Arc<model::Mesh> {
+        match self.morph_target {
+            Some(ref mesh) => mesh,
+            None => &self.frames[self.blend().nearest()],
+        }
+    }
+}
+
+enum Subject {
+    Model(model::VisualModel),
+    Animation(Animation),
+}
 
 pub struct ResourceView {
-    model: model::VisualModel,
+    subject: Subject,
+    screen_width: f32,
+    scrubbing: bool,
     global: render::global::Context,
     object: render::object::Context,
     transform: space::Transform,
@@ -32,10 +79,36 @@ impl ResourceView {
 
         info!("Loading model {}", path);
         let file = settings.open_relative(path);
-        let model = model::load_m3d(file, device, &object, settings.game.physics.shape_sampling);
+        let subject = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("a3d") => {
+                let (raw, frames) = model::load_a3d(file, device);
+                info!("\tGot {} frames", frames.len());
+                let morph_target = if raw.is_morphable() {
+                    Some(model::create_morph_target(&raw.meshes[0], device))
+                } else {
+                    None
+                };
+                Subject::Animation(Animation {
+                    raw,
+                    frames,
+                    morph_target,
+                    morphed_time: None,
+                    time: 0.0,
+                    playing: true,
+                })
+            }
+            _ => Subject::Model(model::load_m3d(
+                file,
+                device,
+                &object,
+                settings.game.physics.shape_sampling,
+            )),
+        };
 
         ResourceView {
-            model,
+            subject,
+            screen_width: settings.window.size[0] as f32,
+            scrubbing: false,
             global,
             object,
             transform: cgmath::Decomposed {
@@ -73,6 +146,16 @@ impl Application for ResourceView {
                 Key::Escape => return false,
                 Key::A => self.rotation = -angle,
                 Key::D => self.rotation = angle,
+                Key::Space => {
+                    if let Subject::Animation(ref mut anim) = self.subject {
+                        anim.playing = !anim.playing;
+                    }
+                }
+                Key::Left | Key::Right => {
+                    if let Subject::Animation(ref mut anim) = self.subject {
+                        anim.step(if key == Key::Left { -1 } else { 1 });
+                    }
+                }
                 _ => (),
             },
             KeyboardInput {
@@ -89,9 +172,28 @@ impl Application for ResourceView {
         true
     }
 
+    fn on_mouse_button(
+        &mut self,
+        state: winit::event::ElementState,
+        button: winit::event::MouseButton,
+    ) {
+        if button == winit::event::MouseButton::Left {
+            self.scrubbing = state == winit::event::ElementState::Pressed;
+        }
+    }
+
+    fn on_cursor_move(&mut self, position: (f64, f64)) {
+        if let Subject::Animation(ref mut anim) = self.subject {
+            if self.scrubbing {
+                anim.scrub(position.0 as f32 / self.screen_width);
+            }
+        }
+    }
+
     fn update(
         &mut self,
         _device: &wgpu::Device,
+        queue: &wgpu::Queue,
         delta: f32,
         _spawner: &LocalSpawner,
     ) -> Vec<wgpu::CommandBuffer> {
@@ -107,10 +209,27 @@ impl Application for ResourceView {
             self.transform = other.concat(&self.transform);
         }
 
+        if let Subject::Animation(ref mut anim) = self.subject {
+            if anim.playing {
+                anim.time += delta;
+            }
+            if let Some(ref target) = anim.morph_target {
+                if anim.morphed_time != Some(anim.time) {
+                    let blend = anim.blend();
+                    let mesh = &anim.raw.meshes[blend.current];
+                    let positions = anim.raw.interpolate_positions(&blend).unwrap();
+                    let normals = anim.raw.interpolate_normals(&blend).unwrap();
+                    model::write_morphed(target, mesh, &positions, &normals, queue);
+                    anim.morphed_time = Some(anim.time);
+                }
+            }
+        }
+
         Vec::new()
     }
 
     fn resize(&mut self, _device: &wgpu::Device, extent: wgpu::Extent3d) {
+        self.screen_width = extent.width as f32;
         self.cam
             .proj
             .update(extent.width as u16, extent.height as u16);
@@ -127,13 +246,28 @@ impl Application for ResourceView {
         _spawner: &LocalSpawner,
     ) -> wgpu::CommandBuffer {
         let mut batcher = render::Batcher::new();
-        batcher.add_model(
-            &self.model,
-            &self.transform,
-            None,
-            &render::body::GpuBody::ZERO,
-            render::object::BodyColor::Dummy,
-        );
+        match self.subject {
+            Subject::Model(ref model) => {
+                batcher.add_model(
+                    model,
+                    &self.transform,
+                    None,
+                    &render::body::GpuBody::ZERO,
+                    render::object::BodyColor::Dummy,
+                );
+            }
+            Subject::Animation(ref anim) => {
+                batcher.add_mesh(
+                    anim.current_mesh(),
+                    render::object::Instance::new(
+                        &self.transform,
+                        0.0,
+                        &render::body::GpuBody::ZERO,
+                        render::object::BodyColor::Dummy,
+                    ),
+                );
+            }
+        }
         batcher.prepare(device);
 
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -3,26 +3,39 @@ mod app;
 mod boilerplate;
 
 fn main() {
-    use std::env;
-
-    let (mut harness, settings) = boilerplate::Harness::init("model");
+    use std::{env, process};
 
     let args: Vec<_> = env::args().collect();
     let mut options = getopts::Options::new();
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
         .optflag("h", "help", "print this help menu");
+    boilerplate::headless_options(&mut options);
 
     let matches = options.parse(&args[1..]).unwrap();
+    let brief = format!("Usage: {} [options] <path_to_model>", args[0]);
     if matches.opt_present("h") || matches.free.len() != 1 {
         println!("Vangers model viewer");
-        let brief = format!("Usage: {} [options] <path_to_model>", args[0]);
         println!("{}", options.usage(&brief));
         return;
     }
 
     let path = &matches.free[0];
-    let app = app::ResourceView::new(path, &settings, &harness.device, &mut harness.queue);
-
-    harness.main_loop(app);
+    let headless = boilerplate::HeadlessConfig::from_matches(&matches).unwrap_or_else(|e| {
+        println!("{}", e);
+        println!("{}", options.usage(&brief));
+        process::exit(1)
+    });
+    match headless {
+        Some(config) => {
+            let (headless, settings) = boilerplate::Headless::init(&config);
+            let app = app::ResourceView::new(path, &settings, &headless.device, &headless.queue);
+            headless.run(app, &config);
+        }
+        None => {
+            let (harness, settings) = boilerplate::Harness::init("model");
+            let app = app::ResourceView::new(path, &settings, &harness.device, &harness.queue);
+            harness.main_loop(app);
+        }
+    }
 }
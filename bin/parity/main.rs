@@ -0,0 +1,304 @@
+use log::info;
+use vangers::{
+    config::{self, common::MAIN_LOOP_TIME, settings},
+    model, physics,
+    render::{
+        body::{encode_control, GpuStore, GpuStoreInit},
+        collision::GpuCollider,
+        request_device, Render,
+    },
+    scene, space,
+};
+
+use cgmath::prelude::*;
+use futures::executor::LocalPool;
+
+use std::{fs::File, io::Write as _, process};
+
+const MAX_QUANT: f32 = 0.1;
+const SHAPE_SAMPLING: u8 = 0;
+
+// A segment of the scripted driving: the control is held for a number of ticks.
+struct Phase {
+    name: &'static str,
+    ticks: usize,
+    control: physics::Control,
+}
+
+fn script() -> Vec<Phase> {
+    vec![
+        Phase {
+            name: "settle",
+            ticks: 20,
+            control: physics::Control::default(),
+        },
+        Phase {
+            name: "accelerate",
+            ticks: 60,
+            control: physics::Control {
+                motor: 1.0,
+                ..Default::default()
+            },
+        },
+        Phase {
+            name: "turn",
+            ticks: 40,
+            control: physics::Control {
+                motor: 1.0,
+                rudder: 1.0,
+                ..Default::default()
+            },
+        },
+        Phase {
+            name: "brake",
+            ticks: 40,
+            control: physics::Control {
+                brake: true,
+                ..Default::default()
+            },
+        },
+    ]
+}
+
+fn angle_between(a: &space::Transform, b: &space::Transform) -> cgmath::Deg<f32> {
+    let diff = a.rot.invert() * b.rot;
+    cgmath::Rad(2.0 * diff.s.abs().min(1.0).acos()).into()
+}
+
+fn main() {
+    use std::env;
+
+    env_logger::init();
+    let args: Vec<_> = env::args().collect();
+    let mut options = getopts::Options::new();
+    options
+        .optflag("h", "help", "print this help menu")
+        .optflag("", "software", "run on a software adapter, e.g. lavapipe")
+        .optopt(
+            "",
+            "common",
+            "common physics parameters (default: tests/data/common.prm)",
+            "FILE",
+        )
+        .optopt(
+            "",
+            "output",
+            "dump the trajectories into a CSV file (default: parity.csv)",
+            "FILE",
+        )
+        .optopt(
+            "",
+            "backend",
+            "graphics backend: Auto, Vulkan, Metal, DX12, DX11 (default: Vulkan)",
+            "NAME",
+        )
+        .optopt(
+            "",
+            "max-distance",
+            "allowed distance between the positions (default: 5)",
+            "UNITS",
+        )
+        .optopt(
+            "",
+            "max-angle",
+            "allowed difference of the orientations (default: 10)",
+            "DEGREES",
+        );
+
+    let matches = options.parse(&args[1..]).unwrap();
+    if matches.opt_present("h") || !matches.free.is_empty() {
+        println!("Vangers CPU/GPU physics parity check");
+        let brief = format!("Usage: {} [options]", args[0]);
+        println!("{}", options.usage(&brief));
+        return;
+    }
+    let common_path = matches
+        .opt_str("common")
+        .unwrap_or_else(|| "tests/data/common.prm".to_string());
+    let output_path = matches
+        .opt_str("output")
+        .unwrap_or_else(|| "parity.csv".to_string());
+    let backend: settings::Backend = ron::de::from_str(
+        &matches
+            .opt_str("backend")
+            .unwrap_or_else(|| "Vulkan".to_string()),
+    )
+    .expect("Unknown backend");
+    let software = matches.opt_present("software");
+    let max_distance = matches
+        .opt_get_default("max-distance", 5.0f32)
+        .expect("Invalid distance");
+    let max_angle = cgmath::Deg(
+        matches
+            .opt_get_default("max-angle", 10.0f32)
+            .expect("Invalid angle"),
+    );
+
+    let common = config::common::load(File::open(&common_path).expect("Unable to open common"));
+    let level = scene::make_level(|_, _| scene::GROUND);
+    let car = scene::make_car(SHAPE_SAMPLING);
+
+    let mut pool = LocalPool::new();
+    let instance = wgpu::Instance::new(backend.to_wgpu());
+    let (device, queue) = request_device(&mut pool, &instance, backend.to_wgpu(), None, software)
+        .expect("Unable to find an adapter, consider a software Vulkan implementation");
+
+    info!("Initializing the GPU store and collider");
+    let gpu_collision = settings::GpuCollision {
+        max_objects: 4,
+        max_polygons_total: 64,
+        max_raster_size: (100, 100),
+    };
+    let render_settings = settings::Render {
+        light: settings::Light {
+            pos: [1.0, 4.0, 4.0, 0.0],
+            color: [1.0; 4],
+            shadow: settings::Shadow {
+                size: 0,
+                terrain: settings::ShadowTerrain::RayTraced,
+            },
+        },
+        terrain: settings::Terrain::RayTraced,
+        fog: settings::Fog {
+            color: [0.0; 4],
+            depth: 50.0,
+        },
+        debug: settings::DebugRender {
+            max_vertices: 0,
+            collision_shapes: false,
+            collision_map: false,
+            impulses: false,
+        },
+    };
+    let extent = wgpu::Extent3d {
+        width: 64,
+        height: 64,
+        depth: 1,
+    };
+    let store_init = GpuStoreInit::new(&device, &gpu_collision);
+    let render = Render::new(
+        &device,
+        &queue,
+        &level,
+        &[[0; 4]; 0x100],
+        &render_settings,
+        extent,
+        store_init.resource(),
+    );
+    let mut collider = GpuCollider::new(
+        &device,
+        &gpu_collision,
+        &common,
+        &render.object,
+        &render.terrain,
+        store_init.resource(),
+    );
+    let mut store = GpuStore::new(&device, &common, store_init, collider.collision_buffer());
+    let shape = model::upload_shape(&car.model.shape, &device, &render.object);
+
+    let center = scene::LEVEL_SIZE / 2;
+    let mut cpu_body = physics::Body::spawn(&level, (center, center), cgmath::Rad(0.0), car.scale);
+    let gpu_body = store.alloc(&cpu_body.transform, &car.model, &car.physics);
+    let mut correction = physics::SpeedCorrection::new(MAIN_LOOP_TIME, &common.speed);
+
+    let mut output = File::create(&output_path).expect("Unable to create the output");
+    writeln!(
+        output,
+        "tick,phase,cpu_x,cpu_y,cpu_z,gpu_x,gpu_y,gpu_z,distance,angle,cpu_speed,gpu_speed"
+    )
+    .unwrap();
+
+    info!("Running the script");
+    let mut tick = 0;
+    let mut divergence = None;
+    for phase in script() {
+        for _ in 0..phase.ticks {
+            let frames = correction.update(MAIN_LOOP_TIME, &common.speed);
+            let dt = correction.time_delta(&common);
+
+            // CPU: the same as the game does for the agents around the player
+            cpu_body.apply_control(&phase.control, frames, &common);
+            let mut cpu_dt = dt;
+            while cpu_dt > MAX_QUANT {
+                cpu_body.step(MAX_QUANT, &car, &level, &common, &phase.control, None, None);
+                cpu_dt -= MAX_QUANT;
+            }
+            cpu_body.step(cpu_dt, &car, &level, &common, &phase.control, None, None);
+
+            // GPU: one submission per tick, followed by a blocking read-back
+            let mut prep_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Preparation"),
+            });
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Update"),
+            });
+            store.update_control(
+                &gpu_body,
+                encode_control(&phase.control, car.stats.max_fly as f32, &common),
+            );
+            store.update_entries(&device, &mut encoder);
+            let mut gpu_dt = dt;
+            loop {
+                let quant = gpu_dt.min(MAX_QUANT);
+                let mut session = collider.begin(&mut encoder, &render.terrain, &pool.spawner());
+                session.add(&shape, gpu_body.index());
+                let ranges = session.finish(&mut prep_encoder, &device);
+                gpu_dt -= quant;
+                let step_frames = if gpu_dt <= 0.0 { frames } else { 0.0 };
+                store.step(&device, &mut encoder, quant, step_frames, ranges);
+                if gpu_dt <= 0.0 {
+                    break;
+                }
+            }
+            store.produce_gpu_results(&device, &mut encoder);
+            queue.submit(vec![prep_encoder.finish(), encoder.finish()]);
+            store.consume_gpu_results(&pool.spawner());
+            device.poll(wgpu::Maintain::Wait);
+            pool.run_until_stalled();
+
+            let gpu_state = store
+                .cpu_mirror()
+                .get(&gpu_body)
+                .cloned()
+                .expect("No GPU results");
+            let cpu = &cpu_body.transform;
+            let gpu = &gpu_state.transform;
+            let distance = (cpu.disp - gpu.disp).magnitude();
+            let angle = angle_between(cpu, gpu);
+            writeln!(
+                output,
+                "{},{},{},{},{},{},{},{},{},{},{},{}",
+                tick,
+                phase.name,
+                cpu.disp.x,
+                cpu.disp.y,
+                cpu.disp.z,
+                gpu.disp.x,
+                gpu.disp.y,
+                gpu.disp.z,
+                distance,
+                angle.0,
+                cpu_body.dynamo.linear_velocity.magnitude(),
+                gpu_state.linear_velocity.magnitude(),
+            )
+            .unwrap();
+
+            if divergence.is_none() && (distance > max_distance || angle > max_angle) {
+                divergence = Some((tick, phase.name, distance, angle));
+            }
+            tick += 1;
+        }
+    }
+
+    info!("Trajectories are written to {}", output_path);
+    match divergence {
+        Some((tick, phase, distance, angle)) => {
+            println!(
+                "Diverged at tick {} ({}): distance {}, angle {:?}",
+                tick, phase, distance, angle
+            );
+            process::exit(1);
+        }
+        None => println!("Backends agree over {} ticks", tick),
+    }
+}
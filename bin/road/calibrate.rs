@@ -0,0 +1,58 @@
+use vangers::{
+    config::{self, common::MAIN_LOOP_TIME},
+    level, physics, replay,
+};
+
+use cgmath::prelude::*;
+
+// Time of driving at full throttle, in seconds.
+const DURATION: f32 = 30.0;
+
+// Drives every vehicle straight over a flat plain, the same way the game does,
+// and prints the top speeds next to the `max_speed` of the original stats.
+pub fn run(settings: &config::Settings) {
+    let game = config::game::Registry::load(settings);
+    let cars = config::car::load_registry(settings, &game);
+    let common = config::common::load_with(settings);
+
+    // plain ground everywhere, without the water bed
+    let mut level = level::Level::new_test();
+    let plain = level::TerrainBits::new(level.terrains.len() as u8).write(1);
+    for meta in level.meta.iter_mut() {
+        *meta = plain;
+    }
+
+    let input = replay::Input {
+        control: physics::Control {
+            motor: 1.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let max_quant = settings.game.physics.max_quant;
+    let mut names = cars.keys().collect::<Vec<_>>();
+    names.sort();
+
+    println!("{:20} {:>10} {:>10}", "Vehicle", "top speed", "max_speed");
+    for name in names {
+        let car = &cars[name];
+        let mut body = physics::Body::spawn(&level, (0, 0), cgmath::Rad(0.0), car.scale);
+        let mut correction = physics::SpeedCorrection::new(MAIN_LOOP_TIME, &common.speed);
+        let mut top_speed = 0f32;
+        for _ in 0..(DURATION / MAIN_LOOP_TIME) as usize {
+            let frames = correction.update(MAIN_LOOP_TIME, &common.speed);
+            let time = physics::TickTime {
+                frames,
+                dt: correction.time_delta(&common),
+                max_quant,
+            };
+            let focus_point = cgmath::Point3::from_vec(body.transform.disp);
+            body.tick(&time, car, &level, &common, &input, &focus_point);
+            top_speed = top_speed.max(body.dynamo.linear_velocity.y);
+        }
+        println!(
+            "{:20} {:>10.1} {:>10}",
+            name, top_speed, car.stats.max_speed
+        );
+    }
+}
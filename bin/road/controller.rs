@@ -0,0 +1,409 @@
+use vangers::{
+    ai,
+    config::{self, settings},
+    physics::Control,
+    replay, space,
+};
+
+use cgmath::prelude::*;
+use serde::Serialize;
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode as Key};
+
+use std::{
+    io::{BufRead as _, BufReader, Write as _},
+    mem, process,
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+// What a controller knows about its agent on every tick.
+pub struct Observation {
+    pub tick_time: f32,
+    // unknown for the GPU bodies until their state is read back
+    pub transform: Option<space::Transform>,
+    pub velocity: cgmath::Vector3<f32>,
+    pub armor: f32,
+    pub shield: f32,
+    // offsets to the nearby vehicles on the horizontal plane
+    pub neighbors: Vec<cgmath::Vector2<f32>>,
+}
+
+// Shared data about the world, which the controllers may consult.
+pub struct World<'a> {
+    pub nav: &'a ai::NavGrid,
+    pub common: &'a config::common::Common,
+}
+
+// Produces the input of an agent for every simulation tick.
+pub trait AgentController: Send + Sync {
+    fn on_key(&mut self, _input: &KeyboardInput) {}
+    fn control(&mut self, observation: &Observation, world: &World) -> replay::Input;
+}
+
+pub fn create(config: &settings::Controller, ai_profile: settings::Ai) -> Box<dyn AgentController> {
+    match *config {
+        settings::Controller::Keyboard => Box::new(Keyboard::default()),
+        settings::Controller::Ai => Box::new(BuiltinAi::new(ai_profile)),
+        settings::Controller::Replay { ref path, agent } => {
+            Box::new(ReplayDriver::new(path, agent))
+        }
+        settings::Controller::External {
+            ref command,
+            ref args,
+        } => Box::new(External::new(command, args)),
+    }
+}
+
+struct Roll {
+    dir: f32,
+    time: f32,
+}
+
+#[derive(Default)]
+pub struct Keyboard {
+    spin_hor: f32,
+    spin_ver: f32,
+    turbo: bool,
+    mole: bool,
+    fly: bool,
+    lift: f32,
+    // charged while the key is held
+    jump: Option<f32>,
+    released_jump: Option<f32>,
+    roll: Option<Roll>,
+    reset: bool,
+}
+
+impl AgentController for Keyboard {
+    fn on_key(&mut self, input: &KeyboardInput) {
+        match *input {
+            KeyboardInput {
+                state: ElementState::Pressed,
+                virtual_keycode: Some(key),
+                ..
+            } => match key {
+                Key::LShift => self.turbo = true,
+                Key::LAlt => self.jump = Some(0.0),
+                Key::M => self.mole = !self.mole,
+                Key::H => self.fly = !self.fly,
+                Key::Up => self.lift = 1.0,
+                Key::Down => self.lift = -1.0,
+                Key::W => self.spin_ver = 1.0,
+                Key::S => self.spin_ver = -1.0,
+                Key::R => self.reset = true,
+                Key::A => self.spin_hor = -1.0,
+                Key::D => self.spin_hor = 1.0,
+                Key::Q => {
+                    self.roll = Some(Roll {
+                        dir: -1.0,
+                        time: 0.0,
+                    })
+                }
+                Key::E => {
+                    self.roll = Some(Roll {
+                        dir: 1.0,
+                        time: 0.0,
+                    })
+                }
+                _ => (),
+            },
+            KeyboardInput {
+                state: ElementState::Released,
+                virtual_keycode: Some(key),
+                ..
+            } => match key {
+                Key::W | Key::S => self.spin_ver = 0.0,
+                Key::A | Key::D => self.spin_hor = 0.0,
+                Key::Q | Key::E => self.roll = None,
+                Key::Up | Key::Down => self.lift = 0.0,
+                Key::LShift => self.turbo = false,
+                Key::LAlt => self.released_jump = self.jump.take(),
+                _ => (),
+            },
+            _ => {}
+        }
+    }
+
+    fn control(&mut self, observation: &Observation, world: &World) -> replay::Input {
+        let common = world.common;
+        let frame_rate = common.speed.standard_frame_rate as f32;
+        if let Some(ref mut jump) = self.jump {
+            let power = observation.tick_time * frame_rate;
+            *jump = (*jump + power).min(common.force.max_jump_power);
+        }
+
+        let roll = match self.roll {
+            Some(ref mut roll) => {
+                let roll_count = (roll.time * frame_rate).min(100.0) as u8;
+                roll.time += observation.tick_time;
+                if roll_count > common.force.side_impulse_delay {
+                    roll.time = 0.0;
+                }
+                if roll_count < common.force.side_impulse_duration {
+                    roll.dir
+                } else {
+                    0.0
+                }
+            }
+            None => 0.0,
+        };
+
+        replay::Input {
+            control: Control {
+                rudder: self.spin_hor,
+                motor: self.spin_ver,
+                turbo: self.turbo,
+                mole: self.mole,
+                fly: self.fly,
+                lift: self.lift,
+                roll,
+                ..Control::default()
+            },
+            jump: self.released_jump.take(),
+            reset: mem::replace(&mut self.reset, false),
+        }
+    }
+}
+
+// Follows the routes on the navigation grid, avoiding the vehicles ahead.
+pub struct BuiltinAi {
+    pilot: ai::Pilot,
+    control: Control,
+    last_position: Option<cgmath::Vector3<f32>>,
+    roll_time: f32,
+}
+
+impl BuiltinAi {
+    pub fn new(profile: settings::Ai) -> Self {
+        BuiltinAi {
+            pilot: ai::Pilot::new(profile),
+            control: Control {
+                motor: 1.0,
+                ..Control::default()
+            },
+            last_position: None,
+            roll_time: 0.0,
+        }
+    }
+}
+
+impl AgentController for BuiltinAi {
+    fn control(&mut self, observation: &Observation, world: &World) -> replay::Input {
+        if let Some(ref transform) = observation.transform {
+            self.pilot.drive(world.nav, transform, &mut self.control);
+
+            // roll over when stuck
+            if self.roll_time > 0.0 {
+                self.roll_time -= observation.tick_time;
+                if self.roll_time <= 0.0 {
+                    self.control.roll = 0.0;
+                }
+            } else if self.last_position == Some(transform.disp) {
+                self.roll_time = 0.5;
+                let x_axis = transform.rot * cgmath::Vector3::unit_x();
+                self.control.roll = x_axis.z.signum();
+            }
+
+            // steer away from the closest vehicle ahead
+            let rot_inv = transform.rot.invert();
+            let ahead = observation
+                .neighbors
+                .iter()
+                .map(|offset| rot_inv * offset.extend(0.0))
+                .filter(|local| local.y > 0.0)
+                .min_by(|a, b| a.y.partial_cmp(&b.y).unwrap());
+            if let Some(local) = ahead {
+                self.control.rudder = -local.x.signum();
+            }
+
+            self.last_position = Some(transform.disp);
+        }
+
+        replay::Input {
+            control: self.control.clone(),
+            ..replay::Input::default()
+        }
+    }
+}
+
+// Repeats the inputs of one of the agents from a recorded session.
+pub struct ReplayDriver {
+    ticks: std::vec::IntoIter<replay::Tick>,
+    // slot of the agent, see `replay::Tick`
+    agent: usize,
+}
+
+impl ReplayDriver {
+    pub fn new(path: &std::path::Path, agent: usize) -> Self {
+        ReplayDriver {
+            ticks: replay::Replay::load(path).ticks.into_iter(),
+            agent,
+        }
+    }
+}
+
+impl AgentController for ReplayDriver {
+    fn control(&mut self, _observation: &Observation, _world: &World) -> replay::Input {
+        // stays idle after the recording is over
+        self.ticks
+            .next()
+            .and_then(|mut tick| {
+                if self.agent < tick.len() {
+                    Some(tick.swap_remove(self.agent))
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+// Observation as sent to the external controllers.
+#[derive(Serialize)]
+struct Message {
+    tick_time: f32,
+    position: Option<[f32; 3]>,
+    // quaternion, with the scalar part first
+    orientation: Option<[f32; 4]>,
+    velocity: [f32; 3],
+    armor: f32,
+    shield: f32,
+    neighbors: Vec<[f32; 2]>,
+}
+
+// How long the external controllers are waited for on every tick.
+const EXTERNAL_TIMEOUT: Duration = Duration::from_secs(1);
+
+// A separate process, getting one line with the observation per tick,
+// and answering with one line with the input, both in RON.
+// The answers are read on a separate thread, so that a stuck process
+// only leaves its agent without the input.
+pub struct External {
+    child: process::Child,
+    lines: Mutex<mpsc::Receiver<String>>,
+    // answers still expected for the ticks that timed out
+    late: usize,
+    exited: bool,
+}
+
+impl External {
+    pub fn new(command: &str, args: &[String]) -> Self {
+        log::info!("Starting the controller {} {:?}", command, args);
+        let mut child = process::Command::new(command)
+            .args(args)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| panic!("Unable to start the controller {}: {}", command, e));
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in output.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        log::error!("Unable to read the controller input: {}", e);
+                        break;
+                    }
+                };
+                // the agent is gone
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        External {
+            child,
+            lines: Mutex::new(receiver),
+            late: 0,
+            exited: false,
+        }
+    }
+
+    fn exit(&mut self, reason: &str) -> replay::Input {
+        log::error!("Controller has exited ({}), the agent is left idle", reason);
+        self.exited = true;
+        replay::Input::default()
+    }
+}
+
+impl AgentController for External {
+    fn control(&mut self, observation: &Observation, _world: &World) -> replay::Input {
+        if self.exited {
+            return replay::Input::default();
+        }
+        let message = Message {
+            tick_time: observation.tick_time,
+            position: observation.transform.map(|t| t.disp.into()),
+            orientation: observation
+                .transform
+                .map(|t| [t.rot.s, t.rot.v.x, t.rot.v.y, t.rot.v.z]),
+            velocity: observation.velocity.into(),
+            armor: observation.armor,
+            shield: observation.shield,
+            neighbors: observation.neighbors.iter().map(|&n| n.into()).collect(),
+        };
+        self.exchange(&message)
+    }
+}
+
+impl External {
+    // Sends the observation and waits for the answer to it.
+    fn exchange(&mut self, message: &Message) -> replay::Input {
+        let input = self.child.stdin.as_mut().unwrap();
+        if let Err(e) = writeln!(input, "{}", ron::ser::to_string(message).unwrap())
+            .and_then(|()| input.flush())
+        {
+            return self.exit(&e.to_string());
+        }
+
+        let deadline = Instant::now() + EXTERNAL_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let result = self.lines.lock().unwrap().recv_timeout(timeout);
+            match result {
+                // an answer to one of the ticks that timed out
+                Ok(_) if self.late != 0 => self.late -= 1,
+                Ok(line) => {
+                    return ron::de::from_str(&line).unwrap_or_else(|e| {
+                        log::warn!("Invalid controller input {:?}: {}", line, e);
+                        replay::Input::default()
+                    })
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    log::warn!("Controller didn't answer in {:?}", EXTERNAL_TIMEOUT);
+                    self.late += 1;
+                    return replay::Input::default();
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return self.exit("no more input"),
+            }
+        }
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn external_invalid_input() {
+    let script = "read line; echo garbage; read line; echo '(control: (motor: 1.0), reset: false)'";
+    let mut external = External::new("sh", &["-c".to_string(), script.to_string()]);
+    let message = Message {
+        tick_time: 0.0,
+        position: None,
+        orientation: None,
+        velocity: [0.0; 3],
+        armor: 1.0,
+        shield: 0.0,
+        neighbors: Vec::new(),
+    };
+    // the garbage is skipped, and the controller keeps going
+    assert_eq!(external.exchange(&message), replay::Input::default());
+    assert_eq!(external.exchange(&message).control.motor, 1.0);
+    assert!(!external.exited);
+}
@@ -1,70 +1,77 @@
-use crate::{boilerplate::Application, physics};
+use crate::{
+    boilerplate::Application,
+    controller::{self, AgentController},
+};
 use m3d::Mesh;
 use vangers::{
-    config, level, model,
+    ai, config,
+    damage::Health,
+    freelist::{self, FreeList},
+    level, model,
+    physics::{self, Control},
     render::{
-        body::{GpuBody, GpuStore, GpuStoreInit},
+        body::{encode_control, GpuBody, GpuStore, GpuStoreInit, GpuStoreMirror},
         collision::{GpuCollider, GpuEpoch},
         debug::LineBuffer,
         object::BodyColor,
         Batcher, Render, ScreenTargets,
     },
-    space,
+    replay, space,
 };
 
 use cgmath::prelude::*;
 use futures::executor::LocalSpawner;
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
 
-#[derive(Debug, PartialEq)]
-struct Ai {
-    last_transform: space::Transform,
-    roll_time: f32,
-}
+// Agents further away from the player only get a single physics step per tick.
+const FULL_SIMULATION_DISTANCE: f32 = 1000.0;
+// Prevents the simulation from spiraling after a long frame.
+const MAX_TICKS_PER_FRAME: usize = 10;
+// How long the pieces of a destroyed vehicle stay around, in seconds.
+const DEBRIS_LIFETIME: f32 = 10.0;
+// New NPCs appear at this range of distances from the player.
+const RESPAWN_DISTANCE: (f32, f32) = (400.0, 800.0);
+// Size of the cells of the grid used to find the neighbors.
+const GRID_CELL_SIZE: f32 = 256.0;
+// Vehicles within this distance are reported to the controllers.
+const NEIGHBOR_DISTANCE: f32 = 100.0;
+// Size of the cells of the grid used to plan the routes.
+const NAV_CELL_SIZE: i32 = 64;
 
 #[derive(Debug, PartialEq)]
 enum Spirit {
     Player,
-    Other(Ai),
-}
-
-#[derive(Clone, Debug, Default, PartialEq)]
-struct Control {
-    motor: f32,
-    rudder: f32,
-    roll: f32,
-    brake: bool,
-    turbo: bool,
+    Other,
+    // remaining lifetime, in seconds
+    Debris { time: f32 },
 }
 
 enum Physics {
-    Cpu {
-        transform: space::Transform,
-        dynamo: physics::Dynamo,
-    },
+    Cpu(physics::Body),
     Gpu {
         body: GpuBody,
         collision_epochs: HashMap<GpuEpoch, usize>,
-        last_control: Control,
     },
 }
 
-enum SimulationStep<'a> {
-    Intermediate,
-    Final {
-        focus_point: &'a cgmath::Point3<f32>,
-        line_buffer: Option<&'a mut LineBuffer>,
-    },
-}
+type AgentId = freelist::Id<Agent>;
 
 pub struct Agent {
     _name: String,
     spirit: Spirit,
     car: config::car::CarInfo,
+    model: model::VisualModel,
     color: BodyColor,
+    // debris is not controlled
+    controller: Option<Box<dyn AgentController>>,
     control: Control,
     jump: Option<f32>,
+    reset: bool,
+    health: Health,
     physics: Physics,
 }
 
@@ -72,152 +79,292 @@ impl Agent {
     fn spawn(
         name: String,
         car: &config::car::CarInfo,
+        model: &model::VisualModel,
         color: BodyColor,
         coords: (i32, i32),
         orientation: cgmath::Rad<f32>,
+        controller: Box<dyn AgentController>,
         level: &level::Level,
         gpu_store: Option<&mut GpuStore>,
     ) -> Self {
-        let height = physics::get_height(level.get(coords).top()) + 5.; //center offset
-        let transform = cgmath::Decomposed {
-            scale: car.scale,
-            disp: cgmath::vec3(coords.0 as f32, coords.1 as f32, height),
-            rot: cgmath::Quaternion::from_angle_z(orientation),
-        };
+        let body = physics::Body::spawn(level, coords, orientation, car.scale);
+        let transform = body.transform;
 
         Agent {
             _name: name,
-            spirit: Spirit::Other(Ai {
-                last_transform: transform,
-                roll_time: 0.0,
-            }),
+            spirit: Spirit::Other,
+            controller: Some(controller),
             car: car.clone(),
+            model: model.clone(),
             color,
             control: Control::default(),
             jump: None,
+            reset: false,
+            health: Health::new(car),
             physics: match gpu_store {
                 Some(store) => Physics::Gpu {
                     body: store.alloc(&transform, &car.model, &car.physics),
                     collision_epochs: HashMap::default(),
-                    last_control: Control::default(),
-                },
-                None => Physics::Cpu {
-                    transform,
-                    dynamo: physics::Dynamo::default(),
                 },
+                None => Physics::Cpu(body),
             },
         }
     }
 
-    fn cpu_apply_control(&mut self, dt: f32, common: &config::common::Common) {
-        let dynamo = match self.physics {
-            Physics::Cpu { ref mut dynamo, .. } => dynamo,
-            Physics::Gpu { .. } => return,
-        };
-        if self.control.rudder != 0.0 {
-            let angle = dynamo.rudder.0 + common.car.rudder_step * 2.0 * dt * self.control.rudder;
-            dynamo.rudder.0 = angle.min(common.car.rudder_max).max(-common.car.rudder_max);
-        }
-        if self.control.motor != 0.0 {
-            dynamo.change_traction(self.control.motor * dt * common.car.traction_incr);
-        }
-        if self.control.brake && dynamo.traction != 0.0 {
-            dynamo.traction *= (-dt).exp2();
+    fn cpu_tick(
+        &mut self,
+        time: &physics::TickTime,
+        level: &level::Level,
+        common: &config::common::Common,
+        focus_point: &cgmath::Point3<f32>,
+    ) {
+        let input = self.input();
+        self.jump = None;
+        self.reset = false;
+        if let Physics::Cpu(ref mut body) = self.physics {
+            body.tick(time, &self.car, level, common, &input, focus_point);
         }
     }
 
+    // A single physics step outside of the regular ticks, for debugging.
     fn cpu_step(
         &mut self,
         dt: f32,
         level: &level::Level,
         common: &config::common::Common,
-        sim_step: SimulationStep,
+        focus_point: &cgmath::Point3<f32>,
+        line_buffer: &mut LineBuffer,
     ) {
-        let (dynamo, transform) = match self.physics {
-            Physics::Cpu {
-                ref mut transform,
-                ref mut dynamo,
-            } => (dynamo, transform),
-            Physics::Gpu { .. } => return,
-        };
-        let (jump, roll, focus_point, line_buffer) = match sim_step {
-            SimulationStep::Intermediate => (None, 0.0, None, None),
-            SimulationStep::Final {
-                focus_point,
-                line_buffer,
-            } => (
-                self.jump.take(),
-                self.control.roll,
-                Some(*focus_point),
-                line_buffer,
-            ),
-        };
-        physics::step(
-            dynamo,
-            transform,
-            dt,
-            &self.car,
-            level,
-            common,
-            if self.control.turbo {
-                common.global.k_traction_turbo
-            } else {
-                1.0
-            },
-            if self.control.brake {
-                common.global.f_brake_max
-            } else {
-                0.0
-            },
-            jump,
-            roll,
-            line_buffer,
-        );
+        if let Physics::Cpu(ref mut body) = self.physics {
+            let jump = self.jump.take();
+            if std::mem::replace(&mut self.reset, false) {
+                body.reset();
+            }
+            body.step(
+                dt,
+                &self.car,
+                level,
+                common,
+                &self.control,
+                jump,
+                Some(line_buffer),
+            );
+            body.wrap_around(level, focus_point);
+        }
+    }
 
-        if let Some(focus) = focus_point {
-            let wrap = cgmath::vec2(level.size.0 as f32, (level.size.1 >> 1) as f32);
-            let offset = cgmath::Point3::from_vec(transform.disp) - focus;
-            transform.disp = focus.to_vec()
-                + cgmath::vec3(
-                    (offset.x + 0.5 * wrap.x).rem_euclid(wrap.x) - 0.5 * wrap.x,
-                    (offset.y + 0.5 * wrap.y).rem_euclid(wrap.y) - 0.5 * wrap.y,
-                    offset.z,
-                );
+    // The GPU collisions are resolved in the shaders, and the hits are not
+    // read back, so only the CPU bodies take damage.
+    fn cpu_hit(&mut self, hit: &physics::collision::Hit, common: &config::common::Common) {
+        if let Physics::Cpu(ref mut body) = self.physics {
+            if hit.strong {
+                log::debug!("Strong hit on the {:?} side", hit.side);
+            }
+            body.apply_hit(&self.car, hit);
+            let damage = Health::hit_damage(hit, &self.car, common);
+            self.health.take(damage, &self.car);
         }
     }
 
-    fn ai_behavior(&mut self, delta: f32) {
-        let ai = match self.spirit {
-            Spirit::Player => return,
-            Spirit::Other(ref mut ai) => ai,
+    // Breaks the vehicle into pieces, each becoming a separate agent.
+    fn cpu_explode(&self, common: &config::common::Common) -> Vec<Agent> {
+        let body = match self.physics {
+            Physics::Cpu(ref body) => body,
+            Physics::Gpu { .. } => return Vec::new(),
         };
-        self.control.motor = 1.0; //full on
+        body.explode(&self.car, common)
+            .into_iter()
+            .zip(self.car.debris())
+            .enumerate()
+            .map(|(i, (body, car))| Agent {
+                _name: format!("{}-debris{}", self._name, i),
+                controller: None,
+                spirit: Spirit::Debris {
+                    time: DEBRIS_LIFETIME,
+                },
+                health: Health::new(&car),
+                car,
+                model: self.model.debrie(i),
+                color: self.color,
+                control: Control::default(),
+                jump: None,
+                reset: false,
+                physics: Physics::Cpu(body),
+            })
+            .collect()
+    }
+
+    fn input(&self) -> replay::Input {
+        replay::Input {
+            control: self.control.clone(),
+            jump: self.jump,
+            reset: self.reset,
+        }
+    }
 
-        let transform = match self.physics {
-            Physics::Cpu { ref transform, .. } => transform,
-            Physics::Gpu { .. } => return, //TODO
+    // GPU bodies are only known once their state is read back.
+    fn transform(&self, mirror: Option<&GpuStoreMirror>) -> Option<space::Transform> {
+        match self.physics {
+            Physics::Cpu(ref body) => Some(body.transform),
+            Physics::Gpu { ref body, .. } => mirror?.get(body).map(|state| state.transform),
+        }
+    }
+
+    fn observe(
+        &self,
+        id: AgentId,
+        tick_time: f32,
+        mirror: Option<&GpuStoreMirror>,
+        grid: &space::SpatialGrid<AgentId>,
+    ) -> controller::Observation {
+        let transform = self.transform(mirror);
+        let velocity = match self.physics {
+            Physics::Cpu(ref body) => Some(body.dynamo.linear_velocity),
+            Physics::Gpu { ref body, .. } => mirror
+                .and_then(|mirror| mirror.get(body))
+                .map(|state| state.linear_velocity),
+        };
+        let neighbors = match transform {
+            Some(ref transform) => {
+                let pos = transform.disp.truncate();
+                grid.query_radius(pos, NEIGHBOR_DISTANCE)
+                    .into_iter()
+                    .filter(|entry| entry.item != id)
+                    .map(|entry| grid.offset(pos, entry.center))
+                    .collect()
+            }
+            None => Vec::new(),
         };
+        controller::Observation {
+            tick_time,
+            transform,
+            velocity: velocity.unwrap_or_else(cgmath::Vector3::zero),
+            armor: self.health.armor,
+            shield: self.health.shield,
+            neighbors,
+        }
+    }
+
+    fn position(&self, mirror: Option<&GpuStoreMirror>) -> cgmath::Vector3<f32> {
+        self.transform(mirror)
+            .map_or(cgmath::Vector3::zero(), |transform| transform.disp)
+    }
 
-        if ai.roll_time > 0.0 {
-            ai.roll_time -= delta;
-            if ai.roll_time <= 0.0 {
-                self.control.roll = 0.0;
+    // Releases the GPU body, so that its slot can be reused.
+    fn despawn(self, gpu_store: Option<&mut GpuStore>) {
+        log::debug!("Despawning {}", self._name);
+        if let Physics::Gpu { body, .. } = self.physics {
+            gpu_store.unwrap().free(body);
+        }
+    }
+}
+
+// Collisions are detected in parallel, while the agents are only read,
+// and then applied sequentially in a fixed order.
+// The candidate pairs come from the grid, which has to be up to date.
+fn cpu_collide(
+    agents: &mut FreeList<Agent>,
+    grid: &space::SpatialGrid<AgentId>,
+    common: &config::common::Common,
+) {
+    use rayon::prelude::*;
+
+    let mut pairs = Vec::new();
+    for (id, agent) in agents.iter() {
+        if let Physics::Cpu(ref body) = agent.physics {
+            let sphere = body.bounding_sphere(&agent.car);
+            for entry in grid.query_radius(sphere.center.truncate(), sphere.radius) {
+                if entry.item.index() > id.index() {
+                    pairs.push((id, entry.item));
+                }
             }
-        } else if ai.last_transform.disp == transform.disp {
-            ai.roll_time = 0.5;
-            let x_axis = transform.rot * cgmath::Vector3::unit_x();
-            self.control.roll = x_axis.z.signum();
         }
+    }
+
+    let hits = {
+        let agents = &*agents;
+        pairs
+            .par_iter()
+            .filter_map(|&(id_a, id_b)| {
+                let (a, b) = (&agents[id_a], &agents[id_b]);
+                match (&a.physics, &b.physics) {
+                    (&Physics::Cpu(ref body_a), &Physics::Cpu(ref body_b)) => {
+                        let (sa, sb) = (
+                            body_a.bounding_sphere(&a.car),
+                            body_b.bounding_sphere(&b.car),
+                        );
+                        // the level wraps around, so the nearest copy of `b` is taken
+                        let offset = grid
+                            .offset(sa.center.truncate(), sb.center.truncate())
+                            .extend(sb.center.z - sa.center.z);
+                        if offset.magnitude2() >= (sa.radius + sb.radius).powi(2) {
+                            return None;
+                        }
+                        let shift = sa.center + offset - sb.center;
+                        let mut near_b;
+                        let body_b = if shift == cgmath::Vector3::zero() {
+                            body_b
+                        } else {
+                            near_b = body_b.clone();
+                            near_b.transform.disp += shift;
+                            &near_b
+                        };
+                        let (hit_a, mut hit_b) =
+                            physics::collision::collide(body_a, &a.car, body_b, &b.car, common)?;
+                        hit_b.point -= shift;
+                        Some((id_a, id_b, (hit_a, hit_b)))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>()
+    };
 
-        ai.last_transform = *transform;
+    for (i, j, (hit_a, hit_b)) in hits {
+        agents[i].cpu_hit(&hit_a, common);
+        agents[j].cpu_hit(&hit_b, common);
     }
+}
 
-    fn position(&self) -> cgmath::Vector3<f32> {
-        match self.physics {
-            Physics::Cpu { ref transform, .. } => transform.disp,
-            Physics::Gpu { .. } => cgmath::Vector3::zero(), //TODO
+// Regenerates the shields, replaces the destroyed vehicles with their debris,
+// and removes the debris that has been lying around for too long.
+// Only the CPU agents are ever destroyed, see `Agent::cpu_hit`.
+fn cpu_update_health(
+    agents: &mut FreeList<Agent>,
+    tick_time: f32,
+    common: &config::common::Common,
+) {
+    let frames = tick_time * common.speed.standard_frame_rate as f32;
+    let mut debris = Vec::new();
+    let mut removed = Vec::new();
+    for (id, agent) in agents.iter_mut() {
+        agent.health.update(frames, &agent.car);
+        match agent.spirit {
+            Spirit::Debris { ref mut time } => {
+                *time -= tick_time;
+                if *time <= 0.0 {
+                    removed.push(id);
+                }
+            }
+            _ if agent.health.is_destroyed() => {
+                log::info!("Agent {} is destroyed", agent._name);
+                debris.extend(agent.cpu_explode(common));
+                if agent.spirit == Spirit::Player {
+                    agent.health = Health::new(&agent.car);
+                    agent.reset = true;
+                } else {
+                    removed.push(id);
+                }
+            }
+            _ => {}
         }
     }
+    for id in removed {
+        agents.remove(id);
+    }
+    for agent in debris {
+        agents.insert(agent);
+    }
 }
 
 struct DataBase {
@@ -228,6 +375,54 @@ struct DataBase {
     game: config::game::Registry,
 }
 
+// NPCs get a random vehicle and color. The names are sorted,
+// so that the same seed produces the same vehicles.
+fn spawn_other(
+    name: String,
+    coords: (i32, i32),
+    controller: Box<dyn AgentController>,
+    rng: &mut rand::rngs::StdRng,
+    db: &DataBase,
+    car_models: &HashMap<String, model::VisualModel>,
+    level: &level::Level,
+    gpu_store: Option<&mut GpuStore>,
+) -> Agent {
+    use rand::{prelude::SliceRandom, Rng};
+
+    let color = match rng.gen_range(0, 3) {
+        0 => BodyColor::Green,
+        1 => BodyColor::Red,
+        2 => BodyColor::Blue,
+        _ => unreachable!(),
+    };
+    let mut car_names = db.cars.keys().collect::<Vec<_>>();
+    car_names.sort();
+    let car_id = *car_names.choose(rng).unwrap();
+    Agent::spawn(
+        name,
+        &db.cars[car_id],
+        &car_models[car_id],
+        color,
+        coords,
+        rng.gen(),
+        controller,
+        level,
+        gpu_store,
+    )
+}
+
+// NPCs get the controllers and the AI profiles from the settings in turn.
+fn other_controller(
+    controllers: &[config::settings::Controller],
+    ai_profiles: &[config::settings::Ai],
+    index: usize,
+) -> Box<dyn AgentController> {
+    controller::create(
+        &config::settings::Controller::pick(controllers, index),
+        config::settings::Ai::pick(ai_profiles, index),
+    )
+}
+
 struct Gpu {
     store: GpuStore,
     collider: GpuCollider,
@@ -283,9 +478,25 @@ impl Clipper {
     }
 }
 
-struct Roll {
-    dir: f32,
-    time: f32,
+// Splits the variable frame time into fixed simulation ticks.
+struct Clock {
+    tick_time: f32,
+    accumulated: f32,
+}
+
+impl Clock {
+    fn advance(&mut self, delta: f32) -> usize {
+        self.accumulated += delta;
+        let count = (self.accumulated / self.tick_time) as usize;
+        if count > MAX_TICKS_PER_FRAME {
+            log::warn!("Skipping {} simulation ticks", count - MAX_TICKS_PER_FRAME);
+            self.accumulated = 0.0;
+            MAX_TICKS_PER_FRAME
+        } else {
+            self.accumulated -= count as f32 * self.tick_time;
+            count
+        }
+    }
 }
 
 pub struct Game {
@@ -296,17 +507,30 @@ pub struct Game {
     //debug_collision_map: bool,
     line_buffer: LineBuffer,
     level: level::Level,
-    agents: Vec<Agent>,
+    nav: ai::NavGrid,
+    agents: FreeList<Agent>,
+    player: AgentId,
+    grid: space::SpatialGrid<AgentId>,
+    car_models: HashMap<String, model::VisualModel>,
+    rng: rand::rngs::StdRng,
+    other_count: usize,
+    spawned_count: usize,
+    despawn_distance: Option<f32>,
+    ai_profiles: Vec<config::settings::Ai>,
+    controllers: Vec<config::settings::Controller>,
     cam: space::Camera,
     cam_style: CameraStyle,
     max_quant: f32,
+    // rotation of the camera while paused
     spin_hor: f32,
     spin_ver: f32,
-    turbo: bool,
-    jump: Option<f32>,
-    roll: Option<Roll>,
     is_paused: bool,
     tick: Option<f32>,
+    clock: Clock,
+    speed_correction: physics::SpeedCorrection,
+    header: replay::Header,
+    playback: Option<std::vec::IntoIter<replay::Tick>>,
+    recorder: Option<replay::Recorder>,
 }
 
 impl Game {
@@ -336,7 +560,10 @@ impl Game {
                     worlds.keys().collect::<Vec<_>>()
                 ),
             };
-            let ini_path = settings.data_path.join(ini_name);
+            let ini_path = match settings.resolve_path(ini_name) {
+                Ok(path) => path,
+                Err(e) => panic!("Unable to find level '{}': {}", settings.game.level, e),
+            };
             log::info!("Using level {}", ini_name);
 
             let config = level::LevelConfig::load(&ini_path);
@@ -367,8 +594,8 @@ impl Game {
             let game = config::game::Registry::load(settings);
             DataBase {
                 _bunches: config::bunches::load(settings.open_relative("bunches.prm")),
-                cars: config::car::load_registry(settings, &game, device, &render.object),
-                common: config::common::load(settings.open_relative("common.prm")),
+                cars: config::car::load_registry(settings, &game),
+                common: config::common::load_with(settings),
                 _escaves: config::escaves::load(settings.open_relative("escaves.prm")),
                 game,
             }
@@ -388,6 +615,16 @@ impl Game {
             Gpu { store, collider }
         });
 
+        log::info!("Uploading car models");
+        let car_models = db
+            .cars
+            .iter()
+            .map(|(name, car)| {
+                let model = model::upload_model(&car.model, device, &render.object);
+                (name.clone(), model)
+            })
+            .collect::<HashMap<_, _>>();
+
         log::info!("Spawning agents");
         let car_names = db.cars.keys().cloned().collect::<Vec<_>>();
         let mut player_agent = Agent::spawn(
@@ -399,15 +636,16 @@ impl Game {
                     settings.car.id, car_names
                 ),
             },
+            &car_models[&settings.car.id],
             settings.car.color,
             coords,
             cgmath::Rad::turn_div_2(),
+            controller::create(&settings.car.controller, config::settings::Ai::default()),
             &level,
             gpu.as_mut().map(|Gpu { ref mut store, .. }| store),
         );
         player_agent.spirit = Spirit::Player;
         for (ms, sid) in player_agent
-            .car
             .model
             .slots
             .iter_mut()
@@ -419,18 +657,17 @@ impl Game {
             ms.scale = info.scale;
         }
 
-        let mut agents = vec![player_agent];
-        let mut rng = rand::thread_rng();
+        let mut agents = FreeList::new();
+        let player = agents.insert(player_agent);
+        let seed = settings.game.seed.unwrap_or_else(rand::random);
+        log::info!("Using random seed {}", seed);
+        let mut rng = {
+            use rand::SeedableRng;
+            rand::rngs::StdRng::seed_from_u64(seed)
+        };
         // populate with random agents
         for i in 0..settings.game.other.count {
-            use rand::{prelude::SliceRandom, Rng};
-            let color = match rng.gen_range(0, 3) {
-                0 => BodyColor::Green,
-                1 => BodyColor::Red,
-                2 => BodyColor::Blue,
-                _ => unreachable!(),
-            };
-            let car_id = car_names.choose(&mut rng).unwrap();
+            use rand::Rng;
             let (x, y) = match settings.game.other.spawn_at {
                 config::settings::SpawnAt::Player => coords,
                 config::settings::SpawnAt::Random => (
@@ -438,18 +675,28 @@ impl Game {
                     rng.gen_range(0, level.size.1),
                 ),
             };
-            let agent = Agent::spawn(
+            let agent = spawn_other(
                 format!("Other-{}", i),
-                &db.cars[car_id],
-                color,
                 (x, y),
-                rng.gen(),
+                other_controller(&settings.game.other.controllers, &settings.game.other.ai, i),
+                &mut rng,
+                &db,
+                &car_models,
                 &level,
                 gpu.as_mut().map(|Gpu { ref mut store, .. }| store),
             );
-            agents.push(agent);
+            agents.insert(agent);
         }
 
+        let speed_correction =
+            physics::SpeedCorrection::new(config::common::MAIN_LOOP_TIME, &db.common.speed);
+        let grid = space::SpatialGrid::new(
+            cgmath::vec2(level.size.0 as f32, level.size.1 as f32),
+            GRID_CELL_SIZE,
+        );
+        log::info!("Building the navigation grid");
+        let nav = ai::NavGrid::new(&level, NAV_CELL_SIZE);
+
         Game {
             db,
             render,
@@ -457,7 +704,17 @@ impl Game {
             gpu,
             line_buffer: LineBuffer::new(),
             level,
+            nav,
+            grid,
             agents,
+            player,
+            car_models,
+            rng,
+            other_count: settings.game.other.count,
+            spawned_count: settings.game.other.count,
+            despawn_distance: settings.game.other.despawn_distance,
+            ai_profiles: settings.game.other.ai.clone(),
+            controllers: settings.game.other.controllers.clone(),
             cam: space::Camera {
                 loc: cgmath::vec3(coords.0 as f32, coords.1 as f32, 200.0),
                 rot: cgmath::Quaternion::new(0.0, 0.0, 1.0, 0.0),
@@ -483,11 +740,161 @@ impl Game {
             //debug_collision_map: settings.render.debug.collision_map,
             spin_hor: 0.0,
             spin_ver: 0.0,
-            turbo: false,
-            jump: None,
-            roll: None,
             is_paused: false,
             tick: None,
+            clock: Clock {
+                tick_time: config::common::MAIN_LOOP_TIME,
+                accumulated: 0.0,
+            },
+            speed_correction,
+            header: replay::Header::new(settings, seed),
+            playback: None,
+            recorder: None,
+        }
+    }
+
+    pub fn record(&mut self, path: &Path) {
+        log::info!("Recording the session into {:?}", path);
+        self.recorder = Some(replay::Recorder::new(path, &self.header));
+    }
+
+    pub fn play(&mut self, replay: replay::Replay) {
+        assert_eq!(self.header, replay.header, "Replay doesn't match the game");
+        self.playback = Some(replay.ticks.into_iter());
+    }
+
+    // Removes the NPCs that wandered too far from the player,
+    // and tops up the destroyed and removed ones with new NPCs around it.
+    fn update_population(&mut self) {
+        use rand::Rng;
+
+        let (player_pos, far) = {
+            let mirror = self.gpu.as_ref().map(|gpu| gpu.store.cpu_mirror());
+            let player_pos = match self.agents[self.player].transform(mirror.as_deref()) {
+                Some(transform) => transform.disp,
+                None => return,
+            };
+            let level = &self.level;
+            let far = self
+                .agents
+                .iter()
+                .filter(|(_, agent)| match (&agent.spirit, self.despawn_distance) {
+                    (&Spirit::Other, Some(max_distance)) => agent
+                        .transform(mirror.as_deref())
+                        .map_or(false, |transform| {
+                            let offset = (transform.disp - player_pos).truncate();
+                            level.wrap_offset(offset).magnitude() > max_distance
+                        }),
+                    _ => false,
+                })
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            (player_pos, far)
+        };
+
+        let mut gpu_store = self.gpu.as_mut().map(|gpu| &mut gpu.store);
+        for id in far {
+            let agent = self.agents.remove(id);
+            agent.despawn(gpu_store.as_mut().map(|store| &mut **store));
+        }
+
+        let mut count = self
+            .agents
+            .values()
+            .filter(|a| match a.spirit {
+                Spirit::Other => true,
+                _ => false,
+            })
+            .count();
+        let max_distance = match self.despawn_distance {
+            Some(distance) => RESPAWN_DISTANCE.1.min(0.9 * distance),
+            None => RESPAWN_DISTANCE.1,
+        };
+        while count < self.other_count {
+            let angle: cgmath::Rad<f32> = self.rng.gen();
+            let distance = self
+                .rng
+                .gen_range(RESPAWN_DISTANCE.0.min(0.5 * max_distance), max_distance);
+            let coords = (
+                (player_pos.x + distance * angle.cos()) as i32,
+                (player_pos.y + distance * angle.sin()) as i32,
+            );
+            let agent = spawn_other(
+                format!("Other-{}", self.spawned_count),
+                coords,
+                other_controller(&self.controllers, &self.ai_profiles, self.spawned_count),
+                &mut self.rng,
+                &self.db,
+                &self.car_models,
+                &self.level,
+                gpu_store.as_mut().map(|store| &mut **store),
+            );
+            log::debug!("Spawning {} at {:?}", agent._name, coords);
+            self.agents.insert(agent);
+            self.spawned_count += 1;
+            count += 1;
+        }
+    }
+
+    // Places the agents with the known positions into the grid.
+    fn update_grid(&mut self) {
+        let mirror = self.gpu.as_ref().map(|gpu| gpu.store.cpu_mirror());
+        self.grid.clear();
+        for (id, agent) in self.agents.iter() {
+            if let Some(transform) = agent.transform(mirror.as_deref()) {
+                let radius = agent.car.model.body.bbox.radius * transform.scale;
+                self.grid.insert(id, transform.disp.truncate(), radius);
+            }
+        }
+    }
+
+    // Gathers the inputs of all the agents for the next tick,
+    // either from their controllers, or from the replay.
+    fn prepare_tick(&mut self, tick_time: f32) {
+        let tick = self.playback.as_mut().and_then(|ticks| ticks.next());
+        match tick {
+            Some(tick) => {
+                for (id, agent) in self.agents.iter_mut() {
+                    let input = tick.get(id.index()).cloned().unwrap_or_default();
+                    agent.control = input.control;
+                    agent.jump = input.jump;
+                    agent.reset = input.reset;
+                }
+            }
+            None => {
+                if self.playback.take().is_some() {
+                    log::info!("Replay is finished, switching to live input");
+                }
+                let mirror = self.gpu.as_ref().map(|gpu| gpu.store.cpu_mirror());
+                let world = controller::World {
+                    nav: &self.nav,
+                    common: &self.db.common,
+                };
+                for (id, agent) in self.agents.iter_mut() {
+                    if agent.controller.is_none() {
+                        continue;
+                    }
+                    let observation = agent.observe(id, tick_time, mirror.as_deref(), &self.grid);
+                    let input = agent
+                        .controller
+                        .as_mut()
+                        .unwrap()
+                        .control(&observation, &world);
+                    agent.control = input.control;
+                    if input.jump.is_some() {
+                        agent.jump = input.jump;
+                    }
+                    agent.reset |= input.reset;
+                }
+            }
+        }
+
+        if let Some(ref mut recorder) = self.recorder {
+            let mut tick = vec![replay::Input::default(); self.agents.slot_count()];
+            for (id, agent) in self.agents.iter() {
+                tick[id.index()] = agent.input();
+            }
+            recorder.record(&tick);
         }
     }
 
@@ -502,11 +909,6 @@ impl Application for Game {
     fn on_key(&mut self, input: winit::event::KeyboardInput) -> bool {
         use winit::event::{ElementState, KeyboardInput, VirtualKeyCode as Key};
 
-        let player = match self.agents.iter_mut().find(|a| a.spirit == Spirit::Player) {
-            Some(agent) => agent,
-            None => return false,
-        };
-
         match input {
             KeyboardInput {
                 state: ElementState::Pressed,
@@ -515,18 +917,10 @@ impl Application for Game {
             } => match key {
                 Key::Escape => return false,
                 Key::P => {
-                    let center = match player.physics {
-                        Physics::Cpu { ref transform, .. } => transform.clone(),
-                        Physics::Gpu { ref body, .. } => self
-                            .gpu
-                            .as_ref()
-                            .unwrap()
-                            .store
-                            .cpu_mirror()
-                            .get(body)
-                            .unwrap()
-                            .clone(),
-                    };
+                    let mirror = self.gpu.as_ref().map(|gpu| gpu.store.cpu_mirror());
+                    let center = self.agents[self.player]
+                        .transform(mirror.as_deref())
+                        .unwrap();
                     self.tick = None;
                     if self.is_paused {
                         self.is_paused = false;
@@ -539,35 +933,10 @@ impl Application for Game {
                 }
                 Key::Comma => self.tick = Some(-1.0),
                 Key::Period => self.tick = Some(1.0),
-                Key::LShift => self.turbo = true,
-                Key::LAlt => self.jump = Some(0.0),
                 Key::W => self.spin_ver = 1.0,
                 Key::S => self.spin_ver = -1.0,
-                Key::R => {
-                    if let Physics::Cpu {
-                        ref mut transform,
-                        ref mut dynamo,
-                    } = player.physics
-                    {
-                        transform.rot = cgmath::One::one();
-                        dynamo.linear_velocity = cgmath::Vector3::zero();
-                        dynamo.angular_velocity = cgmath::Vector3::zero();
-                    }
-                }
                 Key::A => self.spin_hor = -1.0,
                 Key::D => self.spin_hor = 1.0,
-                Key::Q => {
-                    self.roll = Some(Roll {
-                        dir: -1.0,
-                        time: 0.0,
-                    })
-                }
-                Key::E => {
-                    self.roll = Some(Roll {
-                        dir: 1.0,
-                        time: 0.0,
-                    })
-                }
                 _ => (),
             },
             KeyboardInput {
@@ -577,9 +946,6 @@ impl Application for Game {
             } => match key {
                 Key::W | Key::S => self.spin_ver = 0.0,
                 Key::A | Key::D => self.spin_hor = 0.0,
-                Key::Q | Key::E => self.roll = None,
-                Key::LShift => self.turbo = false,
-                Key::LAlt => player.jump = self.jump.take(),
                 _ => (),
             },
             /*
@@ -599,39 +965,30 @@ impl Application for Game {
             _ => {}
         }
 
+        for agent in self.agents.values_mut() {
+            if let Some(ref mut controller) = agent.controller {
+                controller.on_key(&input);
+            }
+        }
         true
     }
 
     fn update(
         &mut self,
         device: &wgpu::Device,
+        _queue: &wgpu::Queue,
         delta: f32,
         spawner: &LocalSpawner,
     ) -> Vec<wgpu::CommandBuffer> {
         let focus_point = self.cam.intersect_height(level::HEIGHT_SCALE as f32 * 0.3);
 
-        if let Some(ref mut jump) = self.jump {
-            let power = delta * (self.db.common.speed.standard_frame_rate as f32);
-            *jump = (*jump + power).min(self.db.common.force.max_jump_power);
-        }
-
         {
-            let player = self
-                .agents
-                .iter_mut()
-                .find(|a| a.spirit == Spirit::Player)
-                .unwrap();
-            let target = match player.physics {
-                Physics::Cpu { ref transform, .. } => transform.clone(),
-                Physics::Gpu { ref body, .. } => self
-                    .gpu
-                    .as_ref()
-                    .unwrap()
-                    .store
-                    .cpu_mirror()
-                    .get(body)
-                    .cloned()
-                    .unwrap_or(space::Transform::one()),
+            let player = &mut self.agents[self.player];
+            let target = {
+                let mirror = self.gpu.as_ref().map(|gpu| gpu.store.cpu_mirror());
+                player
+                    .transform(mirror.as_deref())
+                    .unwrap_or(space::Transform::one())
             };
 
             if self.is_paused {
@@ -643,10 +1000,8 @@ impl Application for Game {
                         tick * self.max_quant,
                         &self.level,
                         &self.db.common,
-                        SimulationStep::Final {
-                            focus_point: &focus_point,
-                            line_buffer: Some(&mut self.line_buffer),
-                        },
+                        &focus_point,
+                        &mut self.line_buffer,
                     );
                 }
 
@@ -659,26 +1014,6 @@ impl Application for Game {
                 return Vec::new();
             }
 
-            player.control.rudder = self.spin_hor;
-            player.control.motor = 1.0 * self.spin_ver;
-            player.control.turbo = self.turbo;
-            player.control.roll = match self.roll {
-                Some(ref mut roll) => {
-                    let roll_count = (roll.time * self.db.common.speed.standard_frame_rate as f32)
-                        .min(100.0) as u8;
-                    roll.time += delta;
-                    if roll_count > self.db.common.force.side_impulse_delay {
-                        roll.time = 0.0;
-                    }
-                    if roll_count < self.db.common.force.side_impulse_duration {
-                        roll.dir
-                    } else {
-                        0.0
-                    }
-                }
-                None => 0.0,
-            };
-
             match self.cam_style {
                 CameraStyle::Simple(ref dir) => {
                     self.cam.look_by(&target, dir);
@@ -689,19 +1024,26 @@ impl Application for Game {
             }
         }
 
-        const TIME_HACK: f32 = 1.0;
-        // Note: the equations below make the game absolutely match the original
-        // in terms of time scale for both input and physics.
-        // However! the game feels much faster, presumably because of the lack
-        // of collision/drag forces that slow you down.
-        let input_factor = TIME_HACK * delta / config::common::MAIN_LOOP_TIME;
-        let mut physics_dt = TIME_HACK * delta * {
-            let n = &self.db.common.nature;
-            let fps = self.db.common.speed.standard_frame_rate as f32;
-            fps * n.time_delta0 * n.num_calls_analysis as f32
-        };
+        let num_ticks = self.clock.advance(delta);
+        if num_ticks == 0 {
+            return Vec::new();
+        }
 
+        // Both the input and the physics are measured in the original frames.
+        let tick_time = self.clock.tick_time;
+        let input_factor = self
+            .speed_correction
+            .update(tick_time, &self.db.common.speed);
+        let tick_dt = self.speed_correction.time_delta(&self.db.common);
+
+        if self.gpu.is_some() {
+            // The GPU path is not deterministic, so the ticks are batched together.
+            self.update_population();
+            self.update_grid();
+            self.prepare_tick(tick_time);
+        }
         if let Some(ref mut gpu) = self.gpu {
+            let mut physics_dt = tick_dt * num_ticks as f32;
             let mut prep_encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Preparation"),
             });
@@ -710,35 +1052,26 @@ impl Application for Game {
             });
 
             // initialize new entries, update
-            for agent in self.agents.iter_mut() {
-                if let Physics::Gpu {
-                    ref body,
-                    ref mut last_control,
-                    ..
-                } = agent.physics
-                {
-                    if *last_control != agent.control {
-                        *last_control = agent.control.clone();
-                        let glob = &self.db.common.global;
-                        let c = [
-                            agent.control.rudder,
-                            agent.control.motor,
-                            if agent.control.turbo {
-                                glob.k_traction_turbo
-                            } else {
-                                1.0
-                            },
-                            if agent.control.brake {
-                                glob.f_brake_max
-                            } else {
-                                0.0
-                            },
-                        ];
-                        gpu.store.update_control(body, c);
-                    }
+            for agent in self.agents.values_mut() {
+                if let Physics::Gpu { ref body, .. } = agent.physics {
+                    let c = encode_control(
+                        &agent.control,
+                        agent.car.stats.max_fly as f32,
+                        &self.db.common,
+                    );
+                    gpu.store.update_control(body, c);
                     if let Some(power) = agent.jump.take() {
                         gpu.store.add_push(body, physics::jump_dir(power));
                     }
+                    if std::mem::replace(&mut agent.reset, false) {
+                        let state = gpu.store.cpu_mirror().get(body).cloned();
+                        if let Some(mut state) = state {
+                            state.transform.rot = cgmath::Quaternion::one();
+                            state.linear_velocity = cgmath::Vector3::zero();
+                            state.angular_velocity = cgmath::Vector3::zero();
+                            gpu.store.set_state(body, &state);
+                        }
+                    }
                 };
             }
             gpu.store.update_entries(device, &mut encoder);
@@ -747,69 +1080,80 @@ impl Application for Game {
                 let mut session = gpu
                     .collider
                     .begin(&mut encoder, &self.render.terrain, spawner);
-                for agent in &mut self.agents {
+                for agent in self.agents.values() {
                     if let Physics::Gpu { ref body, .. } = agent.physics {
-                        session.add(&agent.car.model.shape, body.index());
+                        session.add(&agent.model.shape, body.index());
                     }
                 }
                 let ranges = session.finish(&mut prep_encoder, device);
 
-                gpu.store.step(device, &mut encoder, self.max_quant, ranges);
+                gpu.store
+                    .step(device, &mut encoder, self.max_quant, 0.0, ranges);
                 physics_dt -= self.max_quant;
             }
 
             let mut session = gpu
                 .collider
                 .begin(&mut encoder, &self.render.terrain, spawner);
-            for agent in &mut self.agents {
+            for agent in self.agents.values_mut() {
                 if let Physics::Gpu {
                     ref body,
                     ref mut collision_epochs,
                     ..
                 } = agent.physics
                 {
-                    let start_index = session.add(&agent.car.model.shape, body.index());
+                    // the older epochs were consumed by the store steps they were encoded with
+                    collision_epochs.clear();
+                    let start_index = session.add(&agent.model.shape, body.index());
                     let old = collision_epochs.insert(session.epoch, start_index);
                     assert_eq!(old, None);
                 }
             }
             let ranges = session.finish(&mut prep_encoder, device);
-            gpu.store.step(device, &mut encoder, physics_dt, ranges);
+            // the per-tick counters only advance once for all the ticks
+            let frames = input_factor * num_ticks as f32;
+            gpu.store
+                .step(device, &mut encoder, physics_dt, frames, ranges);
             gpu.store.produce_gpu_results(device, &mut encoder);
 
             vec![prep_encoder.finish(), encoder.finish()]
         } else {
             use rayon::prelude::*;
 
-            let clipper = Clipper::new(&self.cam);
-            let max_quant = self.max_quant;
-            let common = &self.db.common;
-            let level = &self.level;
-
-            self.agents.par_iter_mut().for_each(|a| {
-                let mut dt = physics_dt;
-                a.cpu_apply_control(input_factor, common);
+            for _ in 0..num_ticks {
+                self.prepare_tick(tick_time);
 
-                // only go through the full iteration on visible objects
-                if !clipper.clip(&a.position()) {
-                    while dt > max_quant {
-                        a.cpu_step(max_quant, level, common, SimulationStep::Intermediate);
-                        dt -= max_quant;
-                    }
-                }
-
-                a.cpu_step(
-                    dt,
-                    level,
-                    common,
-                    SimulationStep::Final {
-                        focus_point: &focus_point,
-                        line_buffer: None,
-                    },
-                );
-
-                a.ai_behavior(delta);
-            });
+                // The simulation doesn't depend on the camera, so that it can be replayed.
+                let focus_point = cgmath::Point3::from_vec(self.agents[self.player].position(None));
+                let full_time = physics::TickTime {
+                    frames: input_factor,
+                    dt: tick_dt,
+                    max_quant: self.max_quant,
+                };
+                let common = &self.db.common;
+                let level = &self.level;
+
+                let agents = self.agents.values_mut().collect::<Vec<_>>();
+                agents.into_par_iter().for_each(|a| {
+                    // only go through the full iteration on objects close to the player
+                    let distance = (a.position(None) - focus_point.to_vec()).magnitude();
+                    let time = if distance < FULL_SIMULATION_DISTANCE {
+                        full_time
+                    } else {
+                        physics::TickTime {
+                            max_quant: tick_dt,
+                            ..full_time
+                        }
+                    };
+                    a.cpu_tick(&time, level, common, &focus_point);
+                });
+                self.update_grid();
+                cpu_collide(&mut self.agents, &self.grid, &self.db.common);
+                cpu_update_health(&mut self.agents, tick_time, &self.db.common);
+                self.update_population();
+                // the new and the removed agents, for the controllers and the drawing
+                self.update_grid();
+            }
 
             Vec::new()
         }
@@ -848,24 +1192,31 @@ impl Application for Game {
 
         let identity_transform = space::Transform::one();
         let clipper = Clipper::new(&self.cam);
+        let bounds = self.cam.visible_bounds();
+        let visible = self
+            .grid
+            .query_aabb(bounds.start, bounds.end)
+            .into_iter()
+            .map(|entry| entry.item)
+            .collect::<HashSet<_>>();
         self.batcher.clear();
 
-        for agent in self.agents.iter() {
+        for (id, agent) in self.agents.iter() {
             let (gpu_body, transform) = match agent.physics {
-                Physics::Cpu { ref transform, .. } => {
-                    if clipper.clip(&transform.disp) {
+                Physics::Cpu(ref body) => {
+                    if !visible.contains(&id) || clipper.clip(&body.transform.disp) {
                         continue;
                     }
-                    (&GpuBody::ZERO, transform)
+                    (&GpuBody::ZERO, &body.transform)
                 }
                 Physics::Gpu { ref body, .. } => (body, &identity_transform),
             };
             let debug_shape_scale = match agent.spirit {
                 Spirit::Player => Some(agent.car.physics.scale_bound),
-                Spirit::Other { .. } => None,
+                Spirit::Other | Spirit::Debris { .. } => None,
             };
             self.batcher.add_model(
-                &agent.car.model,
+                &agent.model,
                 &transform,
                 debug_shape_scale,
                 gpu_body,
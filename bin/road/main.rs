@@ -1,36 +1,109 @@
-use log::info;
+use log::warn;
+use vangers::{config, replay};
+
+use std::{path::Path, process};
 
 #[path = "../boilerplate.rs"]
 mod boilerplate;
+mod calibrate;
+mod controller;
 mod game;
-mod physics;
 
 fn main() {
     use std::env;
 
-    let (mut harness, settings) = boilerplate::Harness::init("road");
-
-    info!("Parsing command line");
     let args: Vec<_> = env::args().collect();
     let mut options = getopts::Options::new();
     options
         .parsing_style(getopts::ParsingStyle::StopAtFirstFree)
-        .optflag("h", "help", "print this help menu");
+        .optflag("h", "help", "print this help menu")
+        .optopt(
+            "",
+            "record",
+            "record the session into a replay file",
+            "FILE",
+        )
+        .optopt("", "replay", "play back a recorded session", "FILE")
+        .optflag(
+            "",
+            "calibrate",
+            "print the top speeds of all vehicles without starting the game",
+        );
+    boilerplate::headless_options(&mut options);
 
     let matches = options.parse(&args[1..]).unwrap();
+    let brief = format!("Usage: {} [options]", args[0]);
     if matches.opt_present("h") || !matches.free.is_empty() {
         println!("Vangers game prototype");
-        let brief = format!("Usage: {} [options]", args[0]);
         println!("{}", options.usage(&brief));
         return;
     }
 
-    let game = game::Game::new(
-        &settings,
-        harness.extent,
-        &harness.device,
-        &mut harness.queue,
-    );
+    if matches.opt_present("calibrate") {
+        env_logger::init();
+        let settings = config::Settings::load("config/settings.ron");
+        calibrate::run(&settings);
+        return;
+    }
 
-    harness.main_loop(game);
+    let headless = boilerplate::HeadlessConfig::from_matches(&matches).unwrap_or_else(|e| {
+        println!("{}", e);
+        println!("{}", options.usage(&brief));
+        process::exit(1)
+    });
+    if let Some(mut config) = headless {
+        let (headless, mut settings) = boilerplate::Headless::init(&config);
+        if !config.render && settings.game.physics.gpu_collision.is_some() {
+            warn!("The GPU physics is read back when drawing, rendering offscreen");
+            config.render = true;
+        }
+        let game = start_game(
+            &matches,
+            &mut settings,
+            headless.extent,
+            &headless.device,
+            &headless.queue,
+        );
+        headless.run(game, &config);
+    } else {
+        let (harness, mut settings) = boilerplate::Harness::init("road");
+        let game = start_game(
+            &matches,
+            &mut settings,
+            harness.extent,
+            &harness.device,
+            &harness.queue,
+        );
+        harness.main_loop(game);
+    }
+}
+
+fn start_game(
+    matches: &getopts::Matches,
+    settings: &mut config::Settings,
+    extent: wgpu::Extent3d,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> game::Game {
+    let mut replay = matches
+        .opt_str("replay")
+        .map(|path| replay::Replay::load(Path::new(&path)));
+    let mut record = matches.opt_str("record");
+    if (replay.is_some() || record.is_some()) && settings.game.physics.gpu_collision.is_some() {
+        warn!("Replays are only supported by the CPU physics, ignoring");
+        replay = None;
+        record = None;
+    }
+    if let Some(ref replay) = replay {
+        replay.header.apply(settings);
+    }
+
+    let mut game = game::Game::new(settings, extent, device, queue);
+    if let Some(replay) = replay {
+        game.play(replay);
+    }
+    if let Some(path) = record {
+        game.record(Path::new(&path));
+    }
+    game
 }
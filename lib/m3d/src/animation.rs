@@ -0,0 +1,113 @@
+use crate::{AnimatedMesh, Geometry, Polygon};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameBlend {
+    pub current: usize,
+    pub next: usize,
+    // 0 means fully `current`, 1 means fully `next`
+    pub ratio: f32,
+}
+
+impl FrameBlend {
+    pub fn at(time: f32, frame_time: f32, frame_count: usize, looping: bool) -> Self {
+        if frame_count <= 1 || frame_time <= 0.0 {
+            return FrameBlend {
+                current: 0,
+                next: 0,
+                ratio: 0.0,
+            };
+        }
+        let duration = frame_count as f32 * frame_time;
+        let local = if looping {
+            time.rem_euclid(duration)
+        } else {
+            time.max(0.0).min((frame_count - 1) as f32 * frame_time)
+        };
+        let position = local / frame_time;
+        let current = (position as usize).min(frame_count - 1);
+        let next = if current + 1 < frame_count {
+            current + 1
+        } else if looping {
+            0
+        } else {
+            current
+        };
+        FrameBlend {
+            current,
+            next,
+            ratio: if next != current {
+                position - current as f32
+            } else {
+                0.0
+            },
+        }
+    }
+
+    // The frame to show when no interpolation is possible.
+    pub fn nearest(&self) -> usize {
+        if self.ratio < 0.5 {
+            self.current
+        } else {
+            self.next
+        }
+    }
+}
+
+fn lerp_vectors(a: &[[i8; 3]], b: &[[i8; 3]], ratio: f32) -> Option<Vec<[f32; 3]>> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let lerp = |x: i8, y: i8| x as f32 + (y as f32 - x as f32) * ratio;
+    Some(
+        a.iter()
+            .zip(b)
+            .map(|(u, v)| [lerp(u[0], v[0]), lerp(u[1], v[1]), lerp(u[2], v[2])])
+            .collect(),
+    )
+}
+
+impl<G> AnimatedMesh<G> {
+    pub fn frame_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn duration(&self, frame_time: f32) -> f32 {
+        self.meshes.len() as f32 * frame_time
+    }
+
+    pub fn blend_at(&self, time: f32, frame_time: f32, looping: bool) -> FrameBlend {
+        FrameBlend::at(time, frame_time, self.meshes.len(), looping)
+    }
+}
+
+impl<P: Polygon> AnimatedMesh<Geometry<P>> {
+    // All the frames share the same vertex and polygon layout, so they can be morphed.
+    pub fn is_morphable(&self) -> bool {
+        match self.meshes.first() {
+            Some(first) => self.meshes.iter().all(|mesh| {
+                mesh.geometry.positions.len() == first.geometry.positions.len()
+                    && mesh.geometry.normals.len() == first.geometry.normals.len()
+                    && mesh.geometry.polygons.len() == first.geometry.polygons.len()
+            }),
+            None => false,
+        }
+    }
+
+    // Returns `None` if the frames have different number of positions.
+    pub fn interpolate_positions(&self, blend: &FrameBlend) -> Option<Vec<[f32; 3]>> {
+        lerp_vectors(
+            &self.meshes[blend.current].geometry.positions,
+            &self.meshes[blend.next].geometry.positions,
+            blend.ratio,
+        )
+    }
+
+    // Note: the results are not normalized.
+    pub fn interpolate_normals(&self, blend: &FrameBlend) -> Option<Vec<[f32; 3]>> {
+        lerp_vectors(
+            &self.meshes[blend.current].geometry.normals,
+            &self.meshes[blend.next].geometry.normals,
+            blend.ratio,
+        )
+    }
+}
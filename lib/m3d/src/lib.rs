@@ -1,12 +1,15 @@
+mod animation;
 mod geometry;
+pub mod validate;
 
+pub use self::animation::FrameBlend;
 pub use self::geometry::{
     CollisionQuad, ColorId, DrawTriangle, Geometry, Vertex, NORMALIZER, NUM_COLOR_IDS,
 };
 
 use byteorder::{LittleEndian as E, ReadBytesExt, WriteBytesExt};
 use serde::{Deserialize, Serialize};
-use std::{fs::File, io::Write};
+use std::io::{Read, Write};
 
 const MAX_SLOTS: usize = 3;
 const MAGIC_VERSION: u32 = 8;
@@ -224,6 +227,22 @@ impl<M, S> Model<M, S> {
     }
 }
 
+impl<M: Clone, S: Clone> Model<M, S> {
+    // Standalone model of a single debris piece, flying away after destruction.
+    pub fn debrie(&self, index: usize) -> Self {
+        let debrie = &self.debris[index];
+        Model {
+            body: debrie.mesh.clone(),
+            shape: debrie.shape.clone(),
+            bound: self.bound.clone(),
+            color: self.color.clone(),
+            wheels: Vec::new(),
+            debris: Vec::new(),
+            slots: [Slot::EMPTY, Slot::EMPTY, Slot::EMPTY],
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Bounds {
     pub coord_min: [i32; 3],
@@ -459,7 +478,7 @@ pub struct AnimatedMesh<G> {
 }
 
 impl<P: Polygon> AnimatedMesh<Geometry<P>> {
-    pub fn load(mut input: File) -> Self {
+    pub fn load<I: Read>(mut input: I) -> Self {
         let count = input.read_u32::<E>().unwrap();
         AnimatedMesh {
             bound: UpperBound::read(&mut input),
@@ -468,7 +487,7 @@ impl<P: Polygon> AnimatedMesh<Geometry<P>> {
         }
     }
 
-    pub fn save(&self, mut output: File) {
+    pub fn save<W: Write>(&self, mut output: W) {
         output.write_u32::<E>(self.meshes.len() as u32).unwrap();
         self.bound.write(&mut output);
         self.color.write(&mut output);
@@ -481,7 +500,7 @@ impl<P: Polygon> AnimatedMesh<Geometry<P>> {
 pub type FullModel = Model<DrawMesh, CollisionMesh>;
 
 impl FullModel {
-    pub fn load(mut input: File) -> Self {
+    pub fn load<I: Read>(mut input: I) -> Self {
         log::debug!("\tReading the body...");
         let body: DrawMesh = Mesh::load(&mut input);
 
@@ -552,7 +571,7 @@ impl FullModel {
         }
     }
 
-    pub fn save(&self, mut output: File) {
+    pub fn save<W: Write>(&self, mut output: W) {
         self.body.save(&mut output);
         self.bound.write(&mut output);
         output.write_u32::<E>(self.wheels.len() as u32).unwrap();
@@ -0,0 +1,253 @@
+use crate::{
+    AnimatedMesh, CollisionQuad, DrawTriangle, FullModel, Geometry, Mesh, NORMALIZER, NUM_COLOR_IDS,
+};
+
+use std::fmt;
+
+// Normals are quantized to i8, so their length can't match `NORMALIZER` exactly.
+const NORMAL_TOLERANCE: f32 = 0.1;
+// Distance from the quad plane, in model units.
+const PLANAR_TOLERANCE: f32 = 2.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    PositionIndex { polygon: usize, index: u16 },
+    NormalIndex { polygon: usize, index: u16 },
+    Unnormalized { normal: usize, length: f32 },
+    UnnormalizedFlat { polygon: usize, length: f32 },
+    OutOfBounds { position: usize },
+    MaxRadius { stored: u32, actual: u32 },
+    UnknownColor { polygon: usize, color: u32 },
+    NonPlanar { polygon: usize, distance: f32 },
+    WheelBound { wheel: usize, bound_index: u32 },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::PositionIndex { polygon, index } => {
+                write!(
+                    f,
+                    "polygon {} refers to missing position {}",
+                    polygon, index
+                )
+            }
+            Problem::NormalIndex { polygon, index } => {
+                write!(f, "polygon {} refers to missing normal {}", polygon, index)
+            }
+            Problem::Unnormalized { normal, length } => write!(
+                f,
+                "normal {} has length {} instead of {}",
+                normal, length, NORMALIZER
+            ),
+            Problem::UnnormalizedFlat { polygon, length } => write!(
+                f,
+                "polygon {} flat normal has length {} instead of {}",
+                polygon, length, NORMALIZER
+            ),
+            Problem::OutOfBounds { position } => {
+                write!(f, "position {} is outside of the bounds", position)
+            }
+            Problem::MaxRadius { stored, actual } => write!(
+                f,
+                "max radius is {}, but the geometry extends to {}",
+                stored, actual
+            ),
+            Problem::UnknownColor { polygon, color } => {
+                write!(f, "polygon {} has unknown color ID {}", polygon, color)
+            }
+            Problem::NonPlanar { polygon, distance } => write!(
+                f,
+                "quad {} is not planar, a corner is {} away",
+                polygon, distance
+            ),
+            Problem::WheelBound { wheel, bound_index } => write!(
+                f,
+                "wheel {} bound index {} is not a shape polygon",
+                wheel, bound_index
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub mesh: String,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.mesh, self.problem)
+    }
+}
+
+fn length(v: [i8; 3]) -> f32 {
+    let (x, y, z) = (v[0] as f32, v[1] as f32, v[2] as f32);
+    (x * x + y * y + z * z).sqrt()
+}
+
+fn is_normalized(v: [i8; 3]) -> bool {
+    (length(v) - NORMALIZER).abs() <= NORMALIZER * NORMAL_TOLERANCE
+}
+
+trait CheckPolygon: Sized {
+    fn check(&self, id: usize, geometry: &Geometry<Self>, problems: &mut Vec<Problem>);
+}
+
+impl CheckPolygon for DrawTriangle {
+    fn check(&self, id: usize, geometry: &Geometry<Self>, problems: &mut Vec<Problem>) {
+        for v in &self.vertices {
+            if v.pos as usize >= geometry.positions.len() {
+                problems.push(Problem::PositionIndex {
+                    polygon: id,
+                    index: v.pos,
+                });
+            }
+            if v.normal as usize >= geometry.normals.len() {
+                problems.push(Problem::NormalIndex {
+                    polygon: id,
+                    index: v.normal,
+                });
+            }
+        }
+        if !is_normalized(self.flat_normal) {
+            problems.push(Problem::UnnormalizedFlat {
+                polygon: id,
+                length: length(self.flat_normal),
+            });
+        }
+        if self.material[0] >= NUM_COLOR_IDS {
+            problems.push(Problem::UnknownColor {
+                polygon: id,
+                color: self.material[0],
+            });
+        }
+    }
+}
+
+impl CheckPolygon for CollisionQuad {
+    fn check(&self, id: usize, geometry: &Geometry<Self>, problems: &mut Vec<Problem>) {
+        let mut corners = Vec::with_capacity(4);
+        for &index in &self.vertices {
+            match geometry.positions.get(index as usize) {
+                Some(p) => corners.push([p[0] as f32, p[1] as f32, p[2] as f32]),
+                None => problems.push(Problem::PositionIndex { polygon: id, index }),
+            }
+        }
+        if !is_normalized(self.flat_normal) {
+            problems.push(Problem::UnnormalizedFlat {
+                polygon: id,
+                length: length(self.flat_normal),
+            });
+            return;
+        }
+        if corners.len() != self.vertices.len() {
+            return;
+        }
+
+        let len = length(self.flat_normal);
+        let n = [
+            self.flat_normal[0] as f32 / len,
+            self.flat_normal[1] as f32 / len,
+            self.flat_normal[2] as f32 / len,
+        ];
+        let dot = |c: &[f32; 3]| c[0] * n[0] + c[1] * n[1] + c[2] * n[2];
+        let center = corners.iter().map(dot).sum::<f32>() / corners.len() as f32;
+        let distance = corners
+            .iter()
+            .map(|c| (dot(c) - center).abs())
+            .fold(0.0, f32::max);
+        if distance > PLANAR_TOLERANCE {
+            problems.push(Problem::NonPlanar {
+                polygon: id,
+                distance,
+            });
+        }
+    }
+}
+
+fn check_mesh<P: CheckPolygon>(mesh: &Mesh<Geometry<P>>, name: &str, issues: &mut Vec<Issue>) {
+    let mut problems = Vec::new();
+    let geometry = &mesh.geometry;
+
+    for (i, &n) in geometry.normals.iter().enumerate() {
+        if !is_normalized(n) {
+            problems.push(Problem::Unnormalized {
+                normal: i,
+                length: length(n),
+            });
+        }
+    }
+
+    let mut radius = 0.0f32;
+    for (i, p) in geometry.positions.iter().enumerate() {
+        let outside = (0..3).any(|axis| {
+            (p[axis] as i32) < mesh.bounds.coord_min[axis]
+                || (p[axis] as i32) > mesh.bounds.coord_max[axis]
+        });
+        if outside {
+            problems.push(Problem::OutOfBounds { position: i });
+        }
+        radius = radius.max(length(*p));
+    }
+    // The radius has to cover the geometry, but it doesn't need to be tight.
+    let actual = radius.ceil() as u32;
+    if mesh.max_radius + 1 < actual || mesh.max_radius > 2 * actual + 1 {
+        problems.push(Problem::MaxRadius {
+            stored: mesh.max_radius,
+            actual,
+        });
+    }
+
+    for (i, polygon) in geometry.polygons.iter().enumerate() {
+        polygon.check(i, geometry, &mut problems);
+    }
+
+    issues.extend(problems.into_iter().map(|problem| Issue {
+        mesh: name.to_string(),
+        problem,
+    }));
+}
+
+pub fn full_model(model: &FullModel) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    check_mesh(&model.body, "body", &mut issues);
+    check_mesh(&model.shape, "shape", &mut issues);
+
+    for (i, wheel) in model.wheels.iter().enumerate() {
+        if let Some(ref mesh) = wheel.mesh {
+            check_mesh(mesh, &format!("wheel[{}]", i), &mut issues);
+        }
+        if wheel.bound_index as usize >= model.shape.geometry.polygons.len() {
+            issues.push(Issue {
+                mesh: "shape".to_string(),
+                problem: Problem::WheelBound {
+                    wheel: i,
+                    bound_index: wheel.bound_index,
+                },
+            });
+        }
+    }
+
+    for (i, debrie) in model.debris.iter().enumerate() {
+        check_mesh(&debrie.mesh, &format!("debris[{}]", i), &mut issues);
+        check_mesh(&debrie.shape, &format!("debris[{}].shape", i), &mut issues);
+    }
+
+    for (i, slot) in model.slots.iter().enumerate() {
+        if let Some(ref mesh) = slot.mesh {
+            check_mesh(mesh, &format!("slot[{}]", i), &mut issues);
+        }
+    }
+
+    issues
+}
+
+pub fn animated_mesh(amesh: &AnimatedMesh<Geometry<DrawTriangle>>) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    for (i, mesh) in amesh.meshes.iter().enumerate() {
+        check_mesh(mesh, &format!("frame[{}]", i), &mut issues);
+    }
+    issues
+}
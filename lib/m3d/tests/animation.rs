@@ -0,0 +1,123 @@
+use m3d::{
+    AnimatedMesh, BodyColor, Bounds, DrawTriangle, FrameBlend, Geometry, Mesh, Physics, UpperBound,
+};
+
+const FRAME_TIME: f32 = 0.5;
+
+fn blend_eq(blend: FrameBlend, current: usize, next: usize, ratio: f32) {
+    assert_eq!((blend.current, blend.next), (current, next), "{:?}", blend);
+    assert!((blend.ratio - ratio).abs() < 1e-5, "{:?}", blend);
+}
+
+fn frame(positions: Vec<[i8; 3]>, normals: Vec<[i8; 3]>) -> Mesh<Geometry<DrawTriangle>> {
+    Mesh {
+        geometry: Geometry {
+            positions,
+            normals,
+            polygons: Vec::new(),
+        },
+        bounds: Bounds {
+            coord_min: [-128; 3],
+            coord_max: [127; 3],
+        },
+        parent_off: [0; 3],
+        parent_rot: [0; 3],
+        max_radius: 0,
+        physics: Physics {
+            volume: 0.0,
+            rcm: [0.0; 3],
+            jacobi: [[0.0; 3]; 3],
+        },
+    }
+}
+
+fn animated(meshes: Vec<Mesh<Geometry<DrawTriangle>>>) -> AnimatedMesh<Geometry<DrawTriangle>> {
+    AnimatedMesh {
+        meshes,
+        bound: UpperBound {
+            dimensions: [0; 3],
+            radius: 0,
+        },
+        color: BodyColor {
+            offset: 0,
+            shift: 0,
+        },
+    }
+}
+
+#[test]
+fn blend_ratios() {
+    blend_eq(FrameBlend::at(0.0, FRAME_TIME, 4, false), 0, 1, 0.0);
+    blend_eq(FrameBlend::at(0.25, FRAME_TIME, 4, false), 0, 1, 0.5);
+    blend_eq(FrameBlend::at(0.6, FRAME_TIME, 4, true), 1, 2, 0.2);
+    blend_eq(FrameBlend::at(1.0, FRAME_TIME, 4, true), 2, 3, 0.0);
+}
+
+#[test]
+fn blend_loop_wrap() {
+    // The last frame blends back into the first one.
+    blend_eq(FrameBlend::at(1.75, FRAME_TIME, 4, true), 3, 0, 0.5);
+    // Times past the duration wrap around.
+    blend_eq(FrameBlend::at(2.25, FRAME_TIME, 4, true), 0, 1, 0.5);
+    // So do negative times.
+    blend_eq(FrameBlend::at(-0.25, FRAME_TIME, 4, true), 3, 0, 0.5);
+}
+
+#[test]
+fn blend_clamping() {
+    blend_eq(FrameBlend::at(-1.0, FRAME_TIME, 4, false), 0, 1, 0.0);
+    blend_eq(FrameBlend::at(1.75, FRAME_TIME, 4, false), 3, 3, 0.0);
+    blend_eq(FrameBlend::at(10.0, FRAME_TIME, 4, false), 3, 3, 0.0);
+    // Degenerate animations stay on the first frame.
+    blend_eq(FrameBlend::at(1.0, FRAME_TIME, 1, true), 0, 0, 0.0);
+    blend_eq(FrameBlend::at(1.0, 0.0, 4, true), 0, 0, 0.0);
+}
+
+#[test]
+fn blend_nearest() {
+    assert_eq!(FrameBlend::at(0.2, FRAME_TIME, 4, false).nearest(), 0);
+    assert_eq!(FrameBlend::at(0.3, FRAME_TIME, 4, false).nearest(), 1);
+    assert_eq!(FrameBlend::at(1.9, FRAME_TIME, 4, true).nearest(), 0);
+}
+
+#[test]
+fn interpolate() {
+    let amesh = animated(vec![
+        frame(vec![[0, 0, 0], [10, -10, 4]], vec![[0, 0, 127]]),
+        frame(vec![[20, 40, -20], [10, 10, 4]], vec![[127, 0, 0]]),
+    ]);
+    assert!(amesh.is_morphable());
+    assert_eq!(amesh.frame_count(), 2);
+    assert_eq!(amesh.duration(FRAME_TIME), 1.0);
+
+    let blend = amesh.blend_at(0.125, FRAME_TIME, true);
+    blend_eq(blend, 0, 1, 0.25);
+    assert_eq!(
+        amesh.interpolate_positions(&blend),
+        Some(vec![[5.0, 10.0, -5.0], [10.0, -5.0, 4.0]])
+    );
+    assert_eq!(
+        amesh.interpolate_normals(&blend),
+        Some(vec![[31.75, 0.0, 95.25]])
+    );
+
+    // Blending from the last frame back into the first one.
+    let blend = amesh.blend_at(0.75, FRAME_TIME, true);
+    blend_eq(blend, 1, 0, 0.5);
+    assert_eq!(
+        amesh.interpolate_positions(&blend),
+        Some(vec![[10.0, 20.0, -10.0], [10.0, 0.0, 4.0]])
+    );
+}
+
+#[test]
+fn interpolate_mismatch() {
+    let amesh = animated(vec![
+        frame(vec![[0, 0, 0]], vec![[0, 0, 127]]),
+        frame(vec![[0, 0, 0], [1, 1, 1]], vec![[0, 0, 127]]),
+    ]);
+    assert!(!amesh.is_morphable());
+    let blend = amesh.blend_at(0.25, FRAME_TIME, false);
+    assert_eq!(amesh.interpolate_positions(&blend), None);
+    assert!(amesh.interpolate_normals(&blend).is_some());
+}
@@ -0,0 +1,279 @@
+use m3d::{
+    validate::{self, Problem},
+    AnimatedMesh, BodyColor, Bounds, CollisionQuad, DrawTriangle, FullModel, Geometry, Mesh,
+    Physics, Slot, UpperBound, Vertex, Wheel,
+};
+
+fn mesh<P>(positions: Vec<[i8; 3]>, normals: Vec<[i8; 3]>, polygons: Vec<P>) -> Mesh<Geometry<P>> {
+    Mesh {
+        geometry: Geometry {
+            positions,
+            normals,
+            polygons,
+        },
+        bounds: Bounds {
+            coord_min: [0; 3],
+            coord_max: [10, 10, 10],
+        },
+        parent_off: [0; 3],
+        parent_rot: [0; 3],
+        max_radius: 16,
+        physics: Physics {
+            volume: 0.0,
+            rcm: [0.0; 3],
+            jacobi: [[0.0; 3]; 3],
+        },
+    }
+}
+
+fn triangle() -> DrawTriangle {
+    DrawTriangle {
+        vertices: [
+            Vertex { pos: 0, normal: 0 },
+            Vertex { pos: 1, normal: 0 },
+            Vertex { pos: 2, normal: 0 },
+        ],
+        flat_normal: [0, 0, 124],
+        material: [1, 0],
+    }
+}
+
+// A flat square body with a matching collision shape and a single wheel.
+fn model() -> FullModel {
+    let corners = vec![[0, 0, 0], [10, 0, 0], [10, 10, 0], [0, 10, 0]];
+    let mut body = mesh(corners.clone(), vec![[0, 0, 124]], vec![triangle()]);
+    body.max_radius = 15;
+    let shape = mesh(
+        corners,
+        Vec::new(),
+        vec![CollisionQuad {
+            vertices: [0, 1, 2, 3],
+            middle: [5, 5, 0],
+            flat_normal: [0, 0, 124],
+        }],
+    );
+    FullModel {
+        body,
+        shape,
+        bound: UpperBound {
+            dimensions: [10, 10, 10],
+            radius: 15,
+        },
+        color: BodyColor {
+            offset: 0,
+            shift: 0,
+        },
+        wheels: vec![Wheel {
+            mesh: None,
+            steer: 0,
+            pos: [0.0; 3],
+            width: 1,
+            radius: 1,
+            bound_index: 0,
+        }],
+        debris: Vec::new(),
+        slots: [Slot::EMPTY, Slot::EMPTY, Slot::EMPTY],
+    }
+}
+
+fn problems(model: &FullModel) -> Vec<(String, Problem)> {
+    validate::full_model(model)
+        .into_iter()
+        .map(|issue| (issue.mesh, issue.problem))
+        .collect()
+}
+
+fn single_problem(model: &FullModel, mesh: &str) -> Problem {
+    let mut problems = problems(model);
+    assert_eq!(problems.len(), 1, "{:?}", problems);
+    let (name, problem) = problems.pop().unwrap();
+    assert_eq!(name, mesh);
+    problem
+}
+
+#[test]
+fn valid() {
+    assert_eq!(problems(&model()), Vec::new());
+}
+
+#[test]
+fn position_index() {
+    let mut model = model();
+    model.body.geometry.polygons[0].vertices[1].pos = 7;
+    assert_eq!(
+        single_problem(&model, "body"),
+        Problem::PositionIndex {
+            polygon: 0,
+            index: 7,
+        }
+    );
+
+    let mut model = self::model();
+    model.shape.geometry.polygons[0].vertices[3] = 4;
+    assert_eq!(
+        single_problem(&model, "shape"),
+        Problem::PositionIndex {
+            polygon: 0,
+            index: 4,
+        }
+    );
+}
+
+#[test]
+fn normal_index() {
+    let mut model = model();
+    model.body.geometry.polygons[0].vertices[2].normal = 3;
+    assert_eq!(
+        single_problem(&model, "body"),
+        Problem::NormalIndex {
+            polygon: 0,
+            index: 3,
+        }
+    );
+}
+
+#[test]
+fn unnormalized() {
+    let mut model = model();
+    model.body.geometry.normals[0] = [0, 60, 0];
+    assert_eq!(
+        single_problem(&model, "body"),
+        Problem::Unnormalized {
+            normal: 0,
+            length: 60.0,
+        }
+    );
+}
+
+#[test]
+fn unnormalized_flat() {
+    let mut model = model();
+    model.body.geometry.polygons[0].flat_normal = [0, 0, 100];
+    assert_eq!(
+        single_problem(&model, "body"),
+        Problem::UnnormalizedFlat {
+            polygon: 0,
+            length: 100.0,
+        }
+    );
+}
+
+#[test]
+fn out_of_bounds() {
+    let mut model = model();
+    model.body.bounds.coord_max[1] = 5;
+    match &problems(&model)[..] {
+        [(a, Problem::OutOfBounds { position: 2 }), (b, Problem::OutOfBounds { position: 3 })] => {
+            assert_eq!((a.as_str(), b.as_str()), ("body", "body"))
+        }
+        other => panic!("Unexpected problems {:?}", other),
+    }
+}
+
+#[test]
+fn max_radius() {
+    let mut model = model();
+    model.body.max_radius = 100;
+    assert_eq!(
+        single_problem(&model, "body"),
+        Problem::MaxRadius {
+            stored: 100,
+            actual: 15,
+        }
+    );
+    // The stored radius can't be smaller than the geometry.
+    model.body.max_radius = 5;
+    assert_eq!(
+        single_problem(&model, "body"),
+        Problem::MaxRadius {
+            stored: 5,
+            actual: 15,
+        }
+    );
+}
+
+#[test]
+fn unknown_color() {
+    let mut model = model();
+    model.body.geometry.polygons[0].material[0] = m3d::NUM_COLOR_IDS;
+    assert_eq!(
+        single_problem(&model, "body"),
+        Problem::UnknownColor {
+            polygon: 0,
+            color: m3d::NUM_COLOR_IDS,
+        }
+    );
+}
+
+#[test]
+fn non_planar() {
+    let mut model = model();
+    model.shape.geometry.positions[2] = [10, 10, 8];
+    match single_problem(&model, "shape") {
+        Problem::NonPlanar {
+            polygon: 0,
+            distance,
+        } => assert!((distance - 6.0).abs() < 1e-5, "{}", distance),
+        other => panic!("Unexpected problem {:?}", other),
+    }
+}
+
+#[test]
+fn wheel_bound() {
+    let mut model = model();
+    model.wheels[0].bound_index = 1;
+    assert_eq!(
+        single_problem(&model, "shape"),
+        Problem::WheelBound {
+            wheel: 0,
+            bound_index: 1,
+        }
+    );
+}
+
+#[test]
+fn slot_mesh() {
+    let mut model = model();
+    let mut weapon = mesh(
+        vec![[0, 0, 0], [10, 0, 0], [0, 10, 0]],
+        vec![[0, 0, 124]],
+        vec![triangle()],
+    );
+    weapon.max_radius = 10;
+    weapon.geometry.polygons[0].vertices[2].normal = 1;
+    model.slots[2].mesh = Some(weapon);
+    assert_eq!(
+        single_problem(&model, "slot[2]"),
+        Problem::NormalIndex {
+            polygon: 0,
+            index: 1,
+        }
+    );
+}
+
+#[test]
+fn animation_frames() {
+    let frame = |positions| {
+        let mut mesh = mesh(positions, vec![[0, 0, 124]], vec![triangle()]);
+        mesh.max_radius = 15;
+        mesh
+    };
+    let amesh = AnimatedMesh {
+        meshes: vec![
+            frame(vec![[0, 0, 0], [10, 0, 0], [0, 10, 0]]),
+            frame(vec![[0, 0, 0], [10, 0, 0], [0, 10, 11]]),
+        ],
+        bound: UpperBound {
+            dimensions: [10, 10, 10],
+            radius: 10,
+        },
+        color: BodyColor {
+            offset: 0,
+            shift: 0,
+        },
+    };
+    let issues = validate::animated_mesh(&amesh);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].mesh, "frame[1]");
+    assert_eq!(issues[0].problem, Problem::OutOfBounds { position: 2 });
+}
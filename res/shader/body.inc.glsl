@@ -19,7 +19,8 @@ mat3 calc_j_inv(Model m, float scale) {
 
 struct Body {
     vec4 control; // X=steer, Y=motor, Z = k_turbo, W = f_brake
-    vec4 engine; // X=rudder, Y=traction
+    vec4 control_air; // X = fly, Y = lift, Z = max flying time
+    vec4 engine; // X=rudder, Y=traction, Z = flight altitude (0 = landed), W = flying time
     vec4 pos_scale;
     vec4 orientation;
     vec4 v_linear;
@@ -38,7 +39,7 @@ struct DragConstants {
     vec2 abs_stop;
     vec2 coll;
     vec2 other; // X = wheel speed, Y = drag Z
-    vec2 _pad;
+    vec2 helicopter;
 };
 
 struct GlobalConstants {
@@ -53,4 +54,8 @@ struct GlobalConstants {
     DragConstants drag;
     vec4 contact_elastic; // X = wheel, Y = spring, Z = xy, W = db collision
     vec4 force; // X = k_distance_to_force
+    vec4 heli_height; // X = max, Y = increment, Z = decrement, W = rotor Z offset
+    vec4 heli_thrust; // X = thrust, Y = rotate, Z = strife, W = hovering amplitude
+    vec4 heli_decr; // XY = convert, Z = rudder decrement, W = traction decrement
+    vec4 heli_circle; // XY = circle radius, Z = hovering dphi, W = circle dphi
 };
@@ -10,7 +10,7 @@ layout(set = 0, binding = 0, std430) buffer Storage {
 };
 
 layout(set = 0, binding = 1, std140) uniform Uniforms {
-    vec4 u_Delta;
+    vec4 u_Delta; // X = time step, Y = original frames to count for this step
 };
 
 layout(set = 0, binding = 2, std140) uniform Constants {
@@ -85,6 +85,50 @@ void main() {
     vec3 w_accel = qrot(irot, vec3(body.springs.xy, 0.0));
     mat3 j_inv = calc_j_inv(body.model, body.pos_scale.w);
 
+    // helicopter holds the body at the target altitude until the time runs out
+    float max_fly_time = body.control_air.z;
+    if (body.control_air.x != 0.0 && engine.z == 0.0 && engine.w < max_fly_time) {
+        engine.z = body.pos_scale.z + u_Constants.heli_height.y;
+    }
+    if (engine.z != 0.0 && (body.control_air.x == 0.0 || engine.w >= max_fly_time)) {
+        engine.z = 0.0;
+    }
+    bool flying = engine.z != 0.0;
+    if (flying) {
+        engine.w += u_Delta.y;
+        float change = body.control_air.y > 0.0 ? u_Constants.heli_height.y : u_Constants.heli_height.z;
+        engine.z = clamp(
+            engine.z + body.control_air.y * change * speed_correction_factor,
+            EPSILON,
+            u_Constants.heli_height.x
+        );
+
+        float hover = u_Constants.heli_thrust.w * sin(engine.w * u_Constants.heli_circle.z);
+        float lift = clamp(
+            u_Constants.heli_thrust.x * (engine.z + hover - body.pos_scale.z),
+            -u_Constants.impulse_elastic.x,
+            u_Constants.impulse_elastic.x
+        );
+        // circling around the hovering point
+        float omega = u_Constants.heli_circle.w / u_Constants.nature.x;
+        float phi = engine.w * u_Constants.heli_circle.w;
+        vec2 circle = -omega * omega * u_Constants.heli_circle.xy * vec2(cos(phi), sin(phi));
+        v_accel += qrot(irot, vec3(circle, u_Constants.nature.z + lift));
+        // hanging from the rotor above the center keeps the body level
+        vec3 rotor = vec3(0.0, 0.0, u_Constants.heli_height.w * body.pos_scale.w);
+        w_accel += u_Constants.nature.z * cross(rotor, z_axis);
+
+        v_accel.y += u_Constants.heli_thrust.x * u_Constants.heli_decr.y *
+            body.physics.speed.z * u_Constants.global_speed.z * body.control.z * engine.y;
+        v_accel.x += u_Constants.heli_thrust.z * u_Constants.heli_decr.x * engine.x;
+        v_accel -= vel * body.control.w;
+        w_accel.z -= u_Constants.heli_thrust.y * engine.x;
+        engine.xy *= pow(u_Constants.heli_decr.zw, vec2(speed_correction_factor));
+    } else if (body.control_air.x == 0.0 && spring_touch) {
+        // landing recharges the helicopter, once it's turned off
+        engine.w = 0.0;
+    }
+
     if (wheels_touch) {
         float speed = log(u_Constants.drag.other.x) * body.physics.mobility_ship.x
             * u_Constants.global_speed.x / body.physics.speed.x;
@@ -117,6 +161,9 @@ void main() {
     if (spring_touch) {
         drag *= u_Constants.drag.spring;
     }
+    if (flying) {
+        drag *= u_Constants.drag.helicopter;
+    }
 
     if (spring_touch || wheels_touch) {
         vec3 tmp = vec3(0.0, 0.0, body.physics.scale.w * body.pos_scale.w);
@@ -0,0 +1,273 @@
+use crate::{
+    config::settings,
+    level,
+    physics::{get_height, Control},
+    space,
+};
+
+use cgmath::prelude::*;
+
+use std::{cmp::Ordering, collections::BinaryHeap, f32::consts::PI};
+
+// Level texels sampled per cell side when building the grid.
+const SAMPLES_PER_SIDE: i32 = 8;
+// Extra cost of the slopes relative to the flat ground.
+const SLOPE_COST: f32 = 4.0;
+// Heading difference that results in the full rudder.
+const FULL_RUDDER_ANGLE: f32 = PI / 4.0;
+const NEIGHBORS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NavCell {
+    // average height of the surface
+    pub height: f32,
+    // most of the cell is under water
+    pub water: bool,
+}
+
+pub type CellCoords = (i32, i32);
+
+// Coarse map of the level for the route planning, wrapping the same way as the terrain.
+pub struct NavGrid {
+    cell_size: i32,
+    dims: (i32, i32),
+    cells: Vec<NavCell>,
+}
+
+impl NavGrid {
+    pub fn new(level: &level::Level, cell_size: i32) -> Self {
+        let dims = (
+            (level.size.0 + cell_size - 1) / cell_size,
+            (level.size.1 + cell_size - 1) / cell_size,
+        );
+        let step = (cell_size / SAMPLES_PER_SIDE).max(1);
+        let mut cells = Vec::with_capacity((dims.0 * dims.1) as usize);
+        for cy in 0..dims.1 {
+            for cx in 0..dims.0 {
+                let (mut total, mut count, mut water) = (0.0, 0, 0);
+                for y in (cy * cell_size..(cy + 1) * cell_size).step_by(step as usize) {
+                    for x in (cx * cell_size..(cx + 1) * cell_size).step_by(step as usize) {
+                        if level.is_water((x, y)) {
+                            water += 1;
+                        }
+                        total += get_height(level.get((x, y)).top());
+                        count += 1;
+                    }
+                }
+                cells.push(NavCell {
+                    height: total / count as f32,
+                    water: 2 * water > count,
+                });
+            }
+        }
+        NavGrid {
+            cell_size,
+            dims,
+            cells,
+        }
+    }
+
+    pub fn cell_size(&self) -> i32 {
+        self.cell_size
+    }
+
+    fn size(&self) -> cgmath::Vector2<f32> {
+        cgmath::vec2(
+            (self.dims.0 * self.cell_size) as f32,
+            (self.dims.1 * self.cell_size) as f32,
+        )
+    }
+
+    fn wrap(&self, coords: CellCoords) -> CellCoords {
+        (
+            coords.0.rem_euclid(self.dims.0),
+            coords.1.rem_euclid(self.dims.1),
+        )
+    }
+
+    pub fn locate(&self, pos: cgmath::Vector2<f32>) -> CellCoords {
+        self.wrap((
+            (pos.x / self.cell_size as f32).floor() as i32,
+            (pos.y / self.cell_size as f32).floor() as i32,
+        ))
+    }
+
+    pub fn cell(&self, coords: CellCoords) -> &NavCell {
+        let (x, y) = self.wrap(coords);
+        &self.cells[(y * self.dims.0 + x) as usize]
+    }
+
+    pub fn center(&self, coords: CellCoords) -> cgmath::Vector2<f32> {
+        let (x, y) = self.wrap(coords);
+        let half = 0.5 * self.cell_size as f32;
+        cgmath::vec2(
+            (x * self.cell_size) as f32 + half,
+            (y * self.cell_size) as f32 + half,
+        )
+    }
+
+    pub fn offset(
+        &self,
+        from: cgmath::Vector2<f32>,
+        to: cgmath::Vector2<f32>,
+    ) -> cgmath::Vector2<f32> {
+        space::wrap_offset(to - from, self.size())
+    }
+
+    // Cheapest route from the position towards the goal, ending at the passable cell
+    // closest to the goal within reach, and not including the starting cell.
+    pub fn find_route(
+        &self,
+        from: cgmath::Vector2<f32>,
+        goal: cgmath::Vector2<f32>,
+        profile: &settings::Ai,
+    ) -> Vec<CellCoords> {
+        #[derive(PartialEq)]
+        struct Node(f32, usize);
+        impl Eq for Node {}
+        impl Ord for Node {
+            fn cmp(&self, other: &Self) -> Ordering {
+                // reversed for the min-heap, with the index breaking the ties
+                other
+                    .0
+                    .partial_cmp(&self.0)
+                    .unwrap()
+                    .then(other.1.cmp(&self.1))
+            }
+        }
+        impl PartialOrd for Node {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        let index = |(x, y): CellCoords| (y * self.dims.0 + x) as usize;
+        let coords = |i: usize| (i as i32 % self.dims.0, i as i32 / self.dims.0);
+        let max_cost = 2.0 * profile.route_distance;
+
+        let start = index(self.locate(from));
+        let mut costs = vec![f32::INFINITY; self.cells.len()];
+        let mut parents = vec![usize::MAX; self.cells.len()];
+        let mut heap = BinaryHeap::new();
+        let mut best = (
+            self.offset(goal, self.center(coords(start))).magnitude(),
+            start,
+        );
+        costs[start] = 0.0;
+        heap.push(Node(0.0, start));
+
+        while let Some(Node(cost, current)) = heap.pop() {
+            if cost > costs[current] {
+                continue;
+            }
+            let (cx, cy) = coords(current);
+            let here = &self.cells[current];
+            for &(dx, dy) in NEIGHBORS.iter() {
+                let next = index(self.wrap((cx + dx, cy + dy)));
+                let cell = &self.cells[next];
+                if cell.water && profile.avoid_water {
+                    continue;
+                }
+                let distance = self.cell_size as f32 * ((dx * dx + dy * dy) as f32).sqrt();
+                let slope = (cell.height - here.height).abs() / distance;
+                if slope > profile.max_slope {
+                    continue;
+                }
+                let next_cost = cost + distance * (1.0 + SLOPE_COST * slope);
+                if next_cost >= costs[next] || next_cost > max_cost {
+                    continue;
+                }
+                costs[next] = next_cost;
+                parents[next] = current;
+                heap.push(Node(next_cost, next));
+                let left = self.offset(goal, self.center(coords(next))).magnitude();
+                if left < best.0 {
+                    best = (left, next);
+                }
+            }
+        }
+
+        let mut route = Vec::new();
+        let mut current = best.1;
+        while current != start {
+            route.push(coords(current));
+            current = parents[current];
+        }
+        route.reverse();
+        route
+    }
+}
+
+// Drives a vehicle along the routes planned on the grid.
+#[derive(Debug, PartialEq)]
+pub struct Pilot {
+    profile: settings::Ai,
+    // remaining waypoints, the next one last
+    route: Vec<CellCoords>,
+}
+
+impl Pilot {
+    pub fn new(profile: settings::Ai) -> Self {
+        Pilot {
+            profile,
+            route: Vec::new(),
+        }
+    }
+
+    pub fn next_waypoint(&self) -> Option<CellCoords> {
+        self.route.last().cloned()
+    }
+
+    pub fn drive(&mut self, grid: &NavGrid, transform: &space::Transform, control: &mut Control) {
+        let pos = transform.disp.truncate();
+        while let Some(next) = self.next_waypoint() {
+            if grid.offset(pos, grid.center(next)).magnitude() > self.profile.waypoint_distance {
+                break;
+            }
+            self.route.pop();
+        }
+        if self.route.is_empty() {
+            // keep going roughly where the vehicle is heading
+            let heading = (transform.rot * cgmath::Vector3::unit_y()).truncate();
+            let heading = if heading.magnitude2() > 0.01 {
+                heading.normalize()
+            } else {
+                cgmath::Vector2::unit_y()
+            };
+            let goal = pos + heading * self.profile.route_distance;
+            self.route = grid.find_route(pos, goal, &self.profile);
+            self.route.reverse();
+        }
+
+        control.motor = 1.0;
+        control.brake = false;
+        control.rudder = 0.0;
+        let next = match self.next_waypoint() {
+            Some(next) => next,
+            None => return,
+        };
+        let offset = grid.offset(pos, grid.center(next));
+        let local = transform.rot.invert() * offset.extend(0.0);
+        let angle = local.x.atan2(local.y);
+        control.rudder = (angle / FULL_RUDDER_ANGLE).clamp(-1.0, 1.0);
+
+        let here = grid.locate(pos);
+        let descent = grid.cell(here).height - grid.cell(next).height;
+        let distance = grid
+            .offset(grid.center(here), grid.center(next))
+            .magnitude();
+        if descent > self.profile.brake_slope * distance.max(1.0) {
+            control.motor = 0.0;
+            control.brake = true;
+        }
+    }
+}
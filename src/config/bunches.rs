@@ -2,7 +2,7 @@ use crate::config::text::Reader;
 
 use serde_scan;
 
-use std::fs::File;
+use std::io::Read;
 
 #[derive(Debug, Deserialize)]
 pub struct Cycle {
@@ -20,9 +20,9 @@ pub struct Bunch {
     pub cycles: Vec<Cycle>,
 }
 
-pub fn load(file: File) -> Vec<Bunch> {
+pub fn load<I: Read>(input: I) -> Vec<Bunch> {
     let mut bunches = Vec::new();
-    let mut fi = Reader::new(file);
+    let mut fi = Reader::new(input);
     fi.advance();
     assert_eq!(fi.cur(), "uniVang-ParametersFile_Ver_1");
 
@@ -1,10 +1,6 @@
-use crate::{
-    config::text::Reader, config::Settings, model, render::object::Context as ObjectContext,
-};
+use crate::{config::text::Reader, config::Settings, model};
 
-use wgpu;
-
-use std::{collections::HashMap, fs::File};
+use std::{collections::HashMap, io::Read, path::Path};
 
 pub type BoxSize = u8;
 pub type Price = u32;
@@ -18,7 +14,7 @@ pub enum Kind {
     Constructor,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct CarStats {
     pub class: u8,
     pub price_buy: Price,
@@ -67,7 +63,8 @@ impl CarStats {
 }
 
 #[repr(u8)]
-pub enum _Side {
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
     Front,
     Back,
     Side,
@@ -77,7 +74,7 @@ pub enum _Side {
 
 pub const NUM_SIDES: usize = 5;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct CarPhysics {
     pub name: String,
     // base
@@ -104,8 +101,8 @@ pub struct CarPhysics {
 }
 
 impl CarPhysics {
-    fn load(file: File) -> Self {
-        let mut fi = Reader::new(file);
+    fn load<I: Read>(input: I) -> Self {
+        let mut fi = Reader::new(input);
         fi.advance();
         CarPhysics {
             name: fi.cur().split_whitespace().nth(1).unwrap().to_owned(),
@@ -149,16 +146,26 @@ pub struct CarInfo {
     pub kind: Kind,
     pub stats: CarStats,
     pub physics: CarPhysics,
-    pub model: model::VisualModel,
+    pub model: model::CpuModel,
     pub scale: f32,
 }
 
-pub fn load_registry(
-    settings: &Settings,
-    reg: &super::game::Registry,
-    device: &wgpu::Device,
-    object: &ObjectContext,
-) -> HashMap<String, CarInfo> {
+impl CarInfo {
+    // Debris pieces as standalone physical objects, with the same parameters.
+    pub fn debris(&self) -> Vec<CarInfo> {
+        (0..self.model.debris.len())
+            .map(|index| CarInfo {
+                kind: self.kind.clone(),
+                stats: CarStats::default(),
+                physics: self.physics.clone(),
+                model: self.model.debrie(index),
+                scale: self.scale,
+            })
+            .collect()
+    }
+}
+
+pub fn load_registry(settings: &Settings, reg: &super::game::Registry) -> HashMap<String, CarInfo> {
     let mut map = HashMap::new();
     let mut fi = Reader::new(settings.open_relative("car.prm"));
     fi.advance();
@@ -175,20 +182,20 @@ pub fn load_registry(
     for i in 0..num_main + num_ruffa + num_const {
         let (name, data) = fi.next_entry();
         let mi = &reg.model_infos[name];
-        let mut prm_path = settings.data_path.join(&mi.path).with_extension("prm");
-        let is_default = !prm_path.exists();
+        let mut prm_path = Path::new(&mi.path).with_extension("prm");
+        let is_default = !settings.check_path(prm_path.to_str().unwrap());
         if is_default {
             warn!("Vehicle {} doesn't have parameters, using defaults", name);
             prm_path.set_file_name("default");
         }
-        let physics = CarPhysics::load(File::open(prm_path).unwrap());
+        let physics = CarPhysics::load(settings.open_relative(prm_path.to_str().unwrap()));
         let scale = if is_default {
             mi.scale
         } else {
             physics.scale_size
         };
         let file = settings.open_relative(&mi.path);
-        let model = model::load_m3d(file, device, object, settings.game.physics.shape_sampling);
+        let model = model::prepare_m3d(file, settings.game.physics.shape_sampling);
         map.insert(
             name.to_owned(),
             CarInfo {
@@ -1,6 +1,6 @@
-use crate::config::text::Reader;
+use crate::config::{settings::Settings, text::Reader};
 
-use std::fs::File;
+use std::io::Read;
 
 // see `src/runtime.h` for original defines
 pub const MAIN_LOOP_TIME: f32 = 0.05;
@@ -70,9 +70,9 @@ pub struct Helicopter {
     pub traction_decr: f32,
     pub z_offset: f32,
     pub ampl: f32,
-    pub dphi: u16,
+    pub dphi: Angle,
     pub circle_radius: [f32; 2],
-    pub circle_dphi: u16,
+    pub circle_dphi: Angle,
 }
 
 pub struct Drag {
@@ -93,9 +93,69 @@ pub struct Drag {
     pub abs_min: VelocityPair,
 }
 
+// How a terrain type affects the vehicles touching it,
+// as multipliers of the common coefficients.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    // sideways grip of the wheels and the friction of the springs
+    pub friction: f32,
+    // extra linear drag per original frame
+    pub drag: f32,
+    // how deep the body sinks before the springs hold it
+    pub softness: f32,
+}
+
+impl Material {
+    pub const PLAIN: Self = Material {
+        friction: 1.0,
+        drag: 1.0,
+        softness: 1.0,
+    };
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::PLAIN
+    }
+}
+
 pub struct Terrain {
     pub dz_max: f32,
     pub min_wall_delta: f32,
+    // indexed by the terrain type
+    pub materials: Vec<Material>,
+}
+
+// Number of the terrain types in the original levels.
+pub const NUM_TERRAIN_TYPES: usize = 8;
+// Type of the loose ground, slowing the vehicles down with `drag.stuff`.
+const STUFF_TERRAIN_TYPE: usize = NUM_TERRAIN_TYPES - 1;
+
+impl Terrain {
+    // Types beyond the table are plain ground.
+    pub fn material(&self, terrain_type: u8) -> &Material {
+        self.materials
+            .get(terrain_type as usize)
+            .unwrap_or(&Material::PLAIN)
+    }
+}
+
+// The water bed (type 0) is a swamp, and the last type is the loose stuff,
+// the rest of the types are plain ground.
+fn default_materials(drag: &Drag) -> Vec<Material> {
+    (0..NUM_TERRAIN_TYPES)
+        .map(|terrain_type| match terrain_type {
+            0 => Material {
+                drag: drag.swamp,
+                ..Material::default()
+            },
+            STUFF_TERRAIN_TYPE => Material {
+                drag: drag.stuff,
+                ..Material::default()
+            },
+            _ => Material::default(),
+        })
+        .collect()
 }
 
 pub struct Mole {
@@ -134,6 +194,7 @@ pub struct Force {
 
 pub struct Speed {
     pub standard_frame_rate: u32,
+    // time constant of following the frame time, in seconds
     pub speed_correction_tau: f32,
 }
 
@@ -151,7 +212,7 @@ pub struct Common {
     pub speed: Speed,
 }
 
-fn get_pair(reader: &mut Reader<File>, name: &str) -> VelocityPair {
+fn get_pair<I: Read>(reader: &mut Reader<I>, name: &str) -> VelocityPair {
     let sv = format!("V_{}:", name);
     let sw = format!("W_{}:", name);
     VelocityPair {
@@ -160,8 +221,8 @@ fn get_pair(reader: &mut Reader<File>, name: &str) -> VelocityPair {
     }
 }
 
-pub fn load(file: File) -> Common {
-    let mut fi = Reader::new(file);
+pub fn load<I: Read>(input: I) -> Common {
+    let mut fi = Reader::new(input);
     fi.advance();
     assert_eq!(fi.cur(), "COMMON:\t\t2");
     let traction_scale = 1.0 / 64.0;
@@ -170,7 +231,7 @@ pub fn load(file: File) -> Common {
         const PI_BITS: usize = 11;
         PI / (1 << PI_BITS) as f32
     };
-    Common {
+    let mut common = Common {
         nature: Nature {
             gravity: fi.next_key_value("g:"),
             density: fi.next_key_value("density:"),
@@ -229,12 +290,12 @@ pub fn load(file: File) -> Common {
             traction_decr: fi.next_key_value("heli_traction_decr:"),
             z_offset: fi.next_key_value("heli_z_offset:"),
             ampl: fi.next_key_value("helicopter_ampl:"),
-            dphi: fi.next_key_value("helicopter_dphi:"),
+            dphi: fi.next_key_value::<u16>("helicopter_dphi:") as f32 * angle_scale,
             circle_radius: [
                 fi.next_key_value("helicopter_circle_radius_x:"),
                 fi.next_key_value("helicopter_circle_radius_y:"),
             ],
-            circle_dphi: fi.next_key_value("helicopter_circle_dphi:"),
+            circle_dphi: fi.next_key_value::<u16>("helicopter_circle_dphi:") as f32 * angle_scale,
         },
         drag: Drag {
             speed: get_pair(&mut fi, "drag_speed"),
@@ -256,6 +317,7 @@ pub fn load(file: File) -> Common {
         terrain: Terrain {
             dz_max: fi.next_key_value("dZ_max:"),
             min_wall_delta: fi.next_key_value("MIN_WALL_DELTA:"),
+            materials: Vec::new(),
         },
         mole: Mole {
             k_elastic_mole: fi.next_key_value("k_elastic_mole:"),
@@ -290,10 +352,45 @@ pub fn load(file: File) -> Common {
             side_impulse_delay: fi.next_key_value("side_impulse_delay:"),
             side_impulse_duration: fi.next_key_value("side_impulse_duration:"),
         },
-        //TODO: actually read from the config
-        speed: Speed {
-            standard_frame_rate: 14,
-            speed_correction_tau: 1.6e-2,
+        // some versions of the file don't have this section
+        speed: match fi.try_next_key_value("standard_frame_rate:") {
+            Some(standard_frame_rate) => Speed {
+                standard_frame_rate,
+                speed_correction_tau: fi.next_key_value("speed_correction_tau:"),
+            },
+            None => {
+                warn!("No speed section found, using the defaults");
+                Speed {
+                    standard_frame_rate: 14,
+                    speed_correction_tau: 1.6e-2,
+                }
+            }
         },
+    };
+    common.terrain.materials = default_materials(&common.drag);
+    common
+}
+
+// Loads the parameters of the game data with the overrides of the settings.
+pub fn load_with(settings: &Settings) -> Common {
+    let mut common = load(settings.open_relative("common.prm"));
+    if let Some(ref materials) = settings.game.physics.terrain_materials {
+        if materials.len() < NUM_TERRAIN_TYPES {
+            panic!(
+                "Only {} terrain materials are given, while there are {} terrain types",
+                materials.len(),
+                NUM_TERRAIN_TYPES
+            );
+        }
+        for (terrain_type, material) in materials.iter().enumerate() {
+            if material.softness <= 0.0 {
+                panic!(
+                    "Softness of the terrain type {} has to be positive, got {}",
+                    terrain_type, material.softness
+                );
+            }
+        }
+        common.terrain.materials = materials.clone();
     }
+    common
 }
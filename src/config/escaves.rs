@@ -1,6 +1,6 @@
 use crate::config::text::Reader;
 
-use std::fs::File;
+use std::io::Read;
 
 #[derive(Debug, Deserialize)]
 pub struct ItemSource {
@@ -17,9 +17,9 @@ pub struct Escave {
     pub need_items: Vec<ItemSource>,
 }
 
-pub fn load(file: File) -> Vec<Escave> {
+pub fn load<I: Read>(input: I) -> Vec<Escave> {
     let mut escaves = Vec::new();
-    let mut fi = Reader::new(file);
+    let mut fi = Reader::new(input);
     fi.advance();
     assert_eq!(fi.cur(), "uniVang-ParametersFile_Ver_1");
 
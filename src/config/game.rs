@@ -1,6 +1,6 @@
 use crate::{config::text::Reader, config::Settings};
 
-use std::collections::HashMap;
+use std::{collections::HashMap, io::Read};
 
 pub struct ModelInfo {
     pub path: String,
@@ -13,10 +13,14 @@ pub struct Registry {
 
 impl Registry {
     pub fn load(settings: &Settings) -> Registry {
+        Self::read(settings.open_relative("game.lst"))
+    }
+
+    pub fn read<I: Read>(input: I) -> Registry {
         let mut reg = Registry {
             model_infos: HashMap::new(),
         };
-        let mut fi = Reader::new(settings.open_relative("game.lst"));
+        let mut fi = Reader::new(input);
 
         while !fi.cur().starts_with("NumModel") {
             fi.advance();
@@ -5,6 +5,7 @@ pub mod escaves;
 pub mod game;
 pub mod settings;
 mod text;
+pub mod vfs;
 pub mod worlds;
 
 pub use self::settings::Settings;
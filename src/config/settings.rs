@@ -1,6 +1,10 @@
-use crate::render::object::BodyColor;
+use crate::{
+    config::{common::Material, vfs},
+    render::object::BodyColor,
+};
 
 use std::fs::File;
+use std::io;
 use std::path::PathBuf;
 
 #[derive(Deserialize)]
@@ -8,6 +12,37 @@ pub struct Car {
     pub id: String,
     pub color: BodyColor,
     pub slots: Vec<String>,
+    #[serde(default)]
+    pub controller: Controller,
+}
+
+// Source of the inputs of an agent.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum Controller {
+    Keyboard,
+    // built-in AI, with the profile from `Other::ai`
+    Ai,
+    // inputs of one of the agents from a recorded session,
+    // selected by its slot, which stays the same until the agent is removed
+    Replay { path: PathBuf, agent: usize },
+    // a process reading the observations from stdin and writing the inputs to stdout
+    External { command: String, args: Vec<String> },
+}
+
+impl Controller {
+    // Controller of the NPC with the given number, or the built-in AI if none are set.
+    pub fn pick(controllers: &[Controller], index: usize) -> Self {
+        match controllers.len() {
+            0 => Controller::Ai,
+            count => controllers[index % count].clone(),
+        }
+    }
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Controller::Keyboard
+    }
 }
 
 #[derive(Deserialize)]
@@ -25,7 +60,7 @@ pub struct Camera {
     pub depth_range: (f32, f32),
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum SpawnAt {
     Player,
     Random,
@@ -35,6 +70,50 @@ pub enum SpawnAt {
 pub struct Other {
     pub count: usize,
     pub spawn_at: SpawnAt,
+    // NPCs further away from the player are replaced by new ones around it
+    #[serde(default)]
+    pub despawn_distance: Option<f32>,
+    // driving profiles, assigned to the NPCs in turn
+    #[serde(default)]
+    pub ai: Vec<Ai>,
+    // assigned to the NPCs in turn
+    #[serde(default)]
+    pub controllers: Vec<Controller>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Ai {
+    // steepest slope to drive over, as the height per distance
+    pub max_slope: f32,
+    // descents steeper than this are taken braking
+    pub brake_slope: f32,
+    // how far ahead the routes are planned
+    pub route_distance: f32,
+    // waypoints closer than this are considered reached
+    pub waypoint_distance: f32,
+    pub avoid_water: bool,
+}
+
+impl Ai {
+    // Profile of the NPC with the given number, or the default one if none are set.
+    pub fn pick(profiles: &[Ai], index: usize) -> Self {
+        match profiles.len() {
+            0 => Ai::default(),
+            count => profiles[index % count].clone(),
+        }
+    }
+}
+
+impl Default for Ai {
+    fn default() -> Self {
+        Ai {
+            max_slope: 0.5,
+            brake_slope: 0.2,
+            route_distance: 600.0,
+            waypoint_distance: 40.0,
+            avoid_water: true,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -49,6 +128,9 @@ pub struct Physics {
     pub max_quant: f32,
     pub shape_sampling: u8,
     pub gpu_collision: Option<GpuCollision>,
+    // per terrain type, overriding the defaults of `common::Terrain`
+    #[serde(default)]
+    pub terrain_materials: Option<Vec<Material>>,
 }
 
 #[derive(Deserialize)]
@@ -59,6 +141,9 @@ pub struct Game {
     pub camera: Camera,
     pub other: Other,
     pub physics: Physics,
+    // random seed for the NPC spawning, picked at start if not set
+    #[serde(default)]
+    pub seed: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -151,6 +236,11 @@ pub struct Render {
 #[derive(Deserialize)]
 pub struct Settings {
     pub data_path: PathBuf,
+    // overlays on top of `data_path`, in the order of priority
+    #[serde(default)]
+    pub mods: Vec<PathBuf>,
+    #[serde(skip)]
+    pub vfs: vfs::FileSystem,
     pub car: Car,
     pub game: Game,
     pub window: Window,
@@ -167,13 +257,14 @@ impl Settings {
             .expect("Unable to open the settings file")
             .read_to_string(&mut string)
             .unwrap();
-        let set: Settings = match ron::de::from_str(&string) {
+        let mut set: Settings = match ron::de::from_str(&string) {
             Ok(set) => set,
             Err(e) => panic!("Unable to parse settings RON.\n\t{}\n\tError: {:?}",
                 "Please check if `config/settings.template.ron` has changed and your local config needs to be adjusted.",
                 e,
             ),
         };
+        set.mount_mods();
 
         if !set.check_path("options.dat") {
             panic!(
@@ -185,31 +276,43 @@ impl Settings {
         set
     }
 
-    pub fn open_relative(&self, path: &str) -> File {
-        File::open(self.data_path.join(path)).expect(&format!("Unable to open game file: {}", path))
+    // Rebuilds the file system after `mods` have changed.
+    pub fn mount_mods(&mut self) {
+        self.vfs = match vfs::FileSystem::new(self.mods.iter().cloned(), self.data_path.clone()) {
+            Ok(vfs) => vfs,
+            Err(e) => panic!("Unable to mount the mods {:?}: {}", self.mods, e),
+        };
+    }
+
+    pub fn open_relative(&self, path: &str) -> vfs::File {
+        self.vfs
+            .open(path)
+            .expect(&format!("Unable to open game file: {}", path))
     }
 
     pub fn check_path(&self, path: &str) -> bool {
-        self.data_path.join(path).exists()
+        self.vfs.exists(path)
+    }
+
+    // Files that are loaded by path, like the level data, can't come from archives.
+    pub fn resolve_path(&self, path: &str) -> io::Result<PathBuf> {
+        self.vfs.resolve(path)
+    }
+
+    pub fn list_dir(&self, path: &str) -> Vec<String> {
+        self.vfs.read_dir(path)
     }
 
-    pub fn open_palette(&self) -> File {
-        let path = self
-            .data_path
-            .join("resource")
-            .join("pal")
-            .join("objects.pal");
-        File::open(path).expect("Unable to open palette")
+    pub fn open_palette(&self) -> vfs::File {
+        self.vfs
+            .open("resource/pal/objects.pal")
+            .expect("Unable to open palette")
     }
 
-    pub fn _open_vehicle_model(&self, name: &str) -> File {
-        let path = self
-            .data_path
-            .join("resource")
-            .join("m3d")
-            .join("mechous")
-            .join(name)
-            .with_extension("m3d");
-        File::open(path).expect(&format!("Unable to open vehicle {}", name))
+    pub fn _open_vehicle_model(&self, name: &str) -> vfs::File {
+        let path = format!("resource/m3d/mechous/{}.m3d", name);
+        self.vfs
+            .open(&path)
+            .expect(&format!("Unable to open vehicle {}", name))
     }
 }
@@ -61,6 +61,21 @@ impl<I: Read> Reader<I> {
         tokens.next().unwrap().parse().unwrap()
     }
 
+    // Same as `next_key_value`, but returns `None` at the end of the input.
+    pub fn try_next_key_value<T>(&mut self, key: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Debug,
+    {
+        if !self.advance() {
+            return None;
+        }
+        let mut tokens = self.line.split_whitespace();
+        let name = tokens.next().unwrap();
+        assert_eq!(name, key);
+        Some(tokens.next().unwrap().parse().unwrap())
+    }
+
     pub fn next_entry<T>(&mut self) -> (&str, Vec<T>)
     where
         T: FromStr,
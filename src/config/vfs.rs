@@ -0,0 +1,240 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+fn clean(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// The original data mixes the case and the separators of the paths.
+fn normalize(path: &str) -> String {
+    clean(path).to_lowercase()
+}
+
+fn find_on_disk(base: &Path, path: &str) -> Option<PathBuf> {
+    let mut current = base.to_path_buf();
+    for part in path.split('/') {
+        let exact = current.join(part);
+        current = if exact.exists() {
+            exact
+        } else {
+            fs::read_dir(&current)
+                .ok()?
+                .filter_map(|entry| entry.ok())
+                .find(|entry| entry.file_name().to_string_lossy().to_lowercase() == part)?
+                .path()
+        };
+    }
+    Some(current)
+}
+
+pub enum Root {
+    Directory(PathBuf),
+    Archive {
+        path: PathBuf,
+        archive: Mutex<zip::ZipArchive<fs::File>>,
+        // normalized name -> name in the archive
+        entries: HashMap<String, String>,
+    },
+}
+
+impl Root {
+    pub fn new(path: PathBuf) -> io::Result<Self> {
+        let is_zip = path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("zip"));
+        if !is_zip {
+            return Ok(Root::Directory(path));
+        }
+
+        let file = fs::File::open(&path)?;
+        let archive = zip::ZipArchive::new(file).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid archive {:?}: {}", path, e),
+            )
+        })?;
+        let entries = archive
+            .file_names()
+            .map(|name| (normalize(name), name.to_string()))
+            .collect::<HashMap<_, _>>();
+        info!("Mounted archive {:?} with {} entries", path, entries.len());
+        Ok(Root::Archive {
+            path,
+            archive: Mutex::new(archive),
+            entries,
+        })
+    }
+
+    fn open(&self, path: &str) -> Option<File> {
+        match *self {
+            Root::Directory(ref base) => {
+                let full = find_on_disk(base, path)?;
+                fs::File::open(full).ok().map(File::Disk)
+            }
+            Root::Archive {
+                ref archive,
+                ref entries,
+                ..
+            } => {
+                let name = entries.get(path)?;
+                let mut archive = archive.lock().unwrap();
+                let mut entry = archive.by_name(name).ok()?;
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data).ok()?;
+                Some(File::Memory(io::Cursor::new(data)))
+            }
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        match *self {
+            Root::Directory(ref base) => find_on_disk(base, path).is_some(),
+            Root::Archive { ref entries, .. } => entries.contains_key(path),
+        }
+    }
+
+    fn list(&self, dir: &str, names: &mut Vec<String>) {
+        match *self {
+            Root::Directory(ref base) => {
+                let full = if dir.is_empty() {
+                    Some(base.clone())
+                } else {
+                    find_on_disk(base, dir)
+                };
+                if let Some(entries) = full.and_then(|full| fs::read_dir(full).ok()) {
+                    names.extend(
+                        entries
+                            .filter_map(|entry| entry.ok())
+                            .map(|entry| entry.file_name().to_string_lossy().into_owned()),
+                    );
+                }
+            }
+            Root::Archive { ref entries, .. } => {
+                let dir_parts = if dir.is_empty() {
+                    Vec::new()
+                } else {
+                    dir.split('/').collect::<Vec<_>>()
+                };
+                for name in entries.values() {
+                    // match the components one by one, keeping the original case of the rest
+                    let clean_name = clean(name);
+                    let parts = clean_name.split('/').collect::<Vec<_>>();
+                    if parts.len() > dir_parts.len()
+                        && parts
+                            .iter()
+                            .zip(&dir_parts)
+                            .all(|(part, dir_part)| part.to_lowercase() == *dir_part)
+                    {
+                        names.push(parts[dir_parts.len()].to_string());
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub enum File {
+    Disk(fs::File),
+    Memory(io::Cursor<Vec<u8>>),
+}
+
+impl Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            File::Disk(ref mut file) => file.read(buf),
+            File::Memory(ref mut cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            File::Disk(ref mut file) => file.seek(pos),
+            File::Memory(ref mut cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+// Ordered list of roots, the first one that has a file wins.
+#[derive(Default)]
+pub struct FileSystem {
+    roots: Vec<Root>,
+}
+
+impl FileSystem {
+    pub fn new<I: IntoIterator<Item = PathBuf>>(mods: I, base: PathBuf) -> io::Result<Self> {
+        let mut roots = mods
+            .into_iter()
+            .map(Root::new)
+            .collect::<io::Result<Vec<_>>>()?;
+        roots.push(Root::Directory(base));
+        Ok(FileSystem { roots })
+    }
+
+    pub fn open(&self, path: &str) -> Option<File> {
+        let path = normalize(path);
+        self.roots.iter().find_map(|root| root.open(&path))
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        let path = normalize(path);
+        self.roots.iter().any(|root| root.exists(&path))
+    }
+
+    // Returns the real path on disk. Files inside archives don't have one,
+    // so they are reported as errors instead of falling through to the next root.
+    pub fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let normalized = normalize(path);
+        for root in &self.roots {
+            match *root {
+                Root::Directory(ref base) => {
+                    if let Some(full) = find_on_disk(base, &normalized) {
+                        return Ok(full);
+                    }
+                }
+                Root::Archive {
+                    path: ref archive_path,
+                    ref entries,
+                    ..
+                } => {
+                    if entries.contains_key(&normalized) {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!(
+                                "{} is inside the archive {:?}, but it's only supported on disk",
+                                path, archive_path
+                            ),
+                        ));
+                    }
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not found", path),
+        ))
+    }
+
+    // Lists the merged contents of a directory across all the roots.
+    pub fn read_dir(&self, path: &str) -> Vec<String> {
+        let dir = normalize(path);
+        let mut names = Vec::new();
+        for root in &self.roots {
+            root.list(&dir, &mut names);
+        }
+        let mut seen = HashSet::new();
+        names.retain(|name| seen.insert(name.to_lowercase()));
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+    }
+}
@@ -1,12 +1,12 @@
 use crate::config::text::Reader;
 
 use std::collections::HashMap;
-use std::fs::File;
+use std::io::Read;
 
 pub type Worlds = HashMap<String, String>;
 
-pub fn load(file: File) -> Worlds {
-    let mut fi = Reader::new(file);
+pub fn load<I: Read>(input: I) -> Worlds {
+    let mut fi = Reader::new(input);
     let count = fi.next_value::<usize>();
     (0..count)
         .map(|_| {
@@ -0,0 +1,59 @@
+use crate::{
+    config::{car::CarInfo, common::Common},
+    physics::collision::Hit,
+};
+
+// Armor and shield of a vehicle, in the units of the original parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Health {
+    pub armor: f32,
+    pub shield: f32,
+    // time until the shield starts regenerating, in the original frames
+    pub drop_timer: f32,
+}
+
+impl Health {
+    pub fn new(car: &CarInfo) -> Self {
+        Health {
+            armor: car.stats.max_armor as f32,
+            shield: car.stats.shield_max as f32,
+            drop_timer: 0.0,
+        }
+    }
+
+    pub fn is_destroyed(&self) -> bool {
+        self.armor <= 0.0
+    }
+
+    // Only strong hits cause damage: the ram power of the hitting side
+    // against the defence of the side that got hit.
+    pub fn hit_damage(hit: &Hit, car: &CarInfo, common: &Common) -> f32 {
+        if !hit.strong {
+            return 0.0;
+        }
+        let defence = car.physics.defence[hit.side as usize];
+        let ratio = (1.0 + hit.ram_power as f32) / (1.0 + defence as f32);
+        let damage = common.contact.k_destroy_level * hit.speed * ratio;
+        damage.min(car.stats.max_damage as f32)
+    }
+
+    // The shield absorbs up to `shield_drop` of each hit, the rest goes to the armor.
+    pub fn take(&mut self, damage: f32, car: &CarInfo) {
+        if damage <= 0.0 {
+            return;
+        }
+        let absorbed = damage.min(self.shield).min(car.stats.shield_drop as f32);
+        self.shield -= absorbed;
+        self.armor -= damage - absorbed;
+        self.drop_timer = car.stats.drop_time as f32;
+    }
+
+    pub fn update(&mut self, frames: f32, car: &CarInfo) {
+        if self.drop_timer > 0.0 {
+            self.drop_timer -= frames;
+        } else {
+            let max = car.stats.shield_max as f32;
+            self.shield = (self.shield + frames * car.stats.shield_regen as f32).min(max);
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    config::{self, common::MAIN_LOOP_TIME},
+    level,
+    physics::{self, get_height, Control},
+    replay,
+};
+
+use cgmath::prelude::*;
+use rand::{rngs::StdRng, Rng as _, SeedableRng as _};
+
+use std::sync::Arc;
+
+// Number of the terrain samples along each side of the patch around the car.
+pub const PATCH_SIZE: usize = 9;
+// Distance between the neighboring samples of the patch.
+const PATCH_STEP: f32 = 8.0;
+// Wheels hovering this close to the ground are still considered touching it.
+const CONTACT_EPSILON: f32 = 2.0;
+// Attempts to find a dry spot to spawn at randomly,
+// before going through the level in order.
+const SPAWN_ATTEMPTS: usize = 100;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Observation {
+    pub position: [f32; 3],
+    // quaternion, with the scalar part first
+    pub orientation: [f32; 4],
+    // in the local space of the car, the same as the physics keeps them
+    pub linear_velocity: [f32; 3],
+    pub angular_velocity: [f32; 3],
+    // heights of the terrain relative to the car, row by row along its heading,
+    // each row going from the left to the right
+    pub terrain: Vec<f32>,
+    // one per wheel of the model
+    pub wheel_contacts: Vec<bool>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Step {
+    pub observation: Observation,
+    // distance driven forward during the step
+    pub reward: f32,
+    // the episode is over, either by the time or by turning over
+    pub done: bool,
+}
+
+// A single vehicle driving on a level with the CPU physics,
+// advancing one fixed tick of the game per step.
+pub struct Environment {
+    level: Arc<level::Level>,
+    common: Arc<config::common::Common>,
+    car: config::car::CarInfo,
+    max_quant: f32,
+    episode_ticks: usize,
+    body: physics::Body,
+    correction: physics::SpeedCorrection,
+    tick: usize,
+}
+
+impl Environment {
+    pub fn new(
+        level: Arc<level::Level>,
+        common: Arc<config::common::Common>,
+        car: config::car::CarInfo,
+        max_quant: f32,
+        episode_ticks: usize,
+    ) -> Self {
+        let body = physics::Body::spawn(&level, (0, 0), cgmath::Rad(0.0), car.scale);
+        let correction = physics::SpeedCorrection::new(MAIN_LOOP_TIME, &common.speed);
+        Environment {
+            level,
+            common,
+            car,
+            max_quant,
+            episode_ticks,
+            body,
+            correction,
+            tick: 0,
+        }
+    }
+
+    // Starts a new episode at a random dry place, determined by the seed.
+    // Returns `None` if the whole level is under water.
+    pub fn reset(&mut self, seed: u64) -> Option<Observation> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let size = self.level.size;
+        let mut coords = (0, 0);
+        for _ in 0..SPAWN_ATTEMPTS {
+            coords = (rng.gen_range(0, size.0), rng.gen_range(0, size.1));
+            if !self.level.is_water(coords) {
+                break;
+            }
+        }
+        if self.level.is_water(coords) {
+            let start = coords.1 * size.0 + coords.0;
+            let count = size.0 * size.1;
+            let index = (0..count)
+                .map(|i| (start + i) % count)
+                .find(|&i| !self.level.is_water((i % size.0, i / size.0)))?;
+            coords = (index % size.0, index / size.0);
+        }
+        let orientation = cgmath::Rad(rng.gen_range(0.0, std::f32::consts::PI * 2.0));
+        self.body = physics::Body::spawn(&self.level, coords, orientation, self.car.scale);
+        self.correction = physics::SpeedCorrection::new(MAIN_LOOP_TIME, &self.common.speed);
+        self.tick = 0;
+        Some(self.observe())
+    }
+
+    pub fn step(&mut self, control: &Control) -> Step {
+        let common = &*self.common;
+        let old_pos = self.body.transform.disp;
+
+        let frames = self.correction.update(MAIN_LOOP_TIME, &common.speed);
+        let time = physics::TickTime {
+            frames,
+            dt: self.correction.time_delta(common),
+            max_quant: self.max_quant,
+        };
+        let input = replay::Input {
+            control: control.clone(),
+            jump: None,
+            reset: false,
+        };
+        let focus_point = cgmath::Point3::from_vec(old_pos);
+        self.body
+            .tick(&time, &self.car, &self.level, common, &input, &focus_point);
+        self.tick += 1;
+
+        let transform = &self.body.transform;
+        let forward = transform.rot * cgmath::Vector3::unit_y();
+        let up = transform.rot * cgmath::Vector3::unit_z();
+        Step {
+            observation: self.observe(),
+            reward: (transform.disp - old_pos).dot(forward),
+            done: self.tick >= self.episode_ticks || up.z < 0.0,
+        }
+    }
+
+    fn ground_height(&self, pos: cgmath::Vector2<f32>) -> f32 {
+        let texel = self.level.get((pos.x.floor() as i32, pos.y.floor() as i32));
+        get_height(texel.top())
+    }
+
+    pub fn observe(&self) -> Observation {
+        let transform = &self.body.transform;
+        let pos = transform.disp.truncate();
+
+        let heading = (transform.rot * cgmath::Vector3::unit_y()).truncate();
+        let forward = if heading.magnitude2() > 0.01 {
+            heading.normalize()
+        } else {
+            cgmath::Vector2::unit_y()
+        };
+        let right = cgmath::vec2(forward.y, -forward.x);
+        let half = (PATCH_SIZE / 2) as f32;
+        let mut terrain = Vec::with_capacity(PATCH_SIZE * PATCH_SIZE);
+        for row in 0..PATCH_SIZE {
+            for column in 0..PATCH_SIZE {
+                let offset = forward * (row as f32 - half) * PATCH_STEP
+                    + right * (column as f32 - half) * PATCH_STEP;
+                terrain.push(self.ground_height(pos + offset) - transform.disp.z);
+            }
+        }
+
+        let wheel_contacts = self
+            .car
+            .model
+            .wheels
+            .iter()
+            .map(|wheel| {
+                let center = transform.transform_point(cgmath::Point3::from(wheel.pos));
+                let bottom = center.z - wheel.radius as f32 * transform.scale;
+                bottom <= self.ground_height(cgmath::vec2(center.x, center.y)) + CONTACT_EPSILON
+            })
+            .collect();
+
+        let rot = transform.rot;
+        Observation {
+            position: transform.disp.into(),
+            orientation: [rot.s, rot.v.x, rot.v.y, rot.v.z],
+            linear_velocity: self.body.dynamo.linear_velocity.into(),
+            angular_velocity: self.body.dynamo.angular_velocity.into(),
+            terrain,
+            wheel_contacts,
+        }
+    }
+}
@@ -1,7 +1,10 @@
-use std::marker::PhantomData;
+use std::{fmt, hash, marker::PhantomData, ops};
 
 type Index = u16;
-type Epoch = u16;
+pub type Epoch = u16;
+
+// Zero is never given out, so that it can mark the free slots.
+const START_EPOCH: Epoch = 1;
 
 pub struct Id<T>(Index, Epoch, PhantomData<T>);
 
@@ -11,42 +14,184 @@ impl<T> Id<T> {
     pub fn index(&self) -> usize {
         self.0 as usize
     }
+
+    pub fn epoch(&self) -> Epoch {
+        self.1
+    }
+}
+
+// Implemented by hand, since the derives would require `T` to implement them.
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> {}
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0 && self.1 == other.1
+    }
+}
+
+impl<T> Eq for Id<T> {}
+
+impl<T> hash::Hash for Id<T> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+        self.1.hash(state);
+    }
 }
 
+impl<T> fmt::Debug for Id<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Id({}, epoch {})", self.0, self.1)
+    }
+}
+
+struct Slot<T> {
+    epoch: Epoch,
+    value: Option<T>,
+}
+
+// Generational arena: the ids stay valid until the value is removed,
+// and the ids of the removed values never match the new ones in the same slot.
 pub struct FreeList<T> {
-    epochs: Vec<Epoch>,
+    slots: Vec<Slot<T>>,
     free: Vec<Index>,
-    marker: PhantomData<T>,
 }
 
 impl<T> FreeList<T> {
     pub fn new() -> Self {
         FreeList {
-            epochs: Vec::new(),
+            slots: Vec::new(),
             free: Vec::new(),
-            marker: PhantomData,
         }
     }
 
-    pub fn alloc(&mut self) -> Id<T> {
+    pub fn insert(&mut self, value: T) -> Id<T> {
         match self.free.pop() {
-            Some(index) => Id(index, self.epochs[index as usize], PhantomData),
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                Id(index, slot.epoch, PhantomData)
+            }
             None => {
-                const START_EPOCH: Epoch = 1;
-                let index = self.epochs.len() as Index;
-                self.epochs.push(START_EPOCH);
+                assert!(
+                    self.slots.len() <= Index::MAX as usize,
+                    "Out of the free list slots"
+                );
+                let index = self.slots.len() as Index;
+                self.slots.push(Slot {
+                    epoch: START_EPOCH,
+                    value: Some(value),
+                });
                 Id(index, START_EPOCH, PhantomData)
             }
         }
     }
 
-    pub fn free(&mut self, id: Id<T>) {
-        assert_eq!(self.epochs[id.0 as usize], id.1);
-        self.epochs[id.0 as usize] += 1;
+    pub fn remove(&mut self, id: Id<T>) -> T {
+        let slot = &mut self.slots[id.index()];
+        assert_eq!(slot.epoch, id.1, "Double free of {:?}", id);
+        slot.epoch = slot.epoch.wrapping_add(1).max(START_EPOCH);
         self.free.push(id.0);
+        slot.value.take().unwrap()
+    }
+
+    // Removing bumps the epoch, so the old ids no longer match.
+    pub fn is_alive(&self, id: Id<T>) -> bool {
+        match self.slots.get(id.index()) {
+            Some(slot) => slot.epoch == id.1,
+            None => false,
+        }
+    }
+
+    // Stale ids are a logic error, caught in debug builds only.
+    pub fn get(&self, id: Id<T>) -> Option<&T> {
+        debug_assert!(self.is_alive(id), "Use of a stale {:?}", id);
+        match self.slots.get(id.index()) {
+            Some(slot) if slot.epoch == id.1 => slot.value.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: Id<T>) -> Option<&mut T> {
+        debug_assert!(self.is_alive(id), "Use of a stale {:?}", id);
+        match self.slots.get_mut(id.index()) {
+            Some(slot) if slot.epoch == id.1 => slot.value.as_mut(),
+            _ => None,
+        }
+    }
+
+    // Live values in the order of the slots.
+    pub fn iter(&self) -> impl Iterator<Item = (Id<T>, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| {
+            let id = Id(i as Index, slot.epoch, PhantomData);
+            slot.value.as_ref().map(|value| (id, value))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Id<T>, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(i, slot)| {
+            let id = Id(i as Index, slot.epoch, PhantomData);
+            slot.value.as_mut().map(|value| (id, value))
+        })
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.slots.iter_mut().filter_map(|slot| slot.value.as_mut())
+    }
+
+    // Epochs of all the slots, with zero in place of the free ones.
+    pub fn epochs(&self) -> Vec<Epoch> {
+        self.slots
+            .iter()
+            .map(|slot| if slot.value.is_some() { slot.epoch } else { 0 })
+            .collect()
+    }
+
+    // Number of the live values.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
     }
 
-    pub fn length(&self) -> usize {
-        self.epochs.len()
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of the slots, including the free ones.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+impl<T> Default for FreeList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ops::Index<Id<T>> for FreeList<T> {
+    type Output = T;
+    fn index(&self, id: Id<T>) -> &T {
+        match self.get(id) {
+            Some(value) => value,
+            None => panic!("Use of a stale {:?}", id),
+        }
+    }
+}
+
+impl<T> ops::IndexMut<Id<T>> for FreeList<T> {
+    fn index_mut(&mut self, id: Id<T>) -> &mut T {
+        match self.get_mut(id) {
+            Some(value) => value,
+            None => panic!("Use of a stale {:?}", id),
+        }
     }
 }
@@ -1,6 +1,6 @@
 use byteorder::{LittleEndian as E, ReadBytesExt, WriteBytesExt};
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 use std::time::Instant;
@@ -96,6 +96,13 @@ impl Level {
         }
     }
 
+    // Shortest offset between two points, as the level wraps around.
+    // The original only wraps the vertical axis at half of the height.
+    pub fn wrap_offset(&self, offset: cgmath::Vector2<f32>) -> cgmath::Vector2<f32> {
+        let wrap = cgmath::vec2(self.size.0 as f32, (self.size.1 >> 1).max(1) as f32);
+        crate::space::wrap_offset(offset, wrap)
+    }
+
     pub fn get(&self, mut coord: (i32, i32)) -> Texel {
         let bits = TerrainBits::new(self.terrains.len() as u8);
         while coord.0 < 0 {
@@ -121,6 +128,21 @@ impl Level {
         }
     }
 
+    // Water altitude of the horizontal section containing the given row.
+    pub fn flood_level(&self, y: i32) -> Altitude {
+        let sections = self.flood_map.len() as i32;
+        let section = (y >> self.flood_section_power).rem_euclid(sections);
+        self.flood_map[section as usize]
+    }
+
+    // The water bed is flooded up to the flood level of its section.
+    pub fn is_water(&self, coords: (i32, i32)) -> bool {
+        match self.get(coords) {
+            Texel::Single(Point(altitude, 0)) => altitude < self.flood_level(coords.1),
+            _ => false,
+        }
+    }
+
     pub fn export(&self) -> Vec<u8> {
         let mut data = vec![0; self.size.0 as usize * self.size.1 as usize * 4];
         for y in 0..self.size.1 {
@@ -164,7 +186,7 @@ fn print_palette(data: &[[u8; 4]], info: &str) {
     print!("\n");
 }
 
-pub fn read_palette(input: File, config: Option<&[TerrainConfig]>) -> [[u8; 4]; 0x100] {
+pub fn read_palette<I: Read>(input: I, config: Option<&[TerrainConfig]>) -> [[u8; 4]; 0x100] {
     let mut file = BufReader::new(input);
     let mut data = [[0; 4]; 0x100];
     for p in data.iter_mut() {
@@ -211,16 +233,21 @@ fn report_time(start: Instant) {
 }
 
 pub fn load_flood(config: &LevelConfig) -> Vec<u8> {
+    match File::open(&config.path_data.with_extension("vpr")) {
+        Ok(file) => read_flood(file, config),
+        Err(_) => {
+            let flood_size = config.size.1.as_value() >> config.section.as_power();
+            vec![0; flood_size as usize]
+        }
+    }
+}
+
+pub fn read_flood<I: Read + Seek>(input: I, config: &LevelConfig) -> Vec<u8> {
     let size = (config.size.0.as_value(), config.size.1.as_value());
     let flood_size = size.1 >> config.section.as_power();
 
     let instant = Instant::now();
     let flood_map = {
-        let vpr_file = match File::open(&config.path_data.with_extension("vpr")) {
-            Ok(file) => file,
-            Err(_) => return vec![0; flood_size as usize],
-        };
-
         info!("Loading flood map...");
         let geo_pow = config.geo.as_power();
         let net_size = size.0 * size.1 >> (2 * geo_pow);
@@ -230,8 +257,8 @@ pub fn load_flood(config: &LevelConfig) -> Vec<u8> {
             + 2 * geo_pow * 4
             + 2 * flood_size * geo_pow * 4) as u64;
         let expected_file_size = flood_offset + (flood_size * 4) as u64;
-        assert_eq!(vpr_file.metadata().unwrap().len(), expected_file_size,);
-        let mut vpr = BufReader::new(vpr_file);
+        let mut vpr = BufReader::new(input);
+        assert_eq!(vpr.seek(SeekFrom::End(0)).unwrap(), expected_file_size);
         vpr.seek(SeekFrom::Start(flood_offset)).unwrap();
         (0..flood_size)
             .map(|_| vpr.read_u32::<E>().unwrap() as u8)
@@ -335,6 +362,11 @@ impl LevelData {
 }
 
 pub fn load_vmc(path: &Path, size: (i32, i32)) -> LevelData {
+    let data = fs::read(path).expect("Unable to open VMC");
+    decode_vmc(&data, size)
+}
+
+pub fn decode_vmc(data: &[u8], size: (i32, i32)) -> LevelData {
     use rayon::prelude::*;
     use splay::Splay;
 
@@ -347,7 +379,7 @@ pub fn load_vmc(path: &Path, size: (i32, i32)) -> LevelData {
         size,
     };
 
-    let mut vmc_base = BufReader::new(File::open(path).expect("Unable to open VMC"));
+    let mut vmc_base = data;
 
     info!("\tLoading compression tables...");
     let mut st_table = Vec::<i32>::with_capacity(size.1 as usize);
@@ -368,18 +400,9 @@ pub fn load_vmc(path: &Path, size: (i32, i32)) -> LevelData {
         .collect::<Vec<_>>()
         .par_chunks_mut(64)
         .for_each(|source_group| {
-            //Note: a separate file per group is required
-            let mut vmc = File::open(path).unwrap();
-            let data_size: i16 = source_group
-                .iter()
-                .map(|(_, (_, &size))| size)
-                .max()
-                .unwrap();
-            let mut data = vec![0u8; data_size as usize];
-            for &mut ((ref mut h_row, ref mut m_row), (offset, &size)) in source_group {
-                vmc.seek(SeekFrom::Start(*offset as u64)).unwrap();
-                vmc.read(&mut data[..size as usize]).unwrap();
-                splay.expand(&data[..size as usize], h_row, m_row);
+            for &mut ((ref mut h_row, ref mut m_row), (&offset, &size)) in source_group {
+                let row = &data[offset as usize..offset as usize + size as usize];
+                splay.expand(row, h_row, m_row);
             }
         });
 
@@ -388,6 +411,11 @@ pub fn load_vmc(path: &Path, size: (i32, i32)) -> LevelData {
 }
 
 pub fn load_vmp(path: &Path, size: (i32, i32)) -> LevelData {
+    let file = File::open(path).expect("Unable to open VMP");
+    read_vmp(file, size)
+}
+
+pub fn read_vmp<I: Read>(input: I, size: (i32, i32)) -> LevelData {
     let total = (size.0 * size.1) as usize;
     let mut level = LevelData {
         height: vec![0u8; total],
@@ -395,7 +423,7 @@ pub fn load_vmp(path: &Path, size: (i32, i32)) -> LevelData {
         size,
     };
 
-    let mut vmp = BufReader::new(File::open(path).expect("Unable to open VMP"));
+    let mut vmp = BufReader::new(input);
     level
         .height
         .chunks_mut(size.0 as _)
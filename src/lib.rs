@@ -10,9 +10,15 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+pub mod ai;
 pub mod config;
-mod freelist;
+pub mod damage;
+pub mod environment;
+pub mod freelist;
 pub mod level;
 pub mod model;
+pub mod physics;
 pub mod render;
+pub mod replay;
+pub mod scene;
 pub mod space;
@@ -6,14 +6,23 @@ use crate::render::{
 use m3d;
 use wgpu::util::DeviceExt as _;
 
-use std::{fs::File, mem, ops::Range, slice, sync::Arc};
+use std::{io::Read, ops::Range, sync::Arc};
 
+#[derive(Clone)]
 pub struct BoundingBox {
     pub min: [f32; 3],
     pub max: [f32; 3],
     pub radius: f32,
 }
 
+// CPU side of a mesh, ready to be uploaded.
+pub struct CpuMesh {
+    pub vertices: Vec<ObjectVertex>,
+    pub offset: [f32; 3],
+    pub bbox: BoundingBox,
+    pub physics: m3d::Physics,
+}
+
 pub struct Mesh {
     pub num_vertices: usize,
     pub vertex_buf: wgpu::Buffer,
@@ -29,6 +38,15 @@ pub struct Polygon {
     pub samples: Range<usize>,
 }
 
+pub struct CpuShape {
+    pub polygons: Vec<Polygon>,
+    pub samples: Vec<RawVertex>,
+    pub vertices: Vec<ShapeVertex>,
+    pub polygon_data: Vec<ShapePolygon>,
+    pub sample_data: Option<Vec<DebugPos>>,
+    pub bounds: m3d::Bounds,
+}
+
 pub struct Shape {
     pub polygons: Vec<Polygon>,
     pub samples: Vec<RawVertex>,
@@ -120,60 +138,106 @@ fn vec_i2f(v: [i32; 3]) -> [f32; 3] {
     [v[0] as f32, v[1] as f32, v[2] as f32]
 }
 
-pub fn load_c3d(
-    raw: m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
-    device: &wgpu::Device,
-) -> Arc<Mesh> {
-    let num_vertices = raw.geometry.polygons.len() * 3;
-    debug!("\tGot {} GPU vertices...", num_vertices);
-    let vertex_size = mem::size_of::<ObjectVertex>();
-    let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("C3D"),
-        size: (num_vertices * vertex_size) as wgpu::BufferAddress,
-        usage: wgpu::BufferUsage::VERTEX,
-        mapped_at_creation: true,
-    });
-    {
-        let mut mapping = vertex_buf.slice(..).get_mapped_range_mut();
-        for (chunk, tri) in mapping
-            .chunks_mut(3 * vertex_size)
-            .zip(&raw.geometry.polygons)
-        {
-            let out_vertices =
-                unsafe { slice::from_raw_parts_mut(chunk.as_mut_ptr() as *mut ObjectVertex, 3) };
-            for (vo, v) in out_vertices.iter_mut().zip(&tri.vertices) {
-                let p = raw.geometry.positions[v.pos as usize];
-                let n = raw.geometry.normals[v.normal as usize];
-                *vo = ObjectVertex {
-                    pos: [p[0], p[1], p[2], 1],
-                    color: tri.material[0],
-                    normal: [n[0], n[1], n[2], 0],
-                };
-            }
+fn prepare_vertices(
+    raw: &m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
+    positions: &[[i8; 3]],
+    normals: &[[i8; 3]],
+) -> Arc<CpuMesh> {
+    let mut vertices = Vec::with_capacity(raw.geometry.polygons.len() * 3);
+    for tri in &raw.geometry.polygons {
+        for v in &tri.vertices {
+            let p = positions[v.pos as usize];
+            let n = normals[v.normal as usize];
+            vertices.push(ObjectVertex {
+                pos: [p[0], p[1], p[2], 1],
+                color: tri.material[0],
+                normal: [n[0], n[1], n[2], 0],
+            });
         }
     }
-    vertex_buf.unmap();
+    debug!("\tGot {} GPU vertices...", vertices.len());
 
-    Arc::new(Mesh {
-        num_vertices,
-        vertex_buf,
+    Arc::new(CpuMesh {
+        vertices,
         offset: vec_i2f(raw.parent_off),
         bbox: BoundingBox {
             min: vec_i2f(raw.bounds.coord_min),
             max: vec_i2f(raw.bounds.coord_max),
             radius: raw.max_radius as f32,
         },
-        physics: raw.physics,
+        physics: raw.physics.clone(),
     })
 }
 
-pub fn load_c3d_shape(
-    raw: m3d::Mesh<m3d::Geometry<m3d::CollisionQuad>>,
+pub fn prepare_c3d(raw: m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>) -> Arc<CpuMesh> {
+    prepare_vertices(&raw, &raw.geometry.positions, &raw.geometry.normals)
+}
+
+pub fn upload_mesh(cpu: &CpuMesh, device: &wgpu::Device) -> Arc<Mesh> {
+    upload_mesh_with_usage(cpu, device, wgpu::BufferUsage::VERTEX)
+}
+
+fn upload_mesh_with_usage(
+    cpu: &CpuMesh,
+    device: &wgpu::Device,
+    usage: wgpu::BufferUsage,
+) -> Arc<Mesh> {
+    let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("C3D"),
+        contents: bytemuck::cast_slice(&cpu.vertices),
+        usage,
+    });
+
+    Arc::new(Mesh {
+        num_vertices: cpu.vertices.len(),
+        vertex_buf,
+        offset: cpu.offset,
+        bbox: cpu.bbox.clone(),
+        physics: cpu.physics.clone(),
+    })
+}
+
+pub fn load_c3d(
+    raw: m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
     device: &wgpu::Device,
+) -> Arc<Mesh> {
+    upload_mesh(&prepare_c3d(raw), device)
+}
+
+// A mesh with the topology of `raw`, which vertices can be rewritten by `write_morphed`.
+pub fn create_morph_target(
+    raw: &m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
+    device: &wgpu::Device,
+) -> Arc<Mesh> {
+    let cpu = prepare_vertices(raw, &raw.geometry.positions, &raw.geometry.normals);
+    upload_mesh_with_usage(
+        &cpu,
+        device,
+        wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+    )
+}
+
+// Fills the morph target with the given (interpolated) vertices, using the topology of `raw`.
+pub fn write_morphed(
+    target: &Mesh,
+    raw: &m3d::Mesh<m3d::Geometry<m3d::DrawTriangle>>,
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    queue: &wgpu::Queue,
+) {
+    let round = |v: &[f32; 3]| [v[0].round() as i8, v[1].round() as i8, v[2].round() as i8];
+    let positions = positions.iter().map(round).collect::<Vec<_>>();
+    let normals = normals.iter().map(round).collect::<Vec<_>>();
+    let cpu = prepare_vertices(raw, &positions, &normals);
+    assert_eq!(cpu.vertices.len(), target.num_vertices);
+    queue.write_buffer(&target.vertex_buf, 0, bytemuck::cast_slice(&cpu.vertices));
+}
+
+pub fn prepare_c3d_shape(
+    raw: m3d::Mesh<m3d::Geometry<m3d::CollisionQuad>>,
     shape_sampling: u8,
     with_sample_buf: bool,
-    object: &ObjectContext,
-) -> Arc<Shape> {
+) -> Arc<CpuShape> {
     debug!("\tTessellating polygons...");
     let mut polygons = Vec::new();
     let mut polygon_data = Vec::with_capacity(raw.geometry.polygons.len());
@@ -248,20 +312,33 @@ pub fn load_c3d_shape(
         samples.extend(cur_samples);
     }
 
-    let vertex_buf = device.create_buffer(&wgpu::BufferDescriptor {
+    let vertices = raw
+        .geometry
+        .positions
+        .iter()
+        .map(|p| [p[0], p[1], p[2], 1])
+        .collect();
+
+    Arc::new(CpuShape {
+        polygons,
+        samples,
+        vertices,
+        polygon_data,
+        sample_data: if with_sample_buf {
+            Some(sample_data)
+        } else {
+            None
+        },
+        bounds: raw.bounds,
+    })
+}
+
+pub fn upload_shape(cpu: &CpuShape, device: &wgpu::Device, object: &ObjectContext) -> Arc<Shape> {
+    let vertex_buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Shape"),
-        size: (raw.geometry.positions.len() * mem::size_of::<ShapeVertex>()) as wgpu::BufferAddress,
+        contents: bytemuck::cast_slice(&cpu.vertices),
         usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::STORAGE,
-        mapped_at_creation: true,
     });
-    {
-        let mut mapping = vertex_buf.slice(..).get_mapped_range_mut();
-        for (vo, p) in mapping.chunks_mut(4).zip(&raw.geometry.positions) {
-            vo[..3].copy_from_slice(unsafe { slice::from_raw_parts(p.as_ptr() as *const u8, 3) });
-            vo[3] = 1;
-        }
-    };
-    vertex_buf.unmap();
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         label: Some("Shape"),
         layout: &object.shape_bind_group_layout,
@@ -272,59 +349,113 @@ pub fn load_c3d_shape(
     });
 
     Arc::new(Shape {
-        polygons,
-        samples,
+        polygons: cpu.polygons.clone(),
+        samples: cpu.samples.clone(),
         vertex_buf,
         bind_group,
         polygon_buf: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("polygons"),
-            contents: bytemuck::cast_slice(&polygon_data),
+            contents: bytemuck::cast_slice(&cpu.polygon_data),
             usage: wgpu::BufferUsage::VERTEX,
         }),
-        sample_buf: if with_sample_buf {
+        sample_buf: cpu.sample_data.as_ref().map(|sample_data| {
             let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("samples"),
-                contents: bytemuck::cast_slice(&sample_data),
+                contents: bytemuck::cast_slice(sample_data),
                 usage: wgpu::BufferUsage::VERTEX,
             });
-            Some((buffer, sample_data.len()))
-        } else {
-            None
-        },
-        bounds: raw.bounds,
+            (buffer, sample_data.len())
+        }),
+        bounds: cpu.bounds.clone(),
     })
 }
 
-pub type VisualModel = m3d::Model<Arc<Mesh>, Arc<Shape>>;
-
-pub fn load_m3d(
-    file: File,
+pub fn load_c3d_shape(
+    raw: m3d::Mesh<m3d::Geometry<m3d::CollisionQuad>>,
     device: &wgpu::Device,
-    object: &ObjectContext,
     shape_sampling: u8,
-) -> VisualModel {
-    let raw = m3d::FullModel::load(file);
+    with_sample_buf: bool,
+    object: &ObjectContext,
+) -> Arc<Shape> {
+    upload_shape(
+        &prepare_c3d_shape(raw, shape_sampling, with_sample_buf),
+        device,
+        object,
+    )
+}
+
+pub type CpuModel = m3d::Model<Arc<CpuMesh>, Arc<CpuShape>>;
+pub type VisualModel = m3d::Model<Arc<Mesh>, Arc<Shape>>;
+
+pub fn prepare_m3d<I: Read>(input: I, shape_sampling: u8) -> CpuModel {
+    let raw = m3d::FullModel::load(input);
 
-    let model = VisualModel {
-        body: load_c3d(raw.body, device),
-        shape: load_c3d_shape(raw.shape, device, shape_sampling, true, object),
+    CpuModel {
+        body: prepare_c3d(raw.body),
+        shape: prepare_c3d_shape(raw.shape, shape_sampling, true),
         bound: raw.bound,
         color: raw.color,
         wheels: raw
             .wheels
             .into_iter()
-            .map(|wheel| wheel.map(|mesh| load_c3d(mesh, device)))
+            .map(|wheel| wheel.map(prepare_c3d))
             .collect(),
         debris: raw
             .debris
             .into_iter()
             .map(|debrie| m3d::Debrie {
-                mesh: load_c3d(debrie.mesh, device),
-                shape: load_c3d_shape(debrie.shape, device, 0, false, object),
+                mesh: prepare_c3d(debrie.mesh),
+                shape: prepare_c3d_shape(debrie.shape, 0, false),
             })
             .collect(),
         slots: m3d::Slot::map_all(raw.slots, |_, _| unreachable!()),
-    };
+    }
+}
+
+pub fn upload_model(cpu: &CpuModel, device: &wgpu::Device, object: &ObjectContext) -> VisualModel {
+    VisualModel {
+        body: upload_mesh(&cpu.body, device),
+        shape: upload_shape(&cpu.shape, device, object),
+        bound: cpu.bound.clone(),
+        color: cpu.color.clone(),
+        wheels: cpu
+            .wheels
+            .iter()
+            .map(|wheel| wheel.clone().map(|mesh| upload_mesh(&mesh, device)))
+            .collect(),
+        debris: cpu
+            .debris
+            .iter()
+            .map(|debrie| m3d::Debrie {
+                mesh: upload_mesh(&debrie.mesh, device),
+                shape: upload_shape(&debrie.shape, device, object),
+            })
+            .collect(),
+        slots: m3d::Slot::map_all(cpu.slots.clone(), |mesh, _| upload_mesh(&mesh, device)),
+    }
+}
+
+pub fn load_m3d<I: Read>(
+    input: I,
+    device: &wgpu::Device,
+    object: &ObjectContext,
+    shape_sampling: u8,
+) -> VisualModel {
+    upload_model(&prepare_m3d(input, shape_sampling), device, object)
+}
+
+pub type RawAnimation = m3d::AnimatedMesh<m3d::Geometry<m3d::DrawTriangle>>;
 
-    model
+pub fn load_a3d<I: Read>(input: I, device: &wgpu::Device) -> (RawAnimation, Vec<Arc<Mesh>>) {
+    let raw = RawAnimation::load(input);
+    debug!("\tUploading {} frames...", raw.meshes.len());
+    let frames = raw
+        .meshes
+        .iter()
+        .map(|mesh| {
+            let cpu = prepare_vertices(mesh, &mesh.geometry.positions, &mesh.geometry.normals);
+            upload_mesh(&cpu, device)
+        })
+        .collect();
+    (raw, frames)
 }
@@ -0,0 +1,219 @@
+//! Collisions between vehicles, done in two phases: the broad one over the
+//! bounding spheres, and the narrow one over the collision shapes.
+//! The candidate pairs of the broad phase come from `space::SpatialGrid`.
+
+use super::{body_mass, rigid_body, Body};
+use crate::config::{
+    car::{CarInfo, Side},
+    common::Common,
+};
+
+use cgmath::prelude::*;
+
+pub struct Sphere {
+    pub center: cgmath::Vector3<f32>,
+    pub radius: f32,
+}
+
+#[derive(Debug)]
+pub struct Contact {
+    pub point: cgmath::Vector3<f32>,
+    // pointing from the first body to the second
+    pub normal: cgmath::Vector3<f32>,
+    pub depth: f32,
+}
+
+struct ContactAccumulator {
+    point: cgmath::Vector3<f32>,
+    normal: cgmath::Vector3<f32>,
+    depth: f32,
+    count: usize,
+}
+
+// Checks the collision samples of one body against the shape of the other,
+// which is treated as convex. Normals are accumulated from `body` to `other`.
+fn probe(
+    body: &Body,
+    car: &CarInfo,
+    other: &Body,
+    other_car: &CarInfo,
+    acc: &mut ContactAccumulator,
+    flip: bool,
+) {
+    let scale = car.physics.scale_bound;
+    let other_scale = other.transform.scale * other_car.physics.scale_bound;
+    let shape = &other_car.model.shape;
+    let normals = shape
+        .polygons
+        .iter()
+        .map(|poly| cgmath::Vector3::from(poly.normal).normalize())
+        .collect::<Vec<_>>();
+    let to_other = other.transform.inverse_transform().unwrap();
+
+    for sample in car.model.shape.samples.iter() {
+        let local = cgmath::Point3::new(sample[0] as f32, sample[1] as f32, sample[2] as f32);
+        let world = body.transform.transform_point(local * scale);
+        let pos = to_other.transform_point(world) / other_car.physics.scale_bound;
+        let mut nearest = None;
+        for (poly, normal) in shape.polygons.iter().zip(&normals) {
+            let dist = (pos - cgmath::Point3::from(poly.middle)).dot(*normal);
+            if dist >= 0.0 {
+                nearest = None;
+                break;
+            }
+            match nearest {
+                Some((d, _)) if d >= dist => {}
+                _ => nearest = Some((dist, *normal)),
+            }
+        }
+        if let Some((dist, normal)) = nearest {
+            let outward = other.transform.rot * normal;
+            acc.point += world.to_vec();
+            acc.normal += if flip { outward } else { -outward };
+            acc.depth = acc.depth.max(-dist * other_scale);
+            acc.count += 1;
+        }
+    }
+}
+
+pub fn find_contact(a: &Body, car_a: &CarInfo, b: &Body, car_b: &CarInfo) -> Option<Contact> {
+    let mut acc = ContactAccumulator {
+        point: cgmath::Vector3::zero(),
+        normal: cgmath::Vector3::zero(),
+        depth: 0.0,
+        count: 0,
+    };
+    probe(a, car_a, b, car_b, &mut acc, false);
+    probe(b, car_b, a, car_a, &mut acc, true);
+    if acc.count == 0 || acc.normal.magnitude2() == 0.0 {
+        return None;
+    }
+    Some(Contact {
+        point: acc.point / acc.count as f32,
+        normal: acc.normal.normalize(),
+        depth: acc.depth,
+    })
+}
+
+// Classifies the direction in the local space of a body.
+pub fn side_of(dir: cgmath::Vector3<f32>) -> Side {
+    let abs = cgmath::vec3(dir.x.abs(), dir.y.abs(), dir.z.abs());
+    if abs.z >= abs.x && abs.z >= abs.y {
+        if dir.z > 0.0 {
+            Side::Upper
+        } else {
+            Side::Lower
+        }
+    } else if abs.y >= abs.x {
+        if dir.y > 0.0 {
+            Side::Front
+        } else {
+            Side::Back
+        }
+    } else {
+        Side::Side
+    }
+}
+
+#[derive(Debug)]
+pub struct Hit {
+    pub point: cgmath::Vector3<f32>,
+    // desired change of the velocity at the point
+    pub velocity: cgmath::Vector3<f32>,
+    // side of the body that got hit
+    pub side: Side,
+    // the approach speed exceeded `strong_double_collision_threshold`
+    pub strong: bool,
+    pub speed: f32,
+    // ram power of the side that hit this body
+    pub ram_power: u16,
+}
+
+// Computes the hits to be applied to both bodies, if they are approaching
+// each other or overlapping.
+pub fn resolve(
+    contact: &Contact,
+    a: &Body,
+    car_a: &CarInfo,
+    b: &Body,
+    car_b: &CarInfo,
+    common: &Common,
+) -> Option<(Hit, Hit)> {
+    let velocity_at = |body: &Body, car: &CarInfo| {
+        let rigid = rigid_body(car, &body.transform, &body.dynamo);
+        let r = body.transform.rot.invert() * (contact.point - body.transform.disp);
+        body.transform.rot * rigid.velocity_at(r)
+    };
+    let approach = (velocity_at(a, car_a) - velocity_at(b, car_b)).dot(contact.normal);
+    // stop the approach and push the overlapping bodies apart
+    let delta = approach + common.contact.k_elastic_db_coll * contact.depth;
+    if delta <= 0.0 {
+        return None;
+    }
+
+    let strong = approach > common.contact.strong_double_collision_threshold;
+    let side_a = side_of(a.transform.rot.invert() * contact.normal);
+    let side_b = side_of(b.transform.rot.invert() * -contact.normal);
+    // the impulse is split by the masses, each scaled by the ram power
+    // of the hitting side against the defence of the side being hit,
+    // so that the stronger rammer is pushed back less, and the factors stay within 0..1
+    let push = |car: &CarInfo, side: Side, other: &CarInfo, other_side: Side| {
+        (1.0 + car.physics.ram_power[side as usize] as f32)
+            / (1.0 + other.physics.defence[other_side as usize] as f32)
+    };
+    let weight_a = body_mass(car_a, a.transform.scale, common) * push(car_a, side_a, car_b, side_b);
+    let weight_b = body_mass(car_b, b.transform.scale, common) * push(car_b, side_b, car_a, side_a);
+    let k_a = weight_b / (weight_a + weight_b);
+    let k_b = 1.0 - k_a;
+
+    Some((
+        Hit {
+            point: contact.point,
+            velocity: contact.normal * (-delta * k_a),
+            side: side_a,
+            strong,
+            speed: approach,
+            ram_power: car_b.physics.ram_power[side_b as usize],
+        },
+        Hit {
+            point: contact.point,
+            velocity: contact.normal * (delta * k_b),
+            side: side_b,
+            strong,
+            speed: approach,
+            ram_power: car_a.physics.ram_power[side_a as usize],
+        },
+    ))
+}
+
+pub fn collide(
+    a: &Body,
+    car_a: &CarInfo,
+    b: &Body,
+    car_b: &CarInfo,
+    common: &Common,
+) -> Option<(Hit, Hit)> {
+    let contact = find_contact(a, car_a, b, car_b)?;
+    resolve(&contact, a, car_a, b, car_b, common)
+}
+
+impl Body {
+    pub fn bounding_sphere(&self, car: &CarInfo) -> Sphere {
+        Sphere {
+            center: self.transform.disp,
+            radius: car.model.body.bbox.radius * self.transform.scale,
+        }
+    }
+
+    pub fn apply_hit(&mut self, car: &CarInfo, hit: &Hit) {
+        let rot_inv = self.transform.rot.invert();
+        let mut rigid = rigid_body(car, &self.transform, &self.dynamo);
+        rigid.push(
+            rot_inv * (hit.point - self.transform.disp),
+            rot_inv * hit.velocity,
+        );
+        let (v, w) = rigid.finish();
+        self.dynamo.linear_velocity = v;
+        self.dynamo.angular_velocity = w;
+    }
+}
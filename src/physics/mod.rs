@@ -0,0 +1,844 @@
+//! Physics port of the original game. Most closely described by the following documents:
+//! - https://people.eecs.berkeley.edu/~jfc/mirtich/thesis/mirtichThesis.pdf
+
+use crate::{config, level, render::debug::LineBuffer, replay, space};
+
+use cgmath::prelude::*;
+
+use std::f32::EPSILON;
+
+pub mod collision;
+pub mod rigid;
+pub mod terrain;
+
+pub use self::rigid::RigidBody;
+pub use self::terrain::{get_height, CollisionData};
+
+const MAX_TRACTION: config::common::Traction = 4.0;
+
+#[derive(Debug)]
+struct AccelerationVectors {
+    f: cgmath::Vector3<f32>, // linear
+    k: cgmath::Vector3<f32>, // angular
+}
+
+// Converts the frame time into the number of the original frames, smoothly
+// following the changes of it, like the original did at varying frame rates.
+// Both the control increments and the physics time are scaled by it.
+// Under the fixed tick it converges to the tick time in the original frames.
+pub struct SpeedCorrection {
+    pub factor: f32,
+}
+
+impl SpeedCorrection {
+    pub fn new(frame_time: f32, speed: &config::common::Speed) -> Self {
+        SpeedCorrection {
+            factor: frame_time * speed.standard_frame_rate as f32,
+        }
+    }
+
+    pub fn update(&mut self, frame_time: f32, speed: &config::common::Speed) -> f32 {
+        let target = frame_time * speed.standard_frame_rate as f32;
+        let blend = 1.0 - (-frame_time / speed.speed_correction_tau).exp();
+        self.factor += (target - self.factor) * blend;
+        self.factor
+    }
+
+    // Physics time step for the number of original frames.
+    pub fn time_delta(&self, common: &config::common::Common) -> f32 {
+        self.factor * common.nature.time_delta0 * common.nature.num_calls_analysis as f32
+    }
+}
+
+// Timing of a single tick of the game.
+#[derive(Clone, Copy, Debug)]
+pub struct TickTime {
+    // number of the original frames, scaling the control
+    pub frames: f32,
+    // physics time of the whole tick
+    pub dt: f32,
+    // the longest physics step
+    pub max_quant: f32,
+}
+
+#[derive(Clone)]
+pub struct Dynamo {
+    pub traction: config::common::Traction,
+    pub rudder: cgmath::Rad<f32>,
+    pub linear_velocity: cgmath::Vector3<f32>,
+    pub angular_velocity: cgmath::Vector3<f32>,
+}
+
+impl Default for Dynamo {
+    fn default() -> Self {
+        Dynamo {
+            traction: 0.,
+            rudder: cgmath::Rad(0.),
+            linear_velocity: cgmath::Vector3::zero(),
+            angular_velocity: cgmath::Vector3::zero(),
+        }
+    }
+}
+
+impl Dynamo {
+    pub fn change_traction(&mut self, delta: config::common::Traction) {
+        self.traction = (self.traction + delta).min(MAX_TRACTION).max(-MAX_TRACTION);
+    }
+
+    pub fn slow_down(&mut self, delta: config::common::Traction) {
+        let old = self.traction;
+        self.change_traction(delta * -old.signum());
+        if old * self.traction < 0.0 {
+            self.traction = 0.0;
+        }
+    }
+}
+
+fn body_mass(car: &config::car::CarInfo, scale: f32, common: &config::common::Common) -> f32 {
+    common.nature.density * car.model.body.physics.volume * scale * scale
+}
+
+fn rigid_body(
+    car: &config::car::CarInfo,
+    transform: &space::Transform,
+    dynamo: &Dynamo,
+) -> rigid::RigidBody {
+    let phys = &car.model.body.physics;
+    let jacobian =
+        cgmath::Matrix3::from(phys.jacobi) * (transform.scale * transform.scale / phys.volume);
+    rigid::RigidBody::new(&jacobian, dynamo.linear_velocity, dynamo.angular_velocity)
+}
+
+pub fn jump_dir(power: f32) -> cgmath::Vector3<f32> {
+    5.0 * power * cgmath::vec3(0.0, 3.0, 10.0).normalize()
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Control {
+    pub motor: f32,
+    pub rudder: f32,
+    pub roll: f32,
+    pub brake: bool,
+    pub turbo: bool,
+    pub mole: bool,
+    pub fly: bool,
+    // raise or lower the flight altitude
+    pub lift: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Flight {
+    // target altitude, only set while flying
+    pub altitude: Option<f32>,
+    // time in the air since the last landing, in the original frames
+    pub time: f32,
+}
+
+#[derive(Clone)]
+pub struct Body {
+    pub transform: space::Transform,
+    pub dynamo: Dynamo,
+    // digging through the terrain in the mole mode, or emerging from it
+    pub underground: bool,
+    pub flight: Flight,
+}
+
+impl Body {
+    pub fn new(transform: space::Transform) -> Self {
+        Body {
+            transform,
+            dynamo: Dynamo::default(),
+            underground: false,
+            flight: Flight::default(),
+        }
+    }
+
+    // Places the body with its center slightly above the top of the terrain.
+    pub fn spawn(
+        level: &level::Level,
+        coords: (i32, i32),
+        orientation: cgmath::Rad<f32>,
+        scale: f32,
+    ) -> Self {
+        let height = get_height(level.get(coords).top()) + 5.; //center offset
+        Body::new(cgmath::Decomposed {
+            scale,
+            disp: cgmath::vec3(coords.0 as f32, coords.1 as f32, height),
+            rot: cgmath::Quaternion::from_angle_z(orientation),
+        })
+    }
+
+    // Puts the body back on its wheels and stops it.
+    pub fn reset(&mut self) {
+        self.transform.rot = cgmath::Quaternion::one();
+        self.dynamo.linear_velocity = cgmath::Vector3::zero();
+        self.dynamo.angular_velocity = cgmath::Vector3::zero();
+    }
+
+    // Breaks the body into the debris pieces of the car, flying away from the center.
+    pub fn explode(
+        &self,
+        car: &config::car::CarInfo,
+        common: &config::common::Common,
+    ) -> Vec<Body> {
+        let up = cgmath::Vector3::unit_z();
+        car.model
+            .debris
+            .iter()
+            .map(|debrie| {
+                let offset = cgmath::Vector3::from(debrie.mesh.offset);
+                let dir = if offset.magnitude2() > 0.0 {
+                    offset.normalize()
+                } else {
+                    up
+                };
+                let mut body = Body::new(space::Transform {
+                    disp: self.transform.transform_vector(offset) + self.transform.disp,
+                    ..self.transform
+                });
+                body.dynamo.linear_velocity =
+                    self.dynamo.linear_velocity + (dir + up) * common.force.explosion.v;
+                body.dynamo.angular_velocity =
+                    self.dynamo.angular_velocity + dir.cross(up) * common.force.explosion.w;
+                body
+            })
+            .collect()
+    }
+
+    // Runs a whole tick of the game: the control is applied once, and the time is
+    // split into the steps of at most `max_quant`. Only the last step rolls, jumps
+    // and resets the body, and then the position is wrapped around the focus point.
+    pub fn tick(
+        &mut self,
+        time: &TickTime,
+        car: &config::car::CarInfo,
+        level: &level::Level,
+        common: &config::common::Common,
+        input: &replay::Input,
+        focus_point: &cgmath::Point3<f32>,
+    ) {
+        self.apply_control(&input.control, time.frames, common);
+        let intermediate = Control {
+            roll: 0.0,
+            ..input.control.clone()
+        };
+        let mut dt = time.dt;
+        while dt > time.max_quant {
+            self.step(
+                time.max_quant,
+                car,
+                level,
+                common,
+                &intermediate,
+                None,
+                None,
+            );
+            dt -= time.max_quant;
+        }
+        if input.reset {
+            self.reset();
+        }
+        self.step(dt, car, level, common, &input.control, input.jump, None);
+        self.wrap_around(level, focus_point);
+    }
+
+    // Moves the body to the copy of the level closest to the focus point.
+    pub fn wrap_around(&mut self, level: &level::Level, focus_point: &cgmath::Point3<f32>) {
+        let offset = cgmath::Point3::from_vec(self.transform.disp) - focus_point;
+        self.transform.disp =
+            focus_point.to_vec() + level.wrap_offset(offset.truncate()).extend(offset.z);
+    }
+
+    pub fn apply_control(&mut self, control: &Control, dt: f32, common: &config::common::Common) {
+        let dynamo = &mut self.dynamo;
+        if control.rudder != 0.0 {
+            let angle = dynamo.rudder.0 + common.car.rudder_step * 2.0 * dt * control.rudder;
+            dynamo.rudder.0 = angle.min(common.car.rudder_max).max(-common.car.rudder_max);
+        }
+        if control.motor != 0.0 {
+            dynamo.change_traction(control.motor * dt * common.car.traction_incr);
+        }
+        if control.brake && dynamo.traction != 0.0 {
+            dynamo.traction *= (-dt).exp2();
+        }
+        // the flight is timed once per tick, in the original frames
+        if self.flight.altitude.is_some() {
+            self.flight.time += dt;
+        }
+    }
+
+    pub fn step(
+        &mut self,
+        dt: f32,
+        car: &config::car::CarInfo,
+        level: &level::Level,
+        common: &config::common::Common,
+        control: &Control,
+        jump: Option<f32>,
+        mut line_buffer: Option<&mut LineBuffer>,
+    ) {
+        let Body {
+            ref mut transform,
+            ref mut dynamo,
+            ref mut underground,
+            ref mut flight,
+        } = *self;
+        let roll = control.roll;
+        let f_turbo = if control.turbo {
+            common.global.k_traction_turbo
+        } else {
+            1.0
+        };
+        let f_brake = if control.brake {
+            common.global.f_brake_max
+        } else {
+            0.0
+        };
+
+        let speed_correction_factor = dt / common.nature.time_delta0;
+        let acc_global = AccelerationVectors {
+            f: cgmath::vec3(0.0, 0.0, -common.nature.gravity),
+            k: cgmath::vec3(0.0, 0.0, 0.0),
+        };
+        let rot_inv = transform.rot.invert();
+        log::debug!("dt {}, num {}", dt, common.nature.num_calls_analysis);
+        // Z axis in the local coordinate space
+        let z_axis = rot_inv * cgmath::Vector3::unit_z();
+        let device_modulation = 1.0;
+        let dt_impulse = 1.0;
+
+        let mut rigid = rigid_body(car, transform, dynamo);
+
+        if let Some(power) = jump {
+            let mass = body_mass(car, transform.scale, common);
+            let f =
+                device_modulation * common.force.k_distance_to_force * dt_impulse / mass.powf(0.3);
+            log::info!("jump mass {:?}, f {:?}", mass, f);
+            //DBV dV = A_g2l*DBV(-Sin(Pi/10)*Sin(psi),-Sin(Pi/10)*Cos(psi),Cos(Pi/10));
+            rigid.vel += f * jump_dir(power);
+        }
+
+        let mut wheels_touch = 0u32;
+        let mut spring_touch = 0;
+
+        let mut float_count = 0;
+        let (mut terrain_immersion, mut water_immersion) = (0.0, 0.0);
+        // share of the body weight that a single fully submerged polygon can hold
+        let float_share = car.physics.k_archimedean * common.nature.gravity
+            / car.model.shape.polygons.len() as f32;
+        let body_height = (car.model.body.bbox.max[2] - car.model.body.bbox.min[2])
+            * transform.scale
+            * car.physics.scale_bound;
+        let stand_on_wheels =
+            z_axis.z > 0.0 && (transform.rot * cgmath::Vector3::unit_x()).z.abs() < 0.7;
+        let modulation = 1.0;
+        let mut acc_cur = AccelerationVectors {
+            f: rot_inv * acc_global.f,
+            k: rot_inv * acc_global.k,
+        };
+
+        // mole digs into any terrain but water, and stays there until it emerges
+        let surface = level.get((transform.disp.x as i32, transform.disp.y as i32));
+        let depth = get_height(surface.top()) - transform.disp.z;
+        let diggable = match surface {
+            level::Texel::Single(level::Point(_, ty))
+            | level::Texel::Dual {
+                high: level::Point(_, ty),
+                ..
+            } => ty != 0,
+        };
+        if control.mole {
+            if !*underground && diggable && depth > -body_height {
+                log::info!("Submerging at depth {}", depth);
+                *underground = true;
+            }
+        } else if *underground && depth < -0.5 * body_height {
+            log::info!("Emerged");
+            *underground = false;
+        }
+        if *underground {
+            // the soil holds the body at the digging depth
+            let fz = if control.mole {
+                let restriction = common.impulse.elastic_restriction;
+                let spring = (common.mole.k_elastic_mole * (depth - body_height))
+                    .min(restriction)
+                    .max(-restriction);
+                let submerge = if depth < body_height {
+                    common.mole.mole_submerging_fz
+                } else {
+                    0.0
+                };
+                common.nature.gravity + spring - submerge
+            } else {
+                common.nature.gravity + common.mole.mole_emerging_fz
+            };
+            acc_cur.f += rot_inv * cgmath::vec3(0.0, 0.0, fz);
+
+            let f_traction = common.mole.k_mole
+                * car.physics.underground_speed_factor
+                * common.global.underground_speed_factor
+                * f_turbo
+                * dynamo.traction;
+            acc_cur.f.y += f_traction;
+            acc_cur.f -= rigid.vel * f_brake;
+            acc_cur.k.z -= common.mole.k_mole_rudder * dynamo.rudder.0 * rigid.vel.y;
+
+            if let Some(ref mut lbuf) = line_buffer {
+                // Orange: center -> surface, Green: center -> vertical force
+                let top = transform.disp + cgmath::vec3(0.0, 0.0, depth);
+                lbuf.add(transform.disp.into(), top.into(), 0xFF800000);
+                let end = transform.disp + cgmath::vec3(0.0, 0.0, fz * 10.0);
+                lbuf.add(transform.disp.into(), end.into(), 0x00FF0000);
+            }
+        }
+
+        // helicopter holds the body at the target altitude until the time runs out
+        let max_fly_time = car.stats.max_fly as f32;
+        if control.fly && !*underground {
+            if flight.altitude.is_none() && flight.time < max_fly_time {
+                log::info!("Taking off");
+                flight.altitude = Some(transform.disp.z + common.heli.height_incr as f32);
+            }
+        }
+        if flight.altitude.is_some() && (!control.fly || flight.time >= max_fly_time) {
+            log::info!("Landing after {} frames in the air", flight.time);
+            flight.altitude = None;
+        }
+        if let Some(ref mut altitude) = flight.altitude {
+            let heli = &common.heli;
+            let change = if control.lift > 0.0 {
+                heli.height_incr
+            } else {
+                heli.height_decr
+            };
+            *altitude = (*altitude + control.lift * change as f32 * speed_correction_factor)
+                .min(heli.max_height as f32)
+                .max(0.0);
+
+            let hover = heli.ampl * (flight.time * heli.dphi).sin();
+            let restriction = common.impulse.elastic_restriction;
+            let lift = (heli.k_thrust * (*altitude + hover - transform.disp.z))
+                .min(restriction)
+                .max(-restriction);
+            // circling around the hovering point
+            let omega = heli.circle_dphi / common.nature.time_delta0;
+            let (sin, cos) = (flight.time * heli.circle_dphi).sin_cos();
+            let f_global = cgmath::vec3(
+                -heli.circle_radius[0] * omega * omega * cos,
+                -heli.circle_radius[1] * omega * omega * sin,
+                common.nature.gravity + lift,
+            );
+            acc_cur.f += rot_inv * f_global;
+            // hanging from the rotor above the center keeps the body level
+            let rotor = cgmath::vec3(0.0, 0.0, heli.z_offset * transform.scale);
+            acc_cur.k += common.nature.gravity * rotor.cross(z_axis);
+
+            let f_thrust = heli.k_thrust
+                * heli.convert[1]
+                * car.physics.air_speed_factor
+                * common.global.air_speed_factor
+                * f_turbo
+                * dynamo.traction;
+            acc_cur.f.y += f_thrust;
+            acc_cur.f.x += heli.k_strife * heli.convert[0] * dynamo.rudder.0;
+            acc_cur.f -= rigid.vel * f_brake;
+            acc_cur.k.z -= heli.k_rotate * dynamo.rudder.0;
+            dynamo.traction *= heli.traction_decr.powf(speed_correction_factor);
+            dynamo.rudder.0 *= heli.rudder_decr.powf(speed_correction_factor);
+
+            if let Some(ref mut lbuf) = line_buffer {
+                // Cyan: center -> target altitude
+                let target = cgmath::vec3(transform.disp.x, transform.disp.y, *altitude);
+                lbuf.add(transform.disp.into(), target.into(), 0x00FFFF00);
+            }
+        }
+        let flying = flight.altitude.is_some();
+
+        // terrain and water don't touch the body underground
+        let polygons = if *underground {
+            &[][..]
+        } else {
+            &car.model.shape.polygons[..]
+        };
+
+        let mut down_minus_up = 0i32;
+        let mut acc_springs = AccelerationVectors {
+            f: cgmath::Vector3::zero(),
+            k: cgmath::Vector3::zero(),
+        };
+        let mut acc_float = AccelerationVectors {
+            f: cgmath::Vector3::zero(),
+            k: cgmath::Vector3::zero(),
+        };
+
+        let mut sum_count = 0usize;
+        let mut sum_rg0 = cgmath::Vector3::zero();
+        let mut sum_df = 0.;
+        // material of the ground under the springs, summed over them
+        let (mut sum_friction, mut sum_drag) = (0.0, 0.0);
+
+        for (bound_poly_id, poly) in polygons.iter().enumerate() {
+            let r =
+                cgmath::Vector3::from(poly.middle) * (transform.scale * car.physics.scale_bound);
+            let rg0 = transform.rot * r;
+            let rglob = rg0 + transform.disp;
+            log::debug!(
+                "\t\tpoly[{}]: normal={:?} scale={} mid={:?} r={:?}",
+                bound_poly_id,
+                poly.normal,
+                transform.scale * car.physics.scale_bound,
+                poly.middle,
+                r
+            );
+            match level.get((rglob.x as i32, rglob.y as i32)) {
+                level::Texel::Single(level::Point(_, 0))
+                | level::Texel::Dual {
+                    low: level::Point(_, 0),
+                    ..
+                } => {
+                    let flood_level = get_height(level.flood_level(rglob.y as i32));
+                    let dz = flood_level - rglob.z;
+                    if dz > 0.0 {
+                        float_count += 1;
+                        water_immersion += dz;
+                        // Archimedean force, proportional to the submerged depth
+                        let df = float_share * (dz / body_height).min(1.0);
+                        acc_float.f.z += df;
+                        acc_float.k.x += rg0.y * df;
+                        acc_float.k.y -= rg0.x * df;
+                        if let Some(ref mut lbuf) = line_buffer {
+                            // Blue: polygon center -> buoyancy force
+                            let up = rglob + cgmath::vec3(0.0, 0.0, df * 10.0);
+                            lbuf.add(rglob.into(), up.into(), 0x0000FF00);
+                        }
+                    }
+                }
+                _ => {}
+            };
+            let poly_norm = cgmath::Vector3::from(poly.normal).normalize();
+            if z_axis.dot(poly_norm) < 0.0 {
+                let cdata = terrain::CollisionData::collide_low(
+                    poly,
+                    &car.model.shape.samples,
+                    car.physics.scale_bound,
+                    transform,
+                    level,
+                    &common.terrain,
+                );
+
+                log::debug!("\t\tcollide_low = {:?}", cdata);
+                terrain_immersion += match cdata.soft {
+                    Some(ref cp) => cp.depth.abs(),
+                    None => 0.0,
+                };
+                terrain_immersion += match cdata.hard {
+                    Some(ref cp) => cp.depth.abs(),
+                    None => 0.0,
+                };
+
+                let origin = transform.disp;
+                let mostly_horisontal = {
+                    let tmp = rigid.velocity_at(r);
+                    tmp.z * tmp.z < tmp.x * tmp.x + tmp.y * tmp.y
+                };
+                match cdata {
+                    terrain::CollisionData {
+                        hard: Some(ref cp), ..
+                    } if mostly_horisontal => {
+                        let r1 =
+                            rot_inv * cgmath::vec3(cp.pos.x - origin.x, cp.pos.y - origin.y, 0.0); // ignore vertical
+                        let pv = rigid.velocity_at(r1);
+                        let normal = {
+                            let bm = car.model.body.bbox.max;
+                            let n = cgmath::vec3(r1.x / bm[0], r1.y / bm[1], r1.z / bm[2]);
+                            n.normalize()
+                        };
+                        let dot = pv.dot(normal);
+                        if dot > 0.0 {
+                            rigid.push(r, normal * (dot * -common.impulse.factors[0] * modulation));
+                        }
+                    }
+                    terrain::CollisionData {
+                        soft: Some(ref cp), ..
+                    } => {
+                        //TODO: let r1 = rot_inv * (cp.pos - origin);
+                        let r1 =
+                            rot_inv * cgmath::vec3(cp.pos.x - origin.x, cp.pos.y - origin.y, rg0.z);
+                        let pv = rigid.velocity_at(r1);
+                        if pv.dot(z_axis) < 0.0 {
+                            let vec = if stand_on_wheels {
+                                // ignore XY
+                                cgmath::vec3(0.0, 0.0, pv.z)
+                            } else {
+                                let projected = poly_norm * poly_norm.dot(pv);
+                                let k_friction =
+                                    (common.impulse.k_friction * cp.material.friction).min(1.0);
+                                k_friction * pv + (1.0 - k_friction) * projected
+                            };
+                            rigid.push(r, vec * (-common.impulse.factors[1] * modulation));
+                        }
+                    }
+                    _ => (),
+                }
+                if let Some(ref cp) = cdata.soft {
+                    let df0 = common.contact.k_elastic_spring * cp.depth * modulation
+                        / cp.material.softness;
+                    let df = df0.min(common.impulse.elastic_restriction);
+                    log::debug!("\t\tbound[{}] dF.z = {}, rg0={:?}", bound_poly_id, df, rg0);
+                    acc_springs.f.z += df;
+                    acc_springs.k.x += rg0.y * df;
+                    acc_springs.k.y -= rg0.x * df;
+                    //let impulse = cgmath::vec3(0., 0., df);
+                    //acc_springs.f += impulse;
+                    //acc_springs.k += rg0.cross(impulse);
+                    if stand_on_wheels {
+                        wheels_touch += 1;
+                    } else {
+                        spring_touch += 1;
+                    }
+                    down_minus_up += 1;
+
+                    sum_count += 1;
+                    sum_rg0 += rg0;
+                    sum_df += df;
+                    sum_friction += cp.material.friction;
+                    sum_drag += cp.material.drag;
+
+                    if let Some(ref mut lbuf) = line_buffer {
+                        // Red: center -> collision point
+                        lbuf.add(transform.disp.into(), rglob.into(), 0xFF000000);
+                        // Yellow: collision point -> linear force
+                        let up = rglob + cgmath::vec3(0.0, 0.0, df0);
+                        lbuf.add(rglob.into(), up.into(), 0xFFFF0000);
+                        // Purple: collision point -> angular force
+                        let end = rglob + df * cgmath::vec3(rg0.y, -rg0.x, 0.0);
+                        lbuf.add(rglob.into(), end.into(), 0xFF00FF00);
+                    }
+                }
+            } else {
+                //TODO: upper average
+                // down_minus_up -= 1;
+            }
+        }
+
+        // landing recharges the helicopter, once it's turned off
+        if !flying && !control.fly && wheels_touch + spring_touch != 0 {
+            flight.time = 0.0;
+        }
+
+        let (ground_friction, ground_drag) = if sum_count != 0 {
+            let kf = 1.0 / sum_count as f32;
+            log::debug!("Avg df {} rg0 {:?}", sum_df * kf, sum_rg0 * kf);
+            (sum_friction * kf, sum_drag * kf)
+        } else {
+            (1.0, 1.0)
+        };
+
+        if wheels_touch + spring_touch != 0 {
+            log::debug!("\tsprings total {:?}", acc_springs);
+            acc_cur.f += rot_inv * acc_springs.f;
+            acc_cur.k += rot_inv * acc_springs.k;
+        }
+        if float_count != 0 {
+            log::debug!(
+                "\tfloat {} polygons, immersion {}, total {:?}",
+                float_count,
+                water_immersion,
+                acc_float
+            );
+            acc_cur.f += rot_inv * acc_float.f;
+            acc_cur.k += rot_inv * acc_float.k;
+        }
+
+        // apply drag
+        let mut v_drag = common.drag.free.v * common.drag.speed.v.powf(rigid.vel.magnitude());
+        let mut w_drag = common.drag.free.w
+            * common
+                .drag
+                .speed
+                .w
+                .powf(rigid.angular_velocity().magnitude2()); //why mag2?
+        if wheels_touch > 0 {
+            //TODO: why `ln()`?
+            let speed = common.drag.wheel_speed.ln()
+                * car.physics.mobility_factor
+                * common.global.speed_factor
+                / car.physics.speed_factor;
+            rigid.vel.y *= (1.0 + speed).powf(speed_correction_factor);
+        }
+
+        log::debug!("\tterrain immersion {}", terrain_immersion);
+        let is_after_collision = false;
+        if wheels_touch != 0 && stand_on_wheels {
+            let f_traction_per_wheel = car.physics.mobility_factor
+                * common.global.mobility_factor
+                * f_turbo
+                * dynamo.traction
+                * ground_friction
+                / (car.model.wheels.len() as f32);
+            let rudder_vec = {
+                let (sin, cos) = dynamo.rudder.sin_cos();
+                cgmath::vec3(cos, -sin, 0.0)
+            };
+            for wheel in car.model.wheels.iter() {
+                let pw = transform.transform_point(cgmath::Point3::from(wheel.pos));
+                let detect_wheel_hits = false;
+                if detect_wheel_hits {
+                    let dist = terrain::get_distance_to_terrain(level, pw);
+                    if dist > 0.0 {
+                        continue;
+                    }
+                }
+
+                let rx_max = if wheel.pos[0] > 0.0 {
+                    car.model.body.bbox.max[0]
+                } else {
+                    car.model.body.bbox.min[0]
+                };
+                let pos = cgmath::vec3(rx_max, wheel.pos[1], wheel.pos[2]) * transform.scale;
+                let pv = rigid.velocity_at(pos);
+
+                acc_cur.f.y += f_traction_per_wheel;
+                acc_cur.f -= pv * f_brake;
+
+                if !is_after_collision {
+                    let dir = if wheel.steer != 0 {
+                        rudder_vec
+                    } else {
+                        cgmath::Vector3::unit_x()
+                    };
+
+                    let dot = dir.dot(pv);
+                    let k_wheel = (common.impulse.k_wheel * ground_friction).min(1.0);
+                    let pulse = rigid.push(pos, dir * (dot * -k_wheel));
+                    if let Some(ref mut lbuf) = line_buffer {
+                        let dest = pw + transform.transform_vector(pulse) * 10.0;
+                        lbuf.add(pw.into(), dest.into(), 0xFFFFFF00);
+                    }
+                }
+            }
+        }
+
+        if float_count != 0 {
+            // water propels and steers the whole body instead of the wheels
+            let f_traction = car.physics.k_water_traction
+                * car.physics.water_speed_factor
+                * common.global.water_speed_factor
+                * f_turbo
+                * dynamo.traction;
+            acc_cur.f.y += f_traction;
+            acc_cur.f -= rigid.vel * f_brake;
+            acc_cur.k.z -= car.physics.k_water_rudder * dynamo.rudder.0 * rigid.vel.y;
+        }
+
+        if spring_touch + wheels_touch != 0 || float_count != 0 {
+            let tmp = cgmath::Vector3::new(
+                0.0,
+                0.0,
+                car.physics.z_offset_of_mass_center * transform.scale,
+            );
+            acc_cur.k -= common.nature.gravity * tmp.cross(z_axis);
+            let vz = z_axis.dot(rigid.vel);
+            if vz < -10.0 {
+                v_drag *= common.drag.z.powf(-vz);
+            }
+        }
+
+        if roll != 0.0 && wheels_touch == 0 && spring_touch != 0 {
+            let df = common.force.f_spring_impulse * speed_correction_factor;
+            let x_edge = if roll > 0.0 {
+                car.model.body.bbox.max[0]
+            } else {
+                car.model.body.bbox.min[0]
+            };
+            rigid.add_raw(
+                cgmath::vec3(0.0, 0.0, df),
+                cgmath::vec3(0.0, df * x_edge * transform.scale, 0.0),
+            );
+        }
+
+        log::debug!("\tcur acc {:?}", acc_cur);
+        rigid.add_raw(acc_cur.f * dt, acc_cur.k * dt);
+        let (mut v_vel, mut w_vel) = rigid.finish();
+
+        //log::debug!("J_inv {:?}, handedness {}", j_inv.transpose(), j_inv.x.cross(j_inv.y).dot(j_inv.z));
+        log::debug!("\tresulting v={:?} w={:?}", v_vel, w_vel);
+        if spring_touch != 0 {
+            v_drag *= common.drag.spring.v;
+            w_drag *= common.drag.spring.w;
+        }
+        if spring_touch + wheels_touch != 0 {
+            v_drag *= ground_drag;
+        }
+        if float_count != 0 {
+            v_drag *= common.drag.float.v;
+            w_drag *= common.drag.float.w;
+        }
+        if *underground {
+            v_drag *= common.drag.mole;
+        }
+        if flying {
+            v_drag *= common.drag.helicopter.v;
+            w_drag *= common.drag.helicopter.w;
+        }
+        let (v_mag, w_mag) = (v_vel.magnitude(), w_vel.magnitude());
+        if stand_on_wheels && v_mag < common.drag.abs_min.v && w_mag < common.drag.abs_min.w {
+            let v_pow = common.drag.abs_min.v / (v_mag + EPSILON);
+            let w_pow = common.drag.abs_min.w / (w_mag + EPSILON);
+            v_drag *= common.drag.coll.v.powf(v_pow);
+            w_drag *= common.drag.coll.w.powf(w_pow);
+        }
+
+        if v_mag * v_drag > common.drag.abs_stop.v || w_mag * w_drag > common.drag.abs_stop.w {
+            let radius = car.model.body.bbox.radius; //approx?
+            let local_z_scaled = z_axis * (radius * common.impulse.rolling_scale);
+            let r_diff_sign = down_minus_up.signum() as f32;
+            let vs = v_vel - r_diff_sign * local_z_scaled.cross(w_vel);
+
+            let angle = cgmath::Rad(-dt * w_mag);
+            let vel_rot_inv = cgmath::Quaternion::from_axis_angle(w_vel / (w_mag + EPSILON), angle);
+            transform.disp += (transform.rot * vs) * dt;
+            transform.rot = transform.rot * vel_rot_inv.invert();
+            v_vel = vel_rot_inv * v_vel;
+            w_vel = vel_rot_inv * w_vel;
+            log::debug!(
+                "\tvs={:?} {:?}\n\t\tdisp {:?} scale {}",
+                vs,
+                transform.rot,
+                transform.disp,
+                transform.scale
+            );
+        }
+        //log::debug!("\tdrag v={} w={}", v_drag, w_drag);
+        v_vel *= v_drag.powf(speed_correction_factor);
+        w_vel *= w_drag.powf(speed_correction_factor);
+
+        if let Some(ref mut lbuf) = line_buffer {
+            // Note: velocity and acceleration are in local space
+            let rot = transform.rot;
+            let ba = transform.disp + cgmath::vec3(3.0, 0.0, 10.0);
+            let xf = ba + rot * acc_cur.f;
+            let xk = ba + rot * acc_cur.k;
+            lbuf.add(ba.into(), xf.into(), 0x0000FF00);
+            lbuf.add(ba.into(), xk.into(), 0xFF00FF00);
+            // Yellow: center -> angular springs total
+            lbuf.add(ba.into(), (ba + acc_springs.k).into(), 0xFFFF0000);
+            let bv = transform.disp + cgmath::vec3(-3.0, 0.0, 10.0);
+            let xv = bv + rot * v_vel;
+            let xw = bv + rot * w_vel * 10.0; //TEMP
+            lbuf.add(bv.into(), xv.into(), 0x00FF0000);
+            lbuf.add(bv.into(), xw.into(), 0x00FFFF00);
+        }
+
+        dynamo.linear_velocity = v_vel;
+        dynamo.angular_velocity = w_vel;
+        // unsteer
+        if dynamo.rudder.0 != 0.0 && wheels_touch != 0 {
+            let change = dynamo.rudder.0 * v_vel.y * dt * common.car.rudder_k_decr;
+            dynamo.rudder.0 -= dynamo.rudder.0.signum() * change.abs();
+        }
+        // slow down
+        dynamo.slow_down(dt * common.car.traction_decr);
+    }
+}
@@ -1,4 +1,4 @@
-use vangers::{config, level, model, space};
+use crate::{config, level, model, space};
 
 use cgmath::prelude::*;
 
@@ -6,6 +6,8 @@ use cgmath::prelude::*;
 pub struct CollisionPoint {
     pub pos: cgmath::Vector3<f32>,
     pub depth: f32,
+    // averaged over the touching samples
+    pub material: config::common::Material,
 }
 
 #[derive(Debug)]
@@ -17,6 +19,7 @@ pub struct CollisionData {
 struct HitAccumulator {
     pos: cgmath::Vector3<f32>,
     depth: f32,
+    material: config::common::Material,
     count: f32,
 }
 
@@ -25,12 +28,20 @@ impl HitAccumulator {
         HitAccumulator {
             pos: cgmath::vec3(0.0, 0.0, 0.0),
             depth: 0.0,
+            material: config::common::Material {
+                friction: 0.0,
+                drag: 0.0,
+                softness: 0.0,
+            },
             count: 0.0,
         }
     }
-    fn add(&mut self, pos: cgmath::Vector3<f32>, depth: f32) {
+    fn add(&mut self, pos: cgmath::Vector3<f32>, depth: f32, material: &config::common::Material) {
         self.pos += pos;
         self.depth += depth;
+        self.material.friction += material.friction;
+        self.material.drag += material.drag;
+        self.material.softness += material.softness;
         self.count += 1.0;
     }
     fn finish(&self, min: f32) -> Option<CollisionPoint> {
@@ -38,6 +49,11 @@ impl HitAccumulator {
             Some(CollisionPoint {
                 pos: self.pos / self.count,
                 depth: self.depth / self.count,
+                material: config::common::Material {
+                    friction: self.material.friction / self.count,
+                    drag: self.material.drag / self.count,
+                    softness: self.material.softness / self.count,
+                },
             })
         } else {
             None
@@ -88,30 +104,32 @@ impl CollisionData {
             let sp = cgmath::Point3::from(*s).cast::<f32>().unwrap();
             let pos = transform.transform_point(sp * scale).to_vec();
             let texel = level.get((pos.x as i32, pos.y as i32));
-            let height = match texel {
-                level::Texel::Single(point) => get_height(point.0),
+            let point = match texel {
+                level::Texel::Single(point) => point,
                 level::Texel::Dual { high, low, .. } => {
                     let middle = get_middle(low.0, high.0);
                     if pos.z > middle {
                         let top = get_height(high.0);
                         if pos.z - middle > top - pos.z {
-                            top
+                            high
                         } else {
                             continue;
                         }
                     } else {
-                        get_height(low.0)
+                        low
                     }
                 }
             };
+            let height = get_height(point.0);
+            let material = terraconf.material(point.1);
             let dz = height - pos.z;
             //log::debug!("\t\t\tSample h={:?} at {:?}, dz={}", height, pos, dz);
             if dz > terraconf.min_wall_delta {
                 //log::debug!("\t\t\tHard touch of {} at {:?}", dz, pos);
-                hard.add(pos, dz);
+                hard.add(pos, dz, material);
             } else if dz > 0.0 {
                 //log::debug!("\t\t\tSoft touch of {} at {:?}", dz, pos);
-                soft.add(pos, dz);
+                soft.add(pos, dz, material);
             }
         }
         CollisionData {
@@ -1,7 +1,8 @@
 use crate::{
     config::{car::CarPhysics, common::Common, settings},
     freelist::{self, FreeList},
-    model::VisualModel,
+    model::CpuModel,
+    physics::Control,
     render::{collision::GpuRange, GpuTransform, Shaders},
     space::Transform,
 };
@@ -12,14 +13,40 @@ use futures::{executor::LocalSpawner, task::LocalSpawn as _, FutureExt};
 use wgpu::util::DeviceExt as _;
 
 use std::{
-    mem, slice,
+    mem,
     sync::{Arc, Mutex, MutexGuard},
 };
 
 const WORK_GROUP_WIDTH: u32 = 32;
 const MAX_WHEELS: usize = 4;
 
-pub type GpuControl = [f32; 4];
+pub type GpuControl = [[f32; 4]; 2];
+
+// Packs the control the way `Body::control` and `Body::control_air` expect it.
+pub fn encode_control(control: &Control, max_fly_time: f32, common: &Common) -> GpuControl {
+    [
+        [
+            control.rudder,
+            control.motor,
+            if control.turbo {
+                common.global.k_traction_turbo
+            } else {
+                1.0
+            },
+            if control.brake {
+                common.global.f_brake_max
+            } else {
+                0.0
+            },
+        ],
+        [
+            if control.fly { 1.0 } else { 0.0 },
+            control.lift,
+            max_fly_time,
+            0.0,
+        ],
+    ]
+}
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -66,9 +93,74 @@ pub struct Data {
 unsafe impl Pod for Data {}
 unsafe impl Zeroable for Data {}
 
+// The part of `Data` following the controls, which changes with the simulation.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct State {
+    engine: [f32; 4],
+    pos_scale: [f32; 4],
+    orientation: [f32; 4],
+    linear: [f32; 4],
+    angular: [f32; 4],
+    collision: [f32; 4],
+}
+unsafe impl Pod for State {}
+unsafe impl Zeroable for State {}
+
+// State of a GPU body, as seen by the CPU.
+#[derive(Clone, Debug)]
+pub struct GpuBodyState {
+    pub transform: Transform,
+    // in the local space of the body, like on the CPU
+    pub linear_velocity: cgmath::Vector3<f32>,
+    pub angular_velocity: cgmath::Vector3<f32>,
+    // X = rudder, Y = traction, Z = flight altitude, W = flying time
+    pub engine: [f32; 4],
+    // accumulated terrain springs
+    pub collision: [f32; 4],
+    // XYZ = position, W = steer, only read back
+    pub wheels: [[f32; 4]; MAX_WHEELS],
+}
+
+impl GpuBodyState {
+    fn from_data(data: &Data) -> Self {
+        GpuBodyState {
+            transform: Transform {
+                disp: cgmath::vec3(data.pos_scale[0], data.pos_scale[1], data.pos_scale[2]),
+                rot: cgmath::Quaternion::new(
+                    data.orientation[3],
+                    data.orientation[0],
+                    data.orientation[1],
+                    data.orientation[2],
+                ),
+                scale: data.pos_scale[3],
+            },
+            linear_velocity: cgmath::vec3(data.linear[0], data.linear[1], data.linear[2]),
+            angular_velocity: cgmath::vec3(data.angular[0], data.angular[1], data.angular[2]),
+            engine: data.engine,
+            collision: data.collision,
+            wheels: data.wheels,
+        }
+    }
+
+    fn to_raw(&self) -> State {
+        let gt = GpuTransform::new(&self.transform);
+        let v = self.linear_velocity;
+        let w = self.angular_velocity;
+        State {
+            engine: self.engine,
+            pos_scale: gt.pos_scale,
+            orientation: gt.orientation,
+            linear: [v.x, v.y, v.z, 0.0],
+            angular: [w.x, w.y, w.z, 0.0],
+            collision: self.collision,
+        }
+    }
+}
+
 impl Data {
     const DUMMY: Self = Data {
-        control: [0.0; 4],
+        control: [[0.0; 4]; 2],
         engine: [0.0; 4],
         pos_scale: [0.0, 0.0, 0.0, 1.0],
         orientation: [0.0, 0.0, 0.0, 1.0],
@@ -107,7 +199,7 @@ struct DragConstants {
     abs_stop: [f32; 2],
     coll: [f32; 2],
     other: [f32; 2],
-    _pad: [f32; 2],
+    helicopter: [f32; 2],
 }
 
 #[repr(C)]
@@ -124,6 +216,10 @@ struct Constants {
     drag: DragConstants,
     contact_elastic: [f32; 4],
     force: [f32; 4],
+    heli_height: [f32; 4],
+    heli_thrust: [f32; 4],
+    heli_decr: [f32; 4],
+    heli_circle: [f32; 4],
 }
 unsafe impl Pod for Constants {}
 unsafe impl Zeroable for Constants {}
@@ -246,20 +342,29 @@ impl GpuStoreInit {
 enum Update {
     InitData { index: usize },
     SetControl { index: usize },
+    SetState { index: usize },
 }
 
 struct GpuResult {
     buffer: wgpu::Buffer,
     count: usize,
+    epochs: Vec<freelist::Epoch>,
 }
 
 pub struct GpuStoreMirror {
-    transforms: Vec<Transform>,
+    states: Vec<GpuBodyState>,
+    // epochs of the slots at the time of the copy
+    epochs: Vec<freelist::Epoch>,
 }
 
 impl GpuStoreMirror {
-    pub fn get(&self, body: &GpuBody) -> Option<&Transform> {
-        self.transforms.get(body.index())
+    // Bodies allocated after the copy, or in place of a freed one, are unknown.
+    pub fn get(&self, body: &GpuBody) -> Option<&GpuBodyState> {
+        if self.epochs.get(body.index()) == Some(&body.epoch()) {
+            self.states.get(body.index())
+        } else {
+            None
+        }
     }
 }
 
@@ -276,10 +381,12 @@ pub struct GpuStore {
     bind_group: wgpu::BindGroup,
     bind_group_gather: wgpu::BindGroup,
     bind_group_push: wgpu::BindGroup,
-    free_list: FreeList<Data>,
+    // the initial data of the live bodies, with the latest control
+    bodies: FreeList<Data>,
     updates: Vec<(usize, Update)>,
     update_data: Vec<Data>,
     update_control: Vec<GpuControl>,
+    update_state: Vec<State>,
     pending_pushes: Vec<GpuPush>,
     gpu_result: Option<GpuResult>,
     cpu_mirror: Arc<Mutex<GpuStoreMirror>>,
@@ -467,7 +574,7 @@ impl GpuStore {
                 abs_stop: common.drag.abs_stop.to_array(),
                 coll: common.drag.coll.to_array(),
                 other: [common.drag.wheel_speed, common.drag.z],
-                _pad: [0.0; 2],
+                helicopter: common.drag.helicopter.to_array(),
             },
             contact_elastic: [
                 common.contact.k_elastic_wheel,
@@ -476,6 +583,30 @@ impl GpuStore {
                 common.contact.k_elastic_db_coll,
             ],
             force: [common.force.k_distance_to_force, 0.0, 0.0, 0.0],
+            heli_height: [
+                common.heli.max_height as f32,
+                common.heli.height_incr as f32,
+                common.heli.height_decr as f32,
+                common.heli.z_offset,
+            ],
+            heli_thrust: [
+                common.heli.k_thrust,
+                common.heli.k_rotate,
+                common.heli.k_strife,
+                common.heli.ampl,
+            ],
+            heli_decr: [
+                common.heli.convert[0],
+                common.heli.convert[1],
+                common.heli.rudder_decr,
+                common.heli.traction_decr,
+            ],
+            heli_circle: [
+                common.heli.circle_radius[0],
+                common.heli.circle_radius[1],
+                common.heli.dphi,
+                common.heli.circle_dphi,
+            ],
         };
         let buf_constants = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("body-constants"),
@@ -537,19 +668,26 @@ impl GpuStore {
             bind_group,
             bind_group_gather,
             bind_group_push,
-            free_list: FreeList::new(),
+            bodies: FreeList::new(),
             updates: Vec::new(),
             update_data: Vec::new(),
             update_control: Vec::new(),
+            update_state: Vec::new(),
             pending_pushes: Vec::with_capacity(WORK_GROUP_WIDTH as usize),
             gpu_result: None,
             cpu_mirror: Arc::new(Mutex::new(GpuStoreMirror {
-                transforms: Vec::new(),
+                states: Vec::new(),
+                epochs: Vec::new(),
             })),
         }
     }
 
+    // Only the changes of the control are uploaded.
     pub fn update_control(&mut self, body: &GpuBody, control: GpuControl) {
+        match self.bodies.get_mut(*body) {
+            Some(data) if data.control != control => data.control = control,
+            _ => return,
+        }
         self.updates.push((
             body.index(),
             Update::SetControl {
@@ -559,7 +697,24 @@ impl GpuStore {
         self.update_control.push(control);
     }
 
+    // Overwrites the simulated state, e.g. to teleport or reset the body.
+    pub fn set_state(&mut self, body: &GpuBody, state: &GpuBodyState) {
+        if self.bodies.get(*body).is_none() {
+            return;
+        }
+        self.updates.push((
+            body.index(),
+            Update::SetState {
+                index: self.update_state.len(),
+            },
+        ));
+        self.update_state.push(state.to_raw());
+    }
+
     pub fn add_push(&mut self, body: &GpuBody, vec: cgmath::Vector3<f32>) {
+        if self.bodies.get(*body).is_none() {
+            return;
+        }
         self.pending_pushes.push(GpuPush {
             dir_id: [vec.x, vec.y, vec.z, body.index() as f32],
         });
@@ -577,12 +732,9 @@ impl GpuStore {
     pub fn alloc(
         &mut self,
         transform: &Transform,
-        model: &VisualModel,
+        model: &CpuModel,
         car_physics: &CarPhysics,
     ) -> GpuBody {
-        let id = self.free_list.alloc();
-        assert!(id.index() < self.capacity);
-
         let matrix = cgmath::Matrix3::from(model.body.physics.jacobi)
             .invert()
             .unwrap();
@@ -598,7 +750,7 @@ impl GpuStore {
             wo[3] = if wi.steer != 0 { 1.0 } else { -1.0 };
         }
         let data = Data {
-            control: [0.0, 0.0, 1.0, 0.0],
+            control: [[0.0, 0.0, 1.0, 0.0], [0.0; 4]],
             engine: [0.0; 4],
             pos_scale: gt.pos_scale,
             orientation: gt.orientation,
@@ -633,6 +785,8 @@ impl GpuStore {
             wheels,
         };
 
+        let id = self.bodies.insert(data);
+        assert!(id.index() < self.capacity);
         self.updates.push((
             id.index(),
             Update::InitData {
@@ -643,8 +797,19 @@ impl GpuStore {
         id
     }
 
+    // The slot is zeroed, like the ones past the end, and can be reused right away.
     pub fn free(&mut self, id: GpuBody) {
-        self.free_list.free(id);
+        let index = id.index();
+        self.bodies.remove(id);
+        self.pending_pushes
+            .retain(|push| push.dir_id[3] != index as f32);
+        self.updates.push((
+            index,
+            Update::InitData {
+                index: self.update_data.len(),
+            },
+        ));
+        self.update_data.push(Data::zeroed());
     }
 
     pub fn update_entries(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
@@ -670,6 +835,17 @@ impl GpuStore {
             self.update_control.clear();
             Some(buf)
         };
+        let buf_set_state = if self.update_state.is_empty() {
+            None
+        } else {
+            let buf = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("temp-state"),
+                contents: bytemuck::cast_slice(&self.update_state),
+                usage: wgpu::BufferUsage::COPY_SRC,
+            });
+            self.update_state.clear();
+            Some(buf)
+        };
 
         for (body_id, update) in self.updates.drain(..) {
             let data_size = mem::size_of::<Data>();
@@ -693,6 +869,17 @@ impl GpuStore {
                         size as wgpu::BufferAddress,
                     );
                 }
+                Update::SetState { index } => {
+                    let size = mem::size_of::<State>();
+                    let offset = mem::size_of::<GpuControl>(); // skip control
+                    encoder.copy_buffer_to_buffer(
+                        buf_set_state.as_ref().unwrap(),
+                        (index * size) as wgpu::BufferAddress,
+                        &self.buf_data,
+                        (body_id * data_size + offset) as wgpu::BufferAddress,
+                        size as wgpu::BufferAddress,
+                    );
+                }
             }
         }
     }
@@ -702,6 +889,7 @@ impl GpuStore {
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
         delta: f32,
+        frames: f32,
         raw_ranges: &[GpuRange],
     ) {
         assert!(self.updates.is_empty());
@@ -725,7 +913,7 @@ impl GpuStore {
         }
 
         let num_groups = {
-            let num_objects = self.free_list.length();
+            let num_objects = self.bodies.slot_count();
             let reminder = num_objects % WORK_GROUP_WIDTH as usize;
             let extra = if reminder != 0 { 1 } else { 0 };
             num_objects as u32 / WORK_GROUP_WIDTH + extra
@@ -751,7 +939,7 @@ impl GpuStore {
         // update global uniforms
         {
             let uniforms = Uniforms {
-                delta: [delta, 0.0, 0.0, 0.0],
+                delta: [delta, frames, 0.0, 0.0],
             };
             let temp = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("temp-uniforms"),
@@ -795,59 +983,57 @@ impl GpuStore {
         device: &wgpu::Device,
         encoder: &mut wgpu::CommandEncoder,
     ) {
-        let count = self.free_list.length();
+        let count = self.bodies.slot_count();
+        let size = (count * mem::size_of::<Data>()) as wgpu::BufferAddress;
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Gpu Results"),
-            size: (count * mem::size_of::<GpuTransform>()) as wgpu::BufferAddress,
+            size,
             usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
             mapped_at_creation: false,
         });
-
-        let offset = mem::size_of::<GpuControl>() + mem::size_of::<[f32; 4]>(); // skip control & engine
-        for i in 0..count {
-            encoder.copy_buffer_to_buffer(
-                &self.buf_data,
-                (i * mem::size_of::<Data>() + offset) as wgpu::BufferAddress,
-                &buffer,
-                (i * mem::size_of::<GpuTransform>()) as wgpu::BufferAddress,
-                mem::size_of::<GpuTransform>() as wgpu::BufferAddress,
-            );
+        // the bodies are packed, so the whole range is copied at once
+        if size != 0 {
+            encoder.copy_buffer_to_buffer(&self.buf_data, 0, &buffer, 0, size);
         }
 
-        self.gpu_result = Some(GpuResult { buffer, count })
+        self.gpu_result = Some(GpuResult {
+            buffer,
+            count,
+            epochs: self.bodies.epochs(),
+        })
     }
 
     pub fn consume_gpu_results(&mut self, spawner: &LocalSpawner) {
-        let GpuResult { buffer, count } = match self.gpu_result.take() {
+        let GpuResult {
+            buffer,
+            count,
+            epochs,
+        } = match self.gpu_result.take() {
             Some(gr) => gr,
             None => return,
         };
 
         let latest = Arc::clone(&self.cpu_mirror);
-        let end = (count * mem::size_of::<GpuTransform>()) as wgpu::BufferAddress;
+        if count == 0 {
+            let mut storage = latest.lock().unwrap();
+            storage.states.clear();
+            storage.epochs.clear();
+            return;
+        }
+        let end = (count * mem::size_of::<Data>()) as wgpu::BufferAddress;
         let future = buffer
             .slice(..end)
             .map_async(wgpu::MapMode::Read)
             .map(move |_| {
                 let mapping = buffer.slice(..end).get_mapped_range();
-                let data = unsafe {
-                    slice::from_raw_parts(*mapping.as_ptr() as *const GpuTransform, count)
-                };
-
-                let transforms = data.iter().map(|gt| Transform {
-                    disp: cgmath::vec3(gt.pos_scale[0], gt.pos_scale[1], gt.pos_scale[2]),
-                    rot: cgmath::Quaternion::new(
-                        gt.orientation[3],
-                        gt.orientation[0],
-                        gt.orientation[1],
-                        gt.orientation[2],
-                    ),
-                    scale: gt.pos_scale[3],
-                });
+                let data: &[Data] = bytemuck::cast_slice(&mapping);
 
                 let mut storage = latest.lock().unwrap();
-                storage.transforms.clear();
-                storage.transforms.extend(transforms);
+                storage.states.clear();
+                storage
+                    .states
+                    .extend(data.iter().map(GpuBodyState::from_data));
+                storage.epochs = epochs;
             });
         spawner.spawn_local_obj(Box::new(future).into()).unwrap();
     }
@@ -5,6 +5,7 @@ use crate::{
 };
 
 use bytemuck::{Pod, Zeroable};
+use futures::executor::LocalPool;
 use glsl_to_spirv;
 use wgpu::util::DeviceExt as _;
 
@@ -30,6 +31,40 @@ pub use shadow::FORMAT as SHADOW_FORMAT;
 pub const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
 pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+// Picks the adapter, a software one if asked for, and opens the device on it.
+// Returns `None` if there is no such adapter.
+pub fn request_device(
+    task_pool: &mut LocalPool,
+    instance: &wgpu::Instance,
+    backends: wgpu::BackendBit,
+    compatible_surface: Option<&wgpu::Surface>,
+    software: bool,
+) -> Option<(wgpu::Device, wgpu::Queue)> {
+    info!("Initializing the device");
+    let adapter = if software {
+        instance
+            .enumerate_adapters(backends)
+            .find(|adapter| adapter.get_info().device_type == wgpu::DeviceType::Cpu)?
+    } else {
+        task_pool.run_until(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::Default,
+            compatible_surface,
+        }))?
+    };
+    info!("Using {:?}", adapter.get_info());
+    let device = task_pool
+        .run_until(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+                shader_validation: true,
+            },
+            None,
+        ))
+        .unwrap();
+    Some(device)
+}
+
 pub struct GpuTransform {
     pub pos_scale: [f32; 4],
     pub orientation: [f32; 4],
@@ -50,6 +85,109 @@ pub struct ScreenTargets<'a> {
     pub depth: &'a wgpu::TextureView,
 }
 
+// Color and depth targets outside of the swap chain, with the color readable by the CPU.
+pub struct OffscreenTargets {
+    pub extent: wgpu::Extent3d,
+    color: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    bytes_per_row: u32,
+}
+
+impl OffscreenTargets {
+    pub fn new(device: &wgpu::Device, extent: wgpu::Extent3d) -> Self {
+        let color = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen color"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: COLOR_FORMAT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT | wgpu::TextureUsage::COPY_SRC,
+        });
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen depth"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsage::OUTPUT_ATTACHMENT,
+        });
+        // the rows of a texture copy are aligned
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let bytes_per_row = (extent.width * 4 + align - 1) / align * align;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen readback"),
+            size: (bytes_per_row * extent.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        OffscreenTargets {
+            extent,
+            color_view: color.create_view(&wgpu::TextureViewDescriptor::default()),
+            color,
+            depth_view: depth.create_view(&wgpu::TextureViewDescriptor::default()),
+            buffer,
+            bytes_per_row,
+        }
+    }
+
+    pub fn targets(&self) -> ScreenTargets {
+        ScreenTargets {
+            extent: self.extent,
+            color: &self.color_view,
+            depth: &self.depth_view,
+        }
+    }
+
+    // Copies the color target back, after all the submitted drawing is done.
+    // Returns tightly packed RGBA rows, from the top to the bottom.
+    pub fn read_pixels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::TextureCopyView {
+                texture: &self.color,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::BufferCopyView {
+                buffer: &self.buffer,
+                layout: wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: self.bytes_per_row,
+                    rows_per_image: self.extent.height,
+                },
+            },
+            self.extent,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = self.buffer.slice(..);
+        let future = slice.map_async(wgpu::MapMode::Read);
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(future).expect("Unable to read back the frame");
+
+        let width = self.extent.width as usize;
+        let mut pixels = Vec::with_capacity(width * self.extent.height as usize * 4);
+        {
+            let mapping = slice.get_mapped_range();
+            for row in mapping.chunks(self.bytes_per_row as usize) {
+                // swizzle from `COLOR_FORMAT`, and ignore the alpha like the swap chain does
+                for bgra in row[..width * 4].chunks(4) {
+                    pixels.extend_from_slice(&[bgra[2], bgra[1], bgra[0], 0xFF]);
+                }
+            }
+        }
+        self.buffer.unmap();
+        pixels
+    }
+}
+
 pub struct SurfaceData {
     pub constants: wgpu::Buffer,
     pub height: (wgpu::TextureView, wgpu::Sampler),
@@ -0,0 +1,126 @@
+use crate::{
+    config::{
+        common::Material,
+        settings::{Settings, SpawnAt},
+    },
+    physics::Control,
+};
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+// Everything that affects the simulation besides the per-tick inputs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub seed: u64,
+    pub level: String,
+    pub car: String,
+    pub other_count: usize,
+    pub spawn_at: SpawnAt,
+    #[serde(default)]
+    pub despawn_distance: Option<f32>,
+    pub max_quant: f32,
+    pub shape_sampling: u8,
+    #[serde(default)]
+    pub terrain_materials: Option<Vec<Material>>,
+    #[serde(default)]
+    pub mods: Vec<PathBuf>,
+}
+
+impl Header {
+    pub fn new(settings: &Settings, seed: u64) -> Self {
+        Header {
+            seed,
+            level: settings.game.level.clone(),
+            car: settings.car.id.clone(),
+            other_count: settings.game.other.count,
+            spawn_at: settings.game.other.spawn_at.clone(),
+            despawn_distance: settings.game.other.despawn_distance,
+            max_quant: settings.game.physics.max_quant,
+            shape_sampling: settings.game.physics.shape_sampling,
+            terrain_materials: settings.game.physics.terrain_materials.clone(),
+            mods: settings.mods.clone(),
+        }
+    }
+
+    // Makes the settings produce the same initial state as the recording.
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.game.seed = Some(self.seed);
+        settings.game.level = self.level.clone();
+        settings.car.id = self.car.clone();
+        settings.game.other.count = self.other_count;
+        settings.game.other.spawn_at = self.spawn_at.clone();
+        settings.game.other.despawn_distance = self.despawn_distance;
+        settings.game.physics.max_quant = self.max_quant;
+        settings.game.physics.shape_sampling = self.shape_sampling;
+        settings.game.physics.terrain_materials = self.terrain_materials.clone();
+        if settings.mods != self.mods {
+            settings.mods = self.mods.clone();
+            settings.mount_mods();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    pub control: Control,
+    pub jump: Option<f32>,
+    pub reset: bool,
+}
+
+// Inputs of all the agents for a single simulation tick, indexed by the slots
+// of the agents in the game. The slots of the removed agents are reused by
+// the new ones, and the empty slots have the default input.
+pub type Tick = Vec<Input>;
+
+// The file has the header on the first line, followed by one line per tick,
+// so that a recording survives a crash of the game.
+pub struct Replay {
+    pub header: Header,
+    pub ticks: Vec<Tick>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Self {
+        let file = File::open(path).expect(&format!("Unable to open replay {:?}", path));
+        let mut lines = BufReader::new(file).lines().map(|line| line.unwrap());
+        let header = match lines.next() {
+            Some(line) => ron::de::from_str(&line).expect("Invalid replay header"),
+            None => panic!("Replay {:?} is empty", path),
+        };
+        let ticks = lines
+            .enumerate()
+            .map(|(i, line)| ron::de::from_str(&line).expect(&format!("Invalid replay tick {}", i)))
+            .collect::<Vec<_>>();
+        info!("Loaded replay {:?} with {} ticks", path, ticks.len());
+        Replay { header, ticks }
+    }
+}
+
+pub struct Recorder {
+    output: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn new(path: &Path, header: &Header) -> Self {
+        let file = File::create(path).expect(&format!("Unable to create replay {:?}", path));
+        let mut recorder = Recorder {
+            output: BufWriter::new(file),
+        };
+        recorder.write_line(&ron::ser::to_string(header).unwrap());
+        recorder
+    }
+
+    fn write_line(&mut self, line: &str) {
+        writeln!(self.output, "{}", line).unwrap();
+        self.output.flush().unwrap();
+    }
+
+    pub fn record(&mut self, tick: &[Input]) {
+        let line = ron::ser::to_string(&tick).unwrap();
+        self.write_line(&line);
+    }
+}
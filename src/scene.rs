@@ -0,0 +1,183 @@
+// Synthetic level and vehicle, shared by the physics tests and the parity check.
+use crate::{
+    config::car::{CarInfo, CarPhysics, Kind},
+    level::{Level, TerrainBits},
+    model,
+};
+
+use std::sync::Arc;
+
+pub const LEVEL_SIZE: i32 = 256;
+pub const GROUND: u8 = 64;
+// Half extents of the synthetic vehicle box.
+const HALF: [i8; 3] = [20, 40, 10];
+// Plain ground of the given altitude in a single flood section, without the water bed.
+pub fn make_level<F: Fn(i32, i32) -> u8>(altitude: F) -> Level {
+    let mut level = Level::new_test();
+    level.size = (LEVEL_SIZE, LEVEL_SIZE);
+    level.flood_map = vec![0];
+    level.flood_section_power = 8;
+    level.height = (0..LEVEL_SIZE)
+        .flat_map(|y| (0..LEVEL_SIZE).map(move |x| (x, y)))
+        .map(|(x, y)| altitude(x, y))
+        .collect();
+    let plain = TerrainBits::new(level.terrains.len() as u8).write(1);
+    level.meta = vec![plain; level.height.len()];
+    level
+}
+
+// A box-shaped vehicle, with the collision shape going through the same
+// tessellation as the loaded models, so that both backends get real data.
+pub fn make_car(shape_sampling: u8) -> CarInfo {
+    let [hx, hy, hz] = HALF;
+    let mut positions = Vec::new();
+    let mut polygons = Vec::new();
+    let mut add_quad = |corners: [[i8; 3]; 4], normal: [f32; 3]| {
+        let mut vertices = [0u16; 4];
+        let mut middle = [0i32; 3];
+        for (vertex, corner) in vertices.iter_mut().zip(corners.iter()) {
+            *vertex = match positions.iter().position(|p| p == corner) {
+                Some(index) => index,
+                None => {
+                    positions.push(*corner);
+                    positions.len() - 1
+                }
+            } as u16;
+            for (m, &c) in middle.iter_mut().zip(corner) {
+                *m += c as i32;
+            }
+        }
+        polygons.push(m3d::CollisionQuad {
+            vertices,
+            middle: [
+                (middle[0] / 4) as i8,
+                (middle[1] / 4) as i8,
+                (middle[2] / 4) as i8,
+            ],
+            flat_normal: [
+                (normal[0] * m3d::NORMALIZER) as i8,
+                (normal[1] * m3d::NORMALIZER) as i8,
+                (normal[2] * m3d::NORMALIZER) as i8,
+            ],
+        });
+    };
+    // the bottom is split into 4 quads for a stable contact with the ground
+    for &(x0, x1) in &[(-hx, 0), (0, hx)] {
+        for &(y0, y1) in &[(-hy, 0), (0, hy)] {
+            add_quad(
+                [[x0, y0, -hz], [x1, y0, -hz], [x1, y1, -hz], [x0, y1, -hz]],
+                [0.0, 0.0, -1.0],
+            );
+        }
+    }
+    add_quad(
+        [[-hx, -hy, hz], [hx, -hy, hz], [hx, hy, hz], [-hx, hy, hz]],
+        [0.0, 0.0, 1.0],
+    );
+    // the sides are closed for the collisions between vehicles
+    for &s in &[-1, 1] {
+        add_quad(
+            [
+                [s * hx, -hy, -hz],
+                [s * hx, hy, -hz],
+                [s * hx, hy, hz],
+                [s * hx, -hy, hz],
+            ],
+            [s as f32, 0.0, 0.0],
+        );
+        add_quad(
+            [
+                [-hx, s * hy, -hz],
+                [hx, s * hy, -hz],
+                [hx, s * hy, hz],
+                [-hx, s * hy, hz],
+            ],
+            [0.0, s as f32, 0.0],
+        );
+    }
+
+    let (a, b, c) = (hx as f32, hy as f32, hz as f32);
+    let volume = 8.0 * a * b * c;
+    let physics = m3d::Physics {
+        volume,
+        rcm: [0.0; 3],
+        jacobi: [
+            [volume * (b * b + c * c) / 3.0, 0.0, 0.0],
+            [0.0, volume * (a * a + c * c) / 3.0, 0.0],
+            [0.0, 0.0, volume * (a * a + b * b) / 3.0],
+        ],
+    };
+    let bounds = m3d::Bounds {
+        coord_min: [-hx as i32, -hy as i32, -hz as i32],
+        coord_max: [hx as i32, hy as i32, hz as i32],
+    };
+    let radius = (a * a + b * b + c * c).sqrt();
+
+    let shape = model::prepare_c3d_shape(
+        m3d::Mesh {
+            geometry: m3d::Geometry {
+                positions,
+                normals: Vec::new(),
+                polygons,
+            },
+            bounds: bounds.clone(),
+            parent_off: [0; 3],
+            parent_rot: [0; 3],
+            max_radius: radius as u32,
+            physics: physics.clone(),
+        },
+        shape_sampling,
+        false,
+    );
+    let body = Arc::new(model::CpuMesh {
+        vertices: Vec::new(),
+        offset: [0.0; 3],
+        bbox: model::BoundingBox {
+            min: [-a, -b, -c],
+            max: [a, b, c],
+            radius,
+        },
+        physics,
+    });
+    let wheels = [(-a, b), (a, b), (-a, -b), (a, -b)]
+        .iter()
+        .map(|&(x, y)| m3d::Wheel {
+            mesh: None,
+            steer: if y > 0.0 { 1 } else { 0 },
+            pos: [x, y * 0.75, -c],
+            width: 8,
+            radius: 8,
+            bound_index: 0,
+        })
+        .collect();
+
+    CarInfo {
+        kind: Kind::Main,
+        stats: Default::default(),
+        physics: CarPhysics {
+            name: "Box".to_string(),
+            scale_size: 1.0,
+            scale_bound: 1.0,
+            scale_box: 1.0,
+            speed_factor: 1.0,
+            mobility_factor: 1.0,
+            ..Default::default()
+        },
+        model: model::CpuModel {
+            body,
+            shape,
+            bound: m3d::UpperBound {
+                dimensions: [2 * hx as u32, 2 * hy as u32, 2 * hz as u32],
+                radius: radius as u32,
+            },
+            color: m3d::BodyColor {
+                offset: 0,
+                shift: 0,
+            },
+            wheels,
+            debris: Vec::new(),
+            slots: [m3d::Slot::EMPTY, m3d::Slot::EMPTY, m3d::Slot::EMPTY],
+        },
+        scale: 1.0,
+    }
+}
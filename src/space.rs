@@ -257,3 +257,136 @@ impl Camera {
         self.rot = view.rot;
     }
 }
+
+// The world wraps around, so the offsets are taken to the nearest copy.
+pub fn wrap_offset(
+    offset: cgmath::Vector2<f32>,
+    size: cgmath::Vector2<f32>,
+) -> cgmath::Vector2<f32> {
+    cgmath::vec2(
+        (offset.x + 0.5 * size.x).rem_euclid(size.x) - 0.5 * size.x,
+        (offset.y + 0.5 * size.y).rem_euclid(size.y) - 0.5 * size.y,
+    )
+}
+
+pub struct GridEntry<T> {
+    pub item: T,
+    pub center: cgmath::Vector2<f32>,
+    pub radius: f32,
+}
+
+// Buckets the circles on the horizontal plane of the wrapped world,
+// so that the neighbors are found without going through everything.
+pub struct SpatialGrid<T> {
+    size: cgmath::Vector2<f32>,
+    cell_size: f32,
+    dims: (i32, i32),
+    cells: Vec<Vec<usize>>,
+    entries: Vec<GridEntry<T>>,
+}
+
+impl<T> SpatialGrid<T> {
+    pub fn new(size: cgmath::Vector2<f32>, cell_size: f32) -> Self {
+        let dims = (
+            ((size.x / cell_size).ceil() as i32).max(1),
+            ((size.y / cell_size).ceil() as i32).max(1),
+        );
+        SpatialGrid {
+            size,
+            cell_size,
+            dims,
+            cells: (0..dims.0 * dims.1).map(|_| Vec::new()).collect(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            cell.clear();
+        }
+        self.entries.clear();
+    }
+
+    pub fn offset(
+        &self,
+        from: cgmath::Vector2<f32>,
+        to: cgmath::Vector2<f32>,
+    ) -> cgmath::Vector2<f32> {
+        wrap_offset(to - from, self.size)
+    }
+
+    // Indices of the cells covering the box, each one only once.
+    fn cover(&self, min: cgmath::Vector2<f32>, max: cgmath::Vector2<f32>) -> Vec<usize> {
+        let lo = (
+            (min.x / self.cell_size).floor() as i32,
+            (min.y / self.cell_size).floor() as i32,
+        );
+        let hi = (
+            ((max.x / self.cell_size).floor() as i32).min(lo.0 + self.dims.0 - 1),
+            ((max.y / self.cell_size).floor() as i32).min(lo.1 + self.dims.1 - 1),
+        );
+        let mut cells = Vec::new();
+        for y in lo.1..=hi.1 {
+            let row = y.rem_euclid(self.dims.1) * self.dims.0;
+            for x in lo.0..=hi.0 {
+                cells.push((row + x.rem_euclid(self.dims.0)) as usize);
+            }
+        }
+        cells
+    }
+
+    pub fn insert(&mut self, item: T, center: cgmath::Vector2<f32>, radius: f32) {
+        let index = self.entries.len();
+        let extent = cgmath::vec2(radius, radius);
+        for cell in self.cover(center - extent, center + extent) {
+            self.cells[cell].push(index);
+        }
+        self.entries.push(GridEntry {
+            item,
+            center,
+            radius,
+        });
+    }
+
+    fn candidates(&self, min: cgmath::Vector2<f32>, max: cgmath::Vector2<f32>) -> Vec<usize> {
+        let mut indices = self
+            .cover(min, max)
+            .into_iter()
+            .flat_map(|cell| self.cells[cell].iter().cloned())
+            .collect::<Vec<_>>();
+        indices.sort();
+        indices.dedup();
+        indices
+    }
+
+    // Entries with the circles overlapping the given one, in the insertion order.
+    pub fn query_radius(&self, center: cgmath::Vector2<f32>, radius: f32) -> Vec<&GridEntry<T>> {
+        let extent = cgmath::vec2(radius, radius);
+        self.candidates(center - extent, center + extent)
+            .into_iter()
+            .map(|index| &self.entries[index])
+            .filter(|entry| {
+                let distance = self.offset(center, entry.center).magnitude();
+                distance < radius + entry.radius
+            })
+            .collect()
+    }
+
+    // Entries with the bounding boxes overlapping the given box, in the insertion order.
+    pub fn query_aabb(
+        &self,
+        min: cgmath::Vector2<f32>,
+        max: cgmath::Vector2<f32>,
+    ) -> Vec<&GridEntry<T>> {
+        let center = 0.5 * (min + max);
+        let half = 0.5 * (max - min);
+        self.candidates(min, max)
+            .into_iter()
+            .map(|index| &self.entries[index])
+            .filter(|entry| {
+                let offset = self.offset(center, entry.center);
+                offset.x.abs() <= half.x + entry.radius && offset.y.abs() <= half.y + entry.radius
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,99 @@
+use vangers::{
+    ai::{NavGrid, Pilot},
+    config::settings,
+    level::{Level, TerrainBits},
+    physics::Control,
+    space,
+};
+
+use cgmath::prelude::*;
+
+const CELL_SIZE: i32 = 16;
+
+// Plain ground, with the terrain type and altitude given per texel.
+fn make_level<F: Fn(i32, i32) -> (u8, u8)>(size: (i32, i32), texel: F) -> Level {
+    let mut level = Level::new_test();
+    level.size = size;
+    level.flood_section_power = 8;
+    level.flood_map = vec![100; (size.1 >> 8) as usize];
+    let bits = TerrainBits::new(level.terrains.len() as u8);
+    let texels = (0..size.1)
+        .flat_map(|y| (0..size.0).map(move |x| (x, y)))
+        .map(|(x, y)| texel(x, y))
+        .collect::<Vec<_>>();
+    level.height = texels.iter().map(|&(_, alt)| alt).collect();
+    level.meta = texels.iter().map(|&(ty, _)| bits.write(ty)).collect();
+    level
+}
+
+fn transform_at(x: f32, y: f32) -> space::Transform {
+    space::Transform {
+        scale: 1.0,
+        rot: cgmath::Quaternion::one(),
+        disp: cgmath::vec3(x, y, 40.0),
+    }
+}
+
+#[test]
+fn route_avoids_water() {
+    // a lake across the level, with a single crossing
+    let level = make_level((256, 1024), |x, y| {
+        if (96..160).contains(&y) && !(192..224).contains(&x) {
+            (0, 60)
+        } else {
+            (1, 64)
+        }
+    });
+    let grid = NavGrid::new(&level, CELL_SIZE);
+    assert!(grid.cell(grid.locate(cgmath::vec2(40.0, 120.0))).water);
+    assert!(!grid.cell(grid.locate(cgmath::vec2(200.0, 120.0))).water);
+
+    let profile = settings::Ai::default();
+    let route = grid.find_route(
+        cgmath::vec2(40.0, 40.0),
+        cgmath::vec2(40.0, 220.0),
+        &profile,
+    );
+    assert_eq!(route.last(), Some(&grid.locate(cgmath::vec2(40.0, 220.0))));
+    assert!(route.iter().all(|&cell| !grid.cell(cell).water));
+    let crossing = 192 / CELL_SIZE..224 / CELL_SIZE;
+    assert!(route
+        .iter()
+        .any(|&(x, y)| y == 120 / CELL_SIZE && crossing.contains(&x)));
+
+    let swimmer = settings::Ai {
+        avoid_water: false,
+        ..settings::Ai::default()
+    };
+    let route = grid.find_route(
+        cgmath::vec2(40.0, 40.0),
+        cgmath::vec2(40.0, 220.0),
+        &swimmer,
+    );
+    assert!(route.iter().any(|&cell| grid.cell(cell).water));
+}
+
+#[test]
+fn pilot_brakes_downhill() {
+    let level = make_level((256, 256), |_, y| (1, (255 - y / 2) as u8));
+    let grid = NavGrid::new(&level, CELL_SIZE);
+    let mut control = Control::default();
+
+    let mut pilot = Pilot::new(settings::Ai {
+        brake_slope: 0.2,
+        ..settings::Ai::default()
+    });
+    pilot.drive(&grid, &transform_at(136.0, 40.0), &mut control);
+    assert!(pilot.next_waypoint().is_some());
+    assert!(control.brake);
+    assert_eq!(control.motor, 0.0);
+    assert!(control.rudder.abs() < 0.5);
+
+    let mut pilot = Pilot::new(settings::Ai {
+        brake_slope: 0.4,
+        ..settings::Ai::default()
+    });
+    pilot.drive(&grid, &transform_at(136.0, 40.0), &mut control);
+    assert!(!control.brake);
+    assert_eq!(control.motor, 1.0);
+}
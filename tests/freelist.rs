@@ -0,0 +1,53 @@
+use vangers::freelist::FreeList;
+
+#[test]
+fn reuse_slots() {
+    let mut list = FreeList::new();
+    let a = list.insert("a");
+    let b = list.insert("b");
+    assert_eq!(list.remove(a), "a");
+    let c = list.insert("c");
+
+    assert_eq!(c.index(), a.index());
+    assert_ne!(c, a);
+    assert!(!list.is_alive(a));
+    assert_eq!(list[b], "b");
+    assert_eq!(list[c], "c");
+    assert_eq!(list.len(), 2);
+    assert_eq!(list.slot_count(), 2);
+    assert_eq!(list.iter().collect::<Vec<_>>(), vec![(c, &"c"), (b, &"b")]);
+
+    list.remove(b);
+    assert_eq!(list.epochs(), vec![c.epoch(), 0]);
+    *list.get_mut(c).unwrap() = "d";
+    assert_eq!(list.values().collect::<Vec<_>>(), vec![&"d"]);
+}
+
+#[test]
+#[should_panic]
+fn double_remove() {
+    let mut list = FreeList::new();
+    let a = list.insert(1);
+    list.remove(a);
+    list.remove(a);
+}
+
+#[test]
+#[should_panic]
+fn overflow() {
+    let mut list = FreeList::new();
+    for _ in 0..=0x10000 {
+        list.insert(());
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn stale_access() {
+    let mut list = FreeList::new();
+    let a = list.insert(1);
+    list.remove(a);
+    list.insert(2);
+    list.get(a);
+}
@@ -0,0 +1,695 @@
+use vangers::{
+    config::{
+        car::{CarInfo, Side},
+        common::{self, Common},
+        settings::SpawnAt,
+    },
+    damage::Health,
+    environment::{self, Environment},
+    level::{Level, TerrainBits},
+    physics, replay,
+    scene::{make_car, make_level, GROUND, LEVEL_SIZE},
+    space,
+};
+
+use cgmath::prelude::*;
+
+use std::{fs::File, sync::Arc};
+
+const STEPS: usize = 300;
+
+fn load_common() -> Common {
+    common::load(File::open("tests/data/common.prm").unwrap())
+}
+
+fn half_height(car: &CarInfo) -> f32 {
+    car.model.body.bbox.max[2]
+}
+
+fn simulate(
+    body: &mut physics::Body,
+    steps: usize,
+    car: &CarInfo,
+    level: &Level,
+    common: &Common,
+) -> Vec<cgmath::Vector3<f32>> {
+    let control = physics::Control::default();
+    (0..steps)
+        .map(|_| {
+            body.step(
+                common.nature.time_delta0,
+                car,
+                level,
+                common,
+                &control,
+                None,
+                None,
+            );
+            body.transform.disp
+        })
+        .collect()
+}
+
+fn speed(body: &physics::Body) -> f32 {
+    body.dynamo.linear_velocity.magnitude()
+}
+
+#[test]
+fn rest_on_flat_ground() {
+    let common = load_common();
+    let car = make_car(0);
+    let level = make_level(|_, _| GROUND);
+    let center = LEVEL_SIZE / 2;
+    let mut body = physics::Body::spawn(&level, (center, center), cgmath::Rad(0.0), car.scale);
+
+    simulate(&mut body, STEPS, &car, &level, &common);
+
+    let ground = physics::get_height(GROUND);
+    let bottom = body.transform.disp.z - half_height(&car);
+    assert!(
+        (bottom - ground).abs() < 4.0,
+        "Bottom {} is not on the ground {}",
+        bottom,
+        ground
+    );
+    assert!(speed(&body) < 0.5, "Still moving at {}", speed(&body));
+    let up = body.transform.rot * cgmath::Vector3::unit_z();
+    assert!(up.z > 0.99, "Tilted to {:?}", up);
+    let drift = cgmath::vec2(
+        body.transform.disp.x - center as f32,
+        body.transform.disp.y - center as f32,
+    );
+    assert!(drift.magnitude() < 1.0, "Drifted by {:?}", drift);
+}
+
+#[test]
+fn jump() {
+    let common = load_common();
+    let car = make_car(0);
+    let level = make_level(|_, _| GROUND);
+    let center = LEVEL_SIZE / 2;
+    let mut body = physics::Body::spawn(&level, (center, center), cgmath::Rad(0.0), car.scale);
+    simulate(&mut body, STEPS, &car, &level, &common);
+    let rest = body.transform.disp.z;
+
+    body.step(
+        common.nature.time_delta0,
+        &car,
+        &level,
+        &common,
+        &physics::Control::default(),
+        Some(common.force.max_jump_power),
+        None,
+    );
+    assert!(body.dynamo.linear_velocity.z > 0.0);
+
+    let path = simulate(&mut body, STEPS, &car, &level, &common);
+    let peak = path.iter().map(|p| p.z).fold(rest, f32::max);
+    assert!(peak > rest + 10.0, "Jumped only to {} from {}", peak, rest);
+    // the jump direction is tilted forward
+    assert!(path.last().unwrap().y > center as f32);
+    let landed = path.last().unwrap().z;
+    assert!(
+        (landed - rest).abs() < 4.0,
+        "Landed at {} instead of {}",
+        landed,
+        rest
+    );
+}
+
+#[test]
+fn slide_down_slope() {
+    let common = load_common();
+    let car = make_car(0);
+    // rising towards +Y by one altitude unit per texel
+    let level = make_level(|_, y| y as u8);
+    let center = LEVEL_SIZE / 2;
+    let mut body = physics::Body::spawn(&level, (center, center), cgmath::Rad(0.0), car.scale);
+    // drop it from above, so that it lands on the slope
+    body.transform.disp.z += 20.0;
+    let start = body.transform.disp;
+
+    simulate(&mut body, STEPS, &car, &level, &common);
+
+    let end = body.transform.disp;
+    assert!(
+        end.y < start.y - 10.0,
+        "Didn't slide down: {:?} -> {:?}",
+        start,
+        end
+    );
+    assert!(end.z < start.z, "Didn't descend: {:?} -> {:?}", start, end);
+    assert!((end.x - start.x).abs() < 5.0, "Slid sideways to {:?}", end);
+    assert!(speed(&body) < 0.5, "Still moving at {}", speed(&body));
+}
+
+#[test]
+fn ground_materials() {
+    let mut common = load_common();
+    common.terrain.materials[1].softness = 2.0;
+    let car = make_car(0);
+    let mut level = make_level(|_, _| GROUND);
+    // the first half of the level stays plain ground, the rest is the swamp of type 0
+    let swamp = TerrainBits::new(8).write(0);
+    for meta in level.meta[(LEVEL_SIZE * LEVEL_SIZE / 2) as usize..].iter_mut() {
+        *meta = swamp;
+    }
+    let center = LEVEL_SIZE / 2;
+
+    // settles down, then gets pushed forward and coasts
+    let coast = |y| {
+        let mut body = physics::Body::spawn(&level, (center, y), cgmath::Rad(0.0), car.scale);
+        simulate(&mut body, STEPS, &car, &level, &common);
+        let start = body.transform.disp;
+        body.dynamo.linear_velocity.y = 10.0;
+        simulate(&mut body, 30, &car, &level, &common);
+        (start.z, body.transform.disp.y - start.y)
+    };
+    let (plain_z, plain_distance) = coast(48);
+    let (swamp_z, swamp_distance) = coast(176);
+    assert!(
+        plain_z < swamp_z,
+        "Soft ground holds at {}, harder at {}",
+        plain_z,
+        swamp_z
+    );
+    assert!(
+        swamp_distance < 0.9 * plain_distance,
+        "Swamp didn't slow down: {} vs {}",
+        swamp_distance,
+        plain_distance
+    );
+}
+
+fn run(
+    body: &mut physics::Body,
+    steps: usize,
+    control: &physics::Control,
+    car: &CarInfo,
+    level: &Level,
+    common: &Common,
+) {
+    for _ in 0..steps {
+        body.apply_control(control, 1.0, common);
+        body.step(
+            common.nature.time_delta0,
+            car,
+            level,
+            common,
+            control,
+            None,
+            None,
+        );
+    }
+}
+
+#[test]
+fn float_on_water() {
+    let common = load_common();
+    let mut car = make_car(0);
+    car.physics.k_archimedean = 2.0;
+    car.physics.k_water_traction = 1.0;
+    car.physics.water_speed_factor = 1.0;
+    let mut level = make_level(|_, _| GROUND / 4);
+    level.meta = vec![TerrainBits::new(8).write(0); level.height.len()];
+    // only the second half of the level is flooded
+    level.flood_map = vec![0, GROUND];
+    level.flood_section_power = 7;
+    let center = LEVEL_SIZE / 2;
+    let y = center + LEVEL_SIZE / 4;
+    let mut body = physics::Body::spawn(&level, (center, y), cgmath::Rad(0.0), car.scale);
+
+    simulate(&mut body, STEPS, &car, &level, &common);
+
+    let flood = physics::get_height(GROUND);
+    let z = body.transform.disp.z;
+    assert!(
+        z > flood - half_height(&car) && z < flood + half_height(&car),
+        "Center {} is not on the water surface {}",
+        z,
+        flood
+    );
+    assert!(speed(&body) < 0.5, "Still moving at {}", speed(&body));
+    let up = body.transform.rot * cgmath::Vector3::unit_z();
+    assert!(up.z > 0.99, "Tilted to {:?}", up);
+
+    let start = body.transform.disp;
+    let control = physics::Control {
+        motor: 1.0,
+        ..Default::default()
+    };
+    run(&mut body, 50, &control, &car, &level, &common);
+    let end = body.transform.disp;
+    assert!(
+        end.y > start.y + 5.0,
+        "Didn't swim: {:?} -> {:?}",
+        start,
+        end
+    );
+    assert!(
+        (end.z - start.z).abs() < half_height(&car),
+        "Sank or took off: {:?} -> {:?}",
+        start,
+        end
+    );
+}
+
+#[test]
+fn dig_and_emerge() {
+    let common = load_common();
+    let mut car = make_car(0);
+    car.physics.underground_speed_factor = 1.0;
+    // water would not let the mole in
+    let mut level = make_level(|_, _| GROUND);
+    level.meta = vec![1 << 3; level.height.len()];
+    let center = LEVEL_SIZE / 2;
+    let mut body = physics::Body::spawn(&level, (center, center), cgmath::Rad(0.0), car.scale);
+    simulate(&mut body, STEPS, &car, &level, &common);
+    let rest = body.transform.disp;
+
+    let control = physics::Control {
+        motor: 1.0,
+        mole: true,
+        ..Default::default()
+    };
+    run(&mut body, STEPS, &control, &car, &level, &common);
+    let dug = body.transform.disp;
+    assert!(body.underground);
+    let ground = physics::get_height(GROUND);
+    assert!(
+        dug.z < ground - half_height(&car),
+        "Center {} is not under the ground {}",
+        dug.z,
+        ground
+    );
+    assert!(dug.y > rest.y + 10.0, "Didn't dig forward: {:?}", dug);
+
+    run(
+        &mut body,
+        STEPS,
+        &physics::Control::default(),
+        &car,
+        &level,
+        &common,
+    );
+    assert!(!body.underground);
+    let end = body.transform.disp;
+    assert!(
+        (end.z - rest.z).abs() < 4.0,
+        "Didn't get back to the surface: {} instead of {}",
+        end.z,
+        rest.z
+    );
+}
+
+#[test]
+fn fly_and_land() {
+    let common = load_common();
+    let mut car = make_car(0);
+    car.stats.max_fly = 200;
+    let level = make_level(|_, _| GROUND);
+    let center = LEVEL_SIZE / 2;
+    let mut body = physics::Body::spawn(&level, (center, center), cgmath::Rad(0.0), car.scale);
+    simulate(&mut body, STEPS, &car, &level, &common);
+    let rest = body.transform.disp;
+
+    let mut control = physics::Control {
+        fly: true,
+        lift: 1.0,
+        ..Default::default()
+    };
+    run(&mut body, 50, &control, &car, &level, &common);
+    control.lift = 0.0;
+    run(&mut body, 50, &control, &car, &level, &common);
+    let altitude = body.flight.altitude.unwrap();
+    assert!(
+        altitude > rest.z + 50.0,
+        "Target altitude {} is too low",
+        altitude
+    );
+    let z = body.transform.disp.z;
+    assert!(
+        (z - altitude).abs() < 10.0,
+        "Hovering at {} instead of {}",
+        z,
+        altitude
+    );
+    let up = body.transform.rot * cgmath::Vector3::unit_z();
+    assert!(up.z > 0.9, "Tilted to {:?}", up);
+
+    // the time runs out, and the vehicle falls back
+    run(&mut body, STEPS, &control, &car, &level, &common);
+    assert!(body.flight.altitude.is_none());
+    let end = body.transform.disp;
+    assert!(
+        (end.z - rest.z).abs() < 4.0,
+        "Didn't land: {} instead of {}",
+        end.z,
+        rest.z
+    );
+    // can't take off again until turned off on the ground
+    assert!(body.flight.time >= car.stats.max_fly as f32);
+    run(
+        &mut body,
+        1,
+        &physics::Control::default(),
+        &car,
+        &level,
+        &common,
+    );
+    assert_eq!(body.flight.time, 0.0);
+}
+
+#[test]
+fn head_on_collision() {
+    let common = load_common();
+    let car = make_car(0);
+    let level = make_level(|_, _| GROUND);
+    let center = LEVEL_SIZE / 2;
+    let mut bodies = [
+        physics::Body::spawn(&level, (center, center - 60), cgmath::Rad(0.0), car.scale),
+        physics::Body::spawn(
+            &level,
+            (center, center + 60),
+            cgmath::Rad(std::f32::consts::PI),
+            car.scale,
+        ),
+    ];
+    let control = physics::Control {
+        motor: 1.0,
+        ..Default::default()
+    };
+
+    let mut hits = Vec::new();
+    let mut min_distance = LEVEL_SIZE as f32;
+    for _ in 0..STEPS {
+        for body in bodies.iter_mut() {
+            run(body, 1, &control, &car, &level, &common);
+        }
+        let [ref mut a, ref mut b] = bodies;
+        if let Some((hit_a, hit_b)) = physics::collision::collide(a, &car, b, &car, &common) {
+            a.apply_hit(&car, &hit_a);
+            b.apply_hit(&car, &hit_b);
+            hits.push((hit_a.side, hit_b.side));
+        }
+        let offset = b.transform.disp - a.transform.disp;
+        min_distance = min_distance.min(offset.y);
+    }
+
+    assert_eq!(hits.first(), Some(&(Side::Front, Side::Front)));
+    // the boxes are 80 units long
+    assert!(
+        min_distance > 70.0,
+        "Went through each other down to {}",
+        min_distance
+    );
+    let [ref a, ref b] = bodies;
+    assert!((a.transform.disp.x - b.transform.disp.x).abs() < 5.0);
+}
+
+#[test]
+fn ram_damage_and_debris() {
+    let common = load_common();
+    let mut car = make_car(0);
+    car.stats.max_armor = 10;
+    car.stats.max_damage = 50;
+    car.stats.shield_max = 4;
+    car.stats.shield_drop = 2;
+    car.stats.shield_regen = 1;
+    car.stats.drop_time = 5;
+    car.physics.ram_power[Side::Front as usize] = 9;
+    car.physics.defence[Side::Front as usize] = 4;
+    let piece = m3d::Debrie {
+        mesh: car.model.body.clone(),
+        shape: car.model.shape.clone(),
+    };
+    car.model.debris = vec![piece.clone(), piece];
+    let level = make_level(|_, _| GROUND);
+    let center = LEVEL_SIZE / 2;
+
+    // slightly overlapping, and driving into each other
+    let spawn = |y, angle, speed| {
+        let mut body = physics::Body::spawn(&level, (center, y), cgmath::Rad(angle), car.scale);
+        body.dynamo.linear_velocity.y = speed;
+        body
+    };
+    let a = spawn(center - 39, 0.0, 20.0);
+    let b = spawn(center + 39, std::f32::consts::PI, 20.0);
+    let (hit_a, hit_b) = physics::collision::collide(&a, &car, &b, &car, &common).unwrap();
+    assert!(hit_a.strong && hit_b.strong);
+    assert_eq!((hit_a.side, hit_b.side), (Side::Front, Side::Front));
+    let damage = Health::hit_damage(&hit_a, &car, &common);
+    assert_eq!(damage, car.stats.max_damage as f32);
+    // equal vehicles push each other equally
+    assert_eq!(hit_a.velocity, -hit_b.velocity);
+    // the rammer is pushed back less, and the target more
+    let plain = make_car(0);
+    let (plain_a, plain_b) = physics::collision::collide(&a, &plain, &b, &plain, &common).unwrap();
+    let (ram_a, ram_b) = physics::collision::collide(&a, &car, &b, &plain, &common).unwrap();
+    assert!(ram_a.velocity.magnitude() < plain_a.velocity.magnitude());
+    assert!(ram_b.velocity.magnitude() > plain_b.velocity.magnitude());
+    // the defence works against it
+    let mut armored = make_car(0);
+    armored.physics.defence[Side::Front as usize] = 9;
+    let (_, defended_b) = physics::collision::collide(&a, &car, &b, &armored, &common).unwrap();
+    assert!(defended_b.velocity.magnitude() < ram_b.velocity.magnitude());
+
+    let mut health = Health::new(&car);
+    health.take(damage, &car);
+    assert_eq!(health.shield, 2.0);
+    assert!(health.is_destroyed());
+
+    // a gentle touch doesn't hurt
+    let slow_b = spawn(center + 39, std::f32::consts::PI, 0.0);
+    let slow_a = spawn(center - 39, 0.0, 1.0);
+    let (touch, _) = physics::collision::collide(&slow_a, &car, &slow_b, &car, &common).unwrap();
+    assert!(!touch.strong);
+    assert_eq!(Health::hit_damage(&touch, &car, &common), 0.0);
+
+    // the shield comes back after the drop time
+    let mut health = Health::new(&car);
+    health.take(1.0, &car);
+    assert_eq!(health.shield, 3.0);
+    health.update(5.0, &car);
+    assert_eq!(health.shield, 3.0);
+    health.update(1.0, &car);
+    assert_eq!(health.shield, 4.0);
+
+    let infos = car.debris();
+    let mut pieces = a.explode(&car, &common);
+    assert_eq!(pieces.len(), infos.len());
+    for (body, info) in pieces.iter_mut().zip(&infos) {
+        assert!(body.dynamo.linear_velocity.z > 0.0);
+        simulate(body, STEPS, info, &level, &common);
+        let ground = physics::get_height(GROUND);
+        let bottom = body.transform.disp.z - half_height(info);
+        assert!(
+            (bottom - ground).abs() < 4.0,
+            "Piece landed at {} instead of {}",
+            bottom,
+            ground
+        );
+    }
+}
+
+#[test]
+fn speed_correction() {
+    let common = load_common();
+    assert_eq!(common.speed.standard_frame_rate, 14);
+    let frame_time = common::MAIN_LOOP_TIME;
+    let mut correction = physics::SpeedCorrection::new(frame_time, &common.speed);
+    let standard = frame_time * 14.0;
+    assert_eq!(correction.factor, standard);
+
+    // a hiccup is followed smoothly, and then recovered from
+    let slow = correction.update(0.01, &common.speed);
+    assert!(slow < standard && slow > 0.01 * 14.0, "Jumped to {}", slow);
+    for _ in 0..10 {
+        correction.update(frame_time, &common.speed);
+    }
+    assert!((correction.factor - standard).abs() < 1e-3);
+
+    let expected = standard * common.nature.time_delta0 * common.nature.num_calls_analysis as f32;
+    assert!((correction.time_delta(&common) - expected).abs() < 1e-5);
+}
+
+// Runs the recorded inputs of a single agent through the ticks of the game.
+fn drive(
+    ticks: &[replay::Tick],
+    max_quant: f32,
+    car: &CarInfo,
+    level: &Level,
+    common: &Common,
+) -> Vec<space::Transform> {
+    let mut correction = physics::SpeedCorrection::new(common::MAIN_LOOP_TIME, &common.speed);
+    let center = LEVEL_SIZE / 2;
+    let mut body = physics::Body::spawn(level, (center, center), cgmath::Rad(0.0), car.scale);
+    ticks
+        .iter()
+        .map(|tick| {
+            let frames = correction.update(common::MAIN_LOOP_TIME, &common.speed);
+            let time = physics::TickTime {
+                frames,
+                dt: correction.time_delta(common),
+                max_quant,
+            };
+            let focus_point = cgmath::Point3::from_vec(body.transform.disp);
+            body.tick(&time, car, level, common, &tick[0], &focus_point);
+            body.transform
+        })
+        .collect()
+}
+
+// Position and orientation of every 10th tick of the recorded drive.
+// Run with `VANGERS_BLESS=1` to update it after an intended change of the physics.
+const GOLDEN_TRAJECTORY: &str = "tests/data/trajectory.ron";
+
+#[test]
+fn replay_reproduces_trajectory() {
+    let common = load_common();
+    let car = make_car(0);
+    let level = make_level(|x, y| GROUND + ((x + y) / 16) as u8);
+
+    let ticks = (0..200)
+        .map(|i| {
+            vec![replay::Input {
+                control: physics::Control {
+                    motor: if i < 150 { 1.0 } else { 0.0 },
+                    rudder: ((i / 40) % 3) as f32 - 1.0,
+                    roll: if i % 60 < 5 { 1.0 } else { 0.0 },
+                    brake: i >= 180,
+                    turbo: i % 50 < 10,
+                    mole: false,
+                    fly: i >= 160 && i < 175,
+                    lift: 0.0,
+                },
+                jump: if i == 100 { Some(5.0) } else { None },
+                reset: i == 120,
+            }]
+        })
+        .collect::<Vec<_>>();
+    let header = replay::Header {
+        seed: 7,
+        level: String::new(),
+        car: "Box".to_string(),
+        other_count: 0,
+        spawn_at: SpawnAt::Player,
+        despawn_distance: None,
+        max_quant: 0.1,
+        shape_sampling: 0,
+        terrain_materials: None,
+        mods: Vec::new(),
+    };
+
+    let path = std::env::temp_dir().join(format!("vangers-physics-{}.replay", std::process::id()));
+    {
+        let mut recorder = replay::Recorder::new(&path, &header);
+        for tick in &ticks {
+            recorder.record(tick);
+        }
+    }
+    let loaded = replay::Replay::load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.header, header);
+    assert_eq!(loaded.ticks, ticks);
+
+    let actual = drive(
+        &loaded.ticks,
+        loaded.header.max_quant,
+        &car,
+        &level,
+        &common,
+    );
+    let moved = actual.last().unwrap().disp - actual[0].disp;
+    assert!(moved.magnitude() > 10.0, "Moved only by {:?}", moved);
+    let samples = actual
+        .iter()
+        .step_by(10)
+        .map(|t| {
+            [
+                t.disp.x, t.disp.y, t.disp.z, t.rot.s, t.rot.v.x, t.rot.v.y, t.rot.v.z,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    if std::env::var("VANGERS_BLESS").is_ok() {
+        let pretty = ron::ser::PrettyConfig::default();
+        let string = ron::ser::to_string_pretty(&samples, pretty).unwrap();
+        std::fs::write(GOLDEN_TRAJECTORY, string).unwrap();
+    }
+    let golden: Vec<[f32; 7]> =
+        ron::de::from_reader(File::open(GOLDEN_TRAJECTORY).unwrap()).unwrap();
+    assert_eq!(golden.len(), samples.len());
+    for (i, (a, b)) in samples.iter().zip(&golden).enumerate() {
+        let position = cgmath::vec3(a[0] - b[0], a[1] - b[1], a[2] - b[2]);
+        let rotation = cgmath::vec4(a[3] - b[3], a[4] - b[4], a[5] - b[5], a[6] - b[6]);
+        assert!(
+            position.magnitude() < 0.01 && rotation.magnitude() < 0.001,
+            "Diverged at tick {}: {:?} instead of {:?}",
+            i * 10,
+            a,
+            b
+        );
+    }
+}
+
+#[test]
+fn environment_episode() {
+    let common = Arc::new(load_common());
+    let level = Arc::new(make_level(|_, _| GROUND));
+    let new_env = || Environment::new(level.clone(), common.clone(), make_car(0), 0.1, 100);
+
+    let mut env = new_env();
+    let start = env.reset(7).unwrap();
+    assert_eq!(start.position, new_env().reset(7).unwrap().position);
+    assert_eq!(
+        start.terrain.len(),
+        environment::PATCH_SIZE * environment::PATCH_SIZE
+    );
+    let ground = physics::get_height(GROUND);
+    for &height in start.terrain.iter() {
+        assert_eq!(height, ground - start.position[2]);
+    }
+
+    let control = physics::Control {
+        motor: 1.0,
+        ..Default::default()
+    };
+    let mut total_reward = 0.0;
+    let mut last = None;
+    for tick in 0..100 {
+        let step = env.step(&control);
+        total_reward += step.reward;
+        assert_eq!(step.done, tick == 99, "Unexpected end at tick {}", tick);
+        last = Some(step);
+    }
+    let last = last.unwrap();
+    assert!(total_reward > 10.0, "Only drove {}", total_reward);
+    assert_eq!(last.observation.wheel_contacts, vec![true; 4]);
+}
+
+#[test]
+fn environment_spawn() {
+    let common = Arc::new(load_common());
+    // the water bed is flooded everywhere, except for the given spot
+    let flooded = |dry: Option<(i32, i32)>| {
+        let mut level = make_level(|x, y| {
+            if Some((x, y)) == dry {
+                GROUND * 2
+            } else {
+                GROUND / 4
+            }
+        });
+        level.meta = vec![TerrainBits::new(8).write(0); level.height.len()];
+        level.flood_map = vec![GROUND];
+        Arc::new(level)
+    };
+
+    let mut env = Environment::new(flooded(None), common.clone(), make_car(0), 0.1, 10);
+    assert!(env.reset(7).is_none());
+
+    // the only dry spot is found after the random attempts
+    let mut env = Environment::new(flooded(Some((3, 5))), common, make_car(0), 0.1, 10);
+    let start = env.reset(7).unwrap();
+    assert_eq!((start.position[0], start.position[1]), (3.0, 5.0));
+}
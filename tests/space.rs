@@ -0,0 +1,56 @@
+use vangers::space::SpatialGrid;
+
+fn items<T: Copy>(entries: Vec<&vangers::space::GridEntry<T>>) -> Vec<T> {
+    entries.into_iter().map(|entry| entry.item).collect()
+}
+
+#[test]
+fn radius_wraps_around() {
+    let mut grid = SpatialGrid::new(cgmath::vec2(1024.0, 512.0), 64.0);
+    grid.insert(0, cgmath::vec2(1020.0, 10.0), 5.0);
+    grid.insert(1, cgmath::vec2(500.0, 250.0), 5.0);
+    grid.insert(2, cgmath::vec2(8.0, 508.0), 5.0);
+
+    assert_eq!(
+        items(grid.query_radius(cgmath::vec2(2.0, 2.0), 10.0)),
+        vec![0, 2]
+    );
+    assert_eq!(
+        items(grid.query_radius(cgmath::vec2(490.0, 250.0), 6.0)),
+        vec![1]
+    );
+    assert_eq!(
+        grid.offset(cgmath::vec2(1020.0, 10.0), cgmath::vec2(8.0, 508.0)),
+        cgmath::vec2(12.0, -14.0)
+    );
+
+    grid.clear();
+    assert!(grid.query_radius(cgmath::vec2(2.0, 2.0), 10.0).is_empty());
+}
+
+#[test]
+fn aabb_includes_radius() {
+    let mut grid = SpatialGrid::new(cgmath::vec2(1024.0, 1024.0), 128.0);
+    grid.insert('a', cgmath::vec2(100.0, 100.0), 20.0);
+    grid.insert('b', cgmath::vec2(300.0, 100.0), 20.0);
+    grid.insert('c', cgmath::vec2(1010.0, 100.0), 20.0);
+
+    let found = grid.query_aabb(cgmath::vec2(0.0, 0.0), cgmath::vec2(290.0, 200.0));
+    assert_eq!(items(found), vec!['a', 'b', 'c']);
+    let found = grid.query_aabb(cgmath::vec2(130.0, 0.0), cgmath::vec2(270.0, 200.0));
+    assert!(found.is_empty());
+}
+
+#[test]
+fn large_entries_once() {
+    let mut grid = SpatialGrid::new(cgmath::vec2(256.0, 256.0), 16.0);
+    grid.insert(7, cgmath::vec2(128.0, 128.0), 1000.0);
+    assert_eq!(
+        items(grid.query_radius(cgmath::vec2(0.0, 0.0), 1.0)),
+        vec![7]
+    );
+    assert_eq!(
+        items(grid.query_aabb(cgmath::vec2(-500.0, -500.0), cgmath::vec2(500.0, 500.0))),
+        vec![7]
+    );
+}
@@ -0,0 +1,124 @@
+use vangers::config::vfs::FileSystem;
+
+use std::{
+    fs,
+    io::{ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+// A fresh directory per test, so that they can run in parallel.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vangers-vfs-{}-{}", std::process::id(), name));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_file(base: &Path, path: &str, content: &str) {
+    let full = base.join(path);
+    fs::create_dir_all(full.parent().unwrap()).unwrap();
+    fs::write(full, content).unwrap();
+}
+
+fn write_zip(path: &Path, files: &[(&str, &str)]) {
+    let mut zip = zip::ZipWriter::new(fs::File::create(path).unwrap());
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for &(name, content) in files {
+        if name.ends_with('/') {
+            zip.add_directory(name, options).unwrap();
+        } else {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+    }
+    zip.finish().unwrap();
+}
+
+fn read(vfs: &FileSystem, path: &str) -> String {
+    let mut string = String::new();
+    vfs.open(path)
+        .unwrap_or_else(|| panic!("Unable to open {}", path))
+        .read_to_string(&mut string)
+        .unwrap();
+    string
+}
+
+#[test]
+fn case_insensitive() {
+    let base = temp_dir("case");
+    write_file(&base, "Resource/M3D/Body.m3d", "body");
+
+    let vfs = FileSystem::new(Vec::new(), base.clone()).unwrap();
+    assert_eq!(read(&vfs, "resource\\m3d\\BODY.M3D"), "body");
+    assert!(vfs.exists("./RESOURCE//m3d/body.m3d"));
+    assert!(!vfs.exists("resource/m3d/wheel.m3d"));
+    assert_eq!(
+        vfs.resolve("resource/m3d/body.m3d").unwrap(),
+        base.join("Resource/M3D/Body.m3d")
+    );
+    assert_eq!(vfs.read_dir("RESOURCE/m3d"), vec!["Body.m3d".to_string()]);
+}
+
+#[test]
+fn mod_priority() {
+    let base = temp_dir("priority-base");
+    write_file(&base, "options.dat", "base");
+    write_file(&base, "data/a.txt", "base a");
+    let first = temp_dir("priority-first");
+    write_file(&first, "OPTIONS.DAT", "first");
+    write_file(&first, "Data/B.txt", "first b");
+    let second = temp_dir("priority-second");
+    write_file(&second, "options.dat", "second");
+    write_file(&second, "data/b.txt", "second b");
+    write_file(&second, "data/c.txt", "second c");
+
+    let vfs = FileSystem::new(vec![first, second], base).unwrap();
+    assert_eq!(read(&vfs, "options.dat"), "first");
+    assert_eq!(read(&vfs, "data/a.txt"), "base a");
+    assert_eq!(read(&vfs, "data/b.txt"), "first b");
+    assert_eq!(read(&vfs, "data/c.txt"), "second c");
+    assert_eq!(vfs.read_dir("data"), vec!["a.txt", "B.txt", "c.txt"]);
+}
+
+#[test]
+fn zip_roots() {
+    let base = temp_dir("zip");
+    write_file(&base, "data/a.txt", "base a");
+    write_file(&base, "data/level.ini", "base level");
+    let archive = base.join("Mod.ZIP");
+    write_zip(
+        &archive,
+        &[
+            ("Data/", ""),
+            ("Data/A.txt", "zip a"),
+            ("Data/Sub/Deep.txt", "zip deep"),
+            ("data\\Level.ini", "zip level"),
+        ],
+    );
+
+    let vfs = FileSystem::new(vec![archive], base.clone()).unwrap();
+    assert_eq!(read(&vfs, "DATA/a.TXT"), "zip a");
+    assert_eq!(read(&vfs, "data/sub/deep.txt"), "zip deep");
+    assert_eq!(read(&vfs, "data/level.ini"), "zip level");
+    assert_eq!(vfs.read_dir("data"), vec!["A.txt", "Level.ini", "Sub"]);
+    assert_eq!(vfs.read_dir("DATA/SUB"), vec!["Deep.txt"]);
+
+    // Files shadowed by an archive have no path on disk.
+    assert!(vfs.resolve("data/level.ini").is_err());
+    assert_eq!(
+        vfs.resolve("data/missing.ini").unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn bad_archive() {
+    let base = temp_dir("bad-zip");
+    let archive = base.join("broken.zip");
+    fs::write(&archive, "not a zip").unwrap();
+    assert!(FileSystem::new(vec![archive], base.clone()).is_err());
+    assert!(FileSystem::new(vec![base.join("missing.zip")], base).is_err());
+}